@@ -0,0 +1,64 @@
+use std::hint::black_box;
+
+use client::{
+    bootstrap::pool_schema::PoolUpdate,
+    graph::{DEFAULT_MIN_LOG_PROFIT, Graph},
+};
+use criterion::{Criterion, criterion_group, criterion_main};
+
+// the committed fixture, so numbers stay comparable between runs and machines
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/test_data");
+const MAX_DEPTH: usize = 4;
+
+// every pool gets the same price and depth, enough for the scan to price each cycle
+fn priced_graph() -> Graph {
+    let mut graph = Graph::build_graph(FIXTURE).unwrap();
+    let updates: Vec<(_, PoolUpdate)> = graph
+        .edges
+        .iter()
+        .map(|edge| {
+            (
+                edge.address,
+                PoolUpdate {
+                    new_liquidity: 1_000_000_000_000,
+                    new_sqrt_price: 1u128 << 64,
+                    new_current_tick_index: 0,
+                    slot: 0,
+                    fee_rate: None,
+                    tick_spacing: None,
+                },
+            )
+        })
+        .collect();
+    assert!(graph.update_edges(updates).is_empty());
+    graph
+}
+
+fn bench_build_graph(c: &mut Criterion) {
+    c.bench_function("build_graph", |b| {
+        b.iter(|| Graph::build_graph(black_box(FIXTURE)).unwrap())
+    });
+}
+
+fn bench_build_cycles(c: &mut Criterion) {
+    let mut graph = Graph::build_graph(FIXTURE).unwrap();
+    c.bench_function("build_cycles(4)", |b| {
+        b.iter(|| graph.build_cycles(black_box(MAX_DEPTH)).unwrap())
+    });
+}
+
+fn bench_find_arbitrage_cycles(c: &mut Criterion) {
+    let mut graph = priced_graph();
+    graph.build_cycles(MAX_DEPTH).unwrap();
+    c.bench_function("find_arbitrage_cycles", |b| {
+        b.iter(|| graph.find_arbitrage_cycles(DEFAULT_MIN_LOG_PROFIT))
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_build_graph,
+    bench_build_cycles,
+    bench_find_arbitrage_cycles
+);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+#![no_main]
+
+use client::{
+    config::Cluster,
+    decoders::{self, DexPrograms, TokenProgram},
+};
+use libfuzzer_sys::fuzz_target;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+
+// the first byte picks the owner so decode_account reaches every registered decoder, the rest
+// is the account data. every decoder must answer with Err on bad input, never panic
+fuzz_target!(|input: &[u8]| {
+    let Some((&selector, data)) = input.split_first() else {
+        return;
+    };
+    let mut owners = DexPrograms::for_cluster(Cluster::Mainnet).available();
+    owners.extend([
+        TokenProgram::Token.id(),
+        TokenProgram::Token2022.id(),
+        Pubkey::default(),
+    ]);
+    let account = Account {
+        lamports: 0,
+        data: data.to_vec(),
+        owner: owners[selector as usize % owners.len()],
+        executable: false,
+        rent_epoch: 0,
+    };
+
+    let _ = decoders::decode_account(&account);
+    let _ = decoders::decode_orca_static(&account);
+    let _ = decoders::decode_orca_tick_array(&account, selector as u16);
+    let _ = decoders::decode_raydium_static(&account);
+    let _ = decoders::decode_raydium_vaults(&account);
+    let _ = decoders::decode_raydium_trade_fee_rate(&account);
+    let _ = decoders::decode_raydium_cpmm_account(&account);
+    let _ = decoders::decode_token_account(&account);
+    let _ = decoders::decode_token_amount(&account);
+    let _ = decoders::decode_mint_decimals(&account);
+    let _ = decoders::decode_mint_transfer_fee_bps(&account);
+    let _ = decoders::decode_lookup_table(&account);
+});
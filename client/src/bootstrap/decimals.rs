@@ -0,0 +1,210 @@
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use anyhow::Result;
+use solana_sdk::pubkey::Pubkey;
+use tracing::{info, warn};
+
+use super::{
+    output::{read_stored_pools, write_stored_pools},
+    pool_schema::{PoolInfo, TokenInfo},
+};
+use crate::{config, decoders, refresh::AccountFetcher};
+
+// the files filled from the HTTP APIs, the onchain source already reads decimals from the mints
+const API_POOL_FILES: [&str; 3] = [
+    "orca_pools.json",
+    "raydium_pools.json",
+    "meteora_pools.json",
+];
+
+fn tokens(pool: &PoolInfo) -> impl Iterator<Item = &TokenInfo> {
+    pool.token_a.iter().chain(pool.token_b.iter())
+}
+
+// tokens without an address or with an unparseable one are left for build_graph to reject
+fn mints_missing_decimals(pools: &[PoolInfo]) -> HashSet<Pubkey> {
+    pools
+        .iter()
+        .flat_map(tokens)
+        .filter(|token| token.decimals.is_none())
+        .filter_map(|token| Pubkey::from_str(token.address.as_deref()?).ok())
+        .collect()
+}
+
+// returns how many tokens got their decimals filled in
+fn backfill_decimals(pools: &mut [PoolInfo], decimals: &HashMap<Pubkey, u8>) -> usize {
+    let mut filled = 0;
+    for pool in pools.iter_mut() {
+        for token in pool.token_a.iter_mut().chain(pool.token_b.iter_mut()) {
+            if token.decimals.is_some() {
+                continue;
+            }
+            let mint = token
+                .address
+                .as_deref()
+                .and_then(|address| Pubkey::from_str(address).ok());
+            if let Some(&mint_decimals) = mint.and_then(|mint| decimals.get(&mint)) {
+                token.decimals = Some(mint_decimals);
+                filled += 1;
+            }
+        }
+    }
+    filled
+}
+
+// a failed chunk is skipped, its tokens keep no decimals and their pools are dropped later
+async fn fetch_mint_decimals(
+    client: &dyn AccountFetcher,
+    mints: Vec<Pubkey>,
+    chunk_size: usize,
+) -> HashMap<Pubkey, u8> {
+    let mut decimals = HashMap::new();
+
+    for range in config::account_chunks(mints.len(), chunk_size) {
+        let chunk = &mints[range];
+        match client.fetch_accounts(chunk).await {
+            Ok((accounts, _)) => {
+                decimals.extend(chunk.iter().zip(accounts).filter_map(|(mint, account)| {
+                    Some((*mint, decoders::decode_mint_decimals(&account?).ok()?))
+                }))
+            }
+            Err(e) => warn!("Skipping a chunk of mints: {:?}", e),
+        }
+    }
+
+    decimals
+}
+
+// the APIs sometimes list a token without its decimals, which makes insert_node reject every
+// pool trading it. those mints are read once across all files and the files rewritten in place
+pub async fn backfill_pool_files(
+    data_folder_path: &str,
+    client: &dyn AccountFetcher,
+    chunk_size: usize,
+) -> Result<()> {
+    let mut files = Vec::new();
+    for file in API_POOL_FILES {
+        let path = format!("{}/{}", data_folder_path, file);
+        let stored = read_stored_pools(&path).await?;
+        files.push((path, stored.all_pools));
+    }
+
+    let mints: HashSet<Pubkey> = files
+        .iter()
+        .flat_map(|(_, pools)| mints_missing_decimals(pools))
+        .collect();
+    if mints.is_empty() {
+        return Ok(());
+    }
+    let missing = mints.len();
+    let decimals = fetch_mint_decimals(client, mints.into_iter().collect(), chunk_size).await;
+    info!("Read decimals for {} of {} mints", decimals.len(), missing);
+
+    for (path, mut pools) in files {
+        if backfill_decimals(&mut pools, &decimals) > 0 {
+            write_stored_pools(&path, pools, None, false).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::account::Account;
+
+    use super::*;
+    use crate::{
+        bootstrap::pool_schema::{DexType, PoolType},
+        decoders::{TokenProgram, test_account},
+        refresh::CannedAccounts,
+    };
+
+    // an SPL Mint, mint_authority 0..36, supply 36..44, then the decimals byte
+    fn mint_account(decimals: u8) -> Account {
+        let mut data = vec![0u8; 82];
+        data[44] = decimals;
+        test_account(TokenProgram::Token.id(), data)
+    }
+
+    fn pool(address: &str, mint_a: Pubkey, mint_b: Pubkey, decimals_b: Option<u8>) -> PoolInfo {
+        let token = |mint: Pubkey, decimals| TokenInfo {
+            address: Some(mint.to_string()),
+            decimals,
+            name: None,
+            symbol: None,
+        };
+        PoolInfo {
+            address: Some(address.to_string()),
+            fee_rate: Some(400),
+            pool_type: Some(PoolType::Concentrated),
+            dex: Some(DexType::Orca),
+            tick_spacing: Some(4),
+            token_a: Some(token(mint_a, Some(9))),
+            token_b: Some(token(mint_b, decimals_b)),
+            token_vault_a: Some("vault_a".to_string()),
+            token_vault_b: Some("vault_b".to_string()),
+            config: Some("config".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backfill_reads_decimals_from_mint_accounts() {
+        let dir = std::env::temp_dir().join(format!("decimals-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_str().unwrap();
+        let (wsol, missing, unknown) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let path = format!("{}/orca_pools.json", dir);
+        write_stored_pools(
+            &path,
+            vec![
+                pool("pool_1", wsol, missing, None),
+                pool("pool_2", wsol, unknown, None),
+                pool("pool_3", wsol, missing, Some(6)),
+            ],
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        let client = CannedAccounts {
+            accounts: HashMap::from([(missing, mint_account(5)), (wsol, mint_account(9))]),
+            ..CannedAccounts::default()
+        };
+
+        backfill_pool_files(dir, &client, 1).await.unwrap();
+
+        let decimals: Vec<_> = read_stored_pools(&path)
+            .await
+            .unwrap()
+            .all_pools
+            .iter()
+            .map(|pool| pool.token_b.as_ref().unwrap().decimals)
+            .collect();
+        // the unknown mint has no account and stays missing, set decimals are never overwritten
+        assert_eq!(decimals, vec![Some(5), None, Some(6)]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_skips_accounts_that_are_not_mints() {
+        let (mint, short) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut short_account = mint_account(5);
+        short_account.data.truncate(44);
+        let client = CannedAccounts {
+            accounts: HashMap::from([(mint, mint_account(7)), (short, short_account)]),
+            ..CannedAccounts::default()
+        };
+
+        let decimals =
+            fetch_mint_decimals(&client, vec![mint, short, Pubkey::new_unique()], 2).await;
+
+        assert_eq!(decimals, HashMap::from([(mint, 7)]));
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashSet;
+
+use clap::ValueEnum;
+
+// where setup reads the pool lists from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PoolSource {
+    // the DEX HTTP APIs, sorted by volume
+    #[default]
+    Api,
+    // get_program_accounts against the DEX programs, Orca and Raydium only
+    Onchain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchLimits {
+    pub max_pages: usize,
+    pub page_size: usize,
+}
+
+// per dex, since each API pages differently
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootstrapLimits {
+    pub orca: FetchLimits,
+    pub raydium: FetchLimits,
+    pub meteora: FetchLimits,
+    pub source: PoolSource,
+    // mint addresses, a pool is only written if it trades two of them. None keeps every pool
+    pub token_allow_list: Option<HashSet<String>>,
+}
+
+impl BootstrapLimits {
+    pub fn all(limits: FetchLimits) -> Self {
+        Self {
+            orca: limits,
+            raydium: limits,
+            meteora: limits,
+            source: PoolSource::default(),
+            token_allow_list: None,
+        }
+    }
+}
+
+impl Default for BootstrapLimits {
+    fn default() -> Self {
+        Self {
+            orca: FetchLimits {
+                max_pages: 10,
+                page_size: 50,
+            },
+            raydium: FetchLimits {
+                max_pages: 5,
+                page_size: 100,
+            },
+            meteora: FetchLimits {
+                max_pages: 10,
+                page_size: 100,
+            },
+            source: PoolSource::default(),
+            token_allow_list: None,
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use super::pool_schema::{PoolInfo, TokenInfo};
+
+fn token_address(token: &Option<TokenInfo>) -> Option<&str> {
+    token.as_ref()?.address.as_deref()
+}
+
+// index pairs (i < j) of pools trading the same two tokens, whichever dex lists them
+pub fn matching_pairs(pools: &[PoolInfo]) -> Vec<(usize, usize)> {
+    let mut buckets: HashMap<(&str, &str), Vec<usize>> = HashMap::new();
+
+    for (index, pool) in pools.iter().enumerate() {
+        let (Some(token_a), Some(token_b)) =
+            (token_address(&pool.token_a), token_address(&pool.token_b))
+        else {
+            continue;
+        };
+
+        let key = if token_a <= token_b {
+            (token_a, token_b)
+        } else {
+            (token_b, token_a)
+        };
+        buckets.entry(key).or_default().push(index);
+    }
+
+    let mut pairs: Vec<(usize, usize)> = buckets
+        .values()
+        .flat_map(|indexes| {
+            indexes.iter().enumerate().flat_map(|(position, &first)| {
+                indexes[position + 1..]
+                    .iter()
+                    .map(move |&second| (first, second))
+            })
+        })
+        .collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootstrap::pool_schema::DexType;
+
+    fn pool(dex: DexType, token_a: &str, token_b: &str) -> PoolInfo {
+        let token = |address: &str| TokenInfo {
+            address: Some(address.to_string()),
+            decimals: Some(6),
+            name: None,
+            symbol: None,
+        };
+        PoolInfo {
+            address: None,
+            fee_rate: None,
+            pool_type: None,
+            dex: Some(dex),
+            tick_spacing: None,
+            token_a: Some(token(token_a)),
+            token_b: Some(token(token_b)),
+            token_vault_a: None,
+            token_vault_b: None,
+            config: None,
+        }
+    }
+
+    #[test]
+    fn test_matches_pairs_across_dexes_in_either_order() {
+        let pools = vec![
+            pool(DexType::Orca, "SOL", "USDC"),
+            pool(DexType::Raydium, "SOL", "BONK"),
+            pool(DexType::Raydium, "USDC", "SOL"),
+            pool(DexType::Meteora, "SOL", "USDC"),
+        ];
+
+        assert_eq!(matching_pairs(&pools), vec![(0, 2), (0, 3), (2, 3)]);
+    }
+
+    #[test]
+    fn test_skips_pools_without_tokens() {
+        let mut incomplete = pool(DexType::Orca, "SOL", "USDC");
+        incomplete.token_b = None;
+        let pools = vec![incomplete, pool(DexType::Raydium, "SOL", "USDC")];
+
+        assert!(matching_pairs(&pools).is_empty());
+    }
+}
@@ -1,15 +1,16 @@
 use std::collections::HashSet;
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use reqwest::Url;
 use serde::Deserialize;
 use serde_json::Deserializer;
-use tokio::{
-    fs::File,
-    io::{AsyncWriteExt, BufWriter},
-};
 
-use crate::bootstrap::pool_schema::TokenInfo;
+use super::{
+    limits::FetchLimits,
+    output::write_stored_pools,
+    pool_schema::{DexType, PoolInfo, PoolType, TokenInfo},
+    retry::{RetryPolicy, get_with_retry},
+};
 
 #[derive(Deserialize)]
 struct MeteoraPool {
@@ -20,9 +21,20 @@ struct MeteoraPool {
     token_b_vault: Option<String>,
     token_a_symbol: Option<String>,
     token_b_symbol: Option<String>,
-    pool_type: Option<String>,
-    base_fee: Option<u32>,
-    dynamic_fee: Option<u32>,
+    token_a_decimals: Option<u8>,
+    token_b_decimals: Option<u8>,
+    config: Option<String>,
+    // both fees are reported in percent
+    base_fee: Option<f64>,
+    dynamic_fee: Option<f64>,
+}
+
+impl MeteoraPool {
+    // the pool charges base_fee + dynamic_fee, converted to hundredths of a basis point
+    fn effective_fee_rate(&self) -> Option<u32> {
+        let percent = self.base_fee? + self.dynamic_fee.unwrap_or(0.0);
+        Some((percent * 10_000.0).round() as u32)
+    }
 }
 
 #[derive(Deserialize)]
@@ -32,20 +44,165 @@ struct MeteoraPoolsResponse {
     data: Vec<MeteoraPool>,
 }
 
-pub async fn fetch_pools(data_folder_path: &str, is_test: bool) -> Result<HashSet<TokenInfo>> {
-    let file = File::create(format!("{}/orca_pools.json", data_folder_path))
-        .await
-        .context("Failed to create Orca pools output file")?;
-    let mut writer = BufWriter::new(file);
-    writer
-        .write_all(b"{\"all_pools\":[")
-        .await
-        .context("Failed to write JSON header")?;
-
-    let mut first_item = true;
+const METEORA_POOLS_URL: &str = "https://dammv2-api.meteora.ag/pools";
+
+pub async fn fetch_pools(
+    data_folder_path: &str,
+    limits: &FetchLimits,
+    retry: &RetryPolicy,
+    token_allow_list: Option<&HashSet<String>>,
+    merge: bool,
+) -> Result<HashSet<TokenInfo>> {
+    let base_url = Url::parse(METEORA_POOLS_URL).context("Invalid Meteora API URL")?;
+    let (all_pools, tokens) = collect_pools(&base_url, limits, retry).await?;
+
+    write_stored_pools(
+        &format!("{}/meteora_pools.json", data_folder_path),
+        all_pools,
+        token_allow_list,
+        merge,
+    )
+    .await?;
+
+    Ok(tokens)
+}
+
+async fn collect_pools(
+    base_url: &Url,
+    limits: &FetchLimits,
+    retry: &RetryPolicy,
+) -> Result<(Vec<PoolInfo>, HashSet<TokenInfo>)> {
+    let mut all_pools = Vec::new();
     let client = reqwest::Client::new();
-    let mut url = Url::parse("https://dammv2-api.meteora.ag/pools?order=desc&limit=100")
-        .context("Invalid Orca API URL")?;
+    let mut tokens = HashSet::new();
+
+    for page in 1..=limits.max_pages {
+        let mut url = base_url.clone();
+        url.query_pairs_mut()
+            .append_pair("order", "desc")
+            .append_pair("limit", &limits.page_size.to_string())
+            .append_pair("page", &page.to_string());
+
+        let text = get_with_retry(&client, &url, retry)
+            .await
+            .context("Failed to fetch Meteora pools page")?;
+
+        let mut deserializer = Deserializer::from_str(&text);
+        let deserialized_response: MeteoraPoolsResponse =
+            serde_path_to_error::deserialize(&mut deserializer)
+                .context("Failed to deserialize Meteora response")?;
+
+        if deserialized_response.status != 200 {
+            bail!(
+                "Meteora API returned status {}",
+                deserialized_response.status
+            );
+        }
+
+        for pool in &deserialized_response.data {
+            let token_a = TokenInfo {
+                address: pool.token_a_mint.clone(),
+                decimals: pool.token_a_decimals,
+                name: None,
+                symbol: pool.token_a_symbol.clone(),
+            };
+            let token_b = TokenInfo {
+                address: pool.token_b_mint.clone(),
+                decimals: pool.token_b_decimals,
+                name: None,
+                symbol: pool.token_b_symbol.clone(),
+            };
+            tokens.insert(token_a.clone());
+            tokens.insert(token_b.clone());
+
+            let generic_pool = PoolInfo {
+                address: pool.pool_address.clone(),
+                fee_rate: pool.effective_fee_rate(),
+                pool_type: Some(PoolType::Concentrated),
+                dex: Some(DexType::Meteora),
+                tick_spacing: None, // DAMM v2 prices continuously, there are no ticks
+                token_a: Some(token_a),
+                token_b: Some(token_b),
+                token_vault_a: pool.token_a_vault.clone(),
+                token_vault_b: pool.token_b_vault.clone(),
+                config: pool.config.clone(),
+            };
+
+            if generic_pool.check().is_ok() {
+                all_pools.push(generic_pool);
+            }
+        }
+
+        if page >= deserialized_response.pages as usize {
+            break;
+        }
+    }
+
+    Ok((all_pools, tokens))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::bootstrap::mock_server::mock_server;
+
+    fn page_body(pool_address: &str, pages: u32) -> String {
+        format!(
+            r#"{{"status":200,"pages":{},"data":[{{
+                "pool_address":"{}",
+                "token_a_mint":"So11111111111111111111111111111111111111112",
+                "token_b_mint":"EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                "token_a_vault":"vault_a","token_b_vault":"vault_b",
+                "token_a_symbol":"SOL","token_b_symbol":"USDC",
+                "token_a_decimals":9,"token_b_decimals":6,
+                "config":"config","base_fee":0.25,"dynamic_fee":0.0
+            }}]}}"#,
+            pages, pool_address
+        )
+    }
+
+    #[tokio::test]
+    async fn test_max_pages_stops_after_one_page() {
+        let (url, requests) = mock_server(vec![
+            (200, page_body("pool_1", 3)),
+            (200, page_body("pool_2", 3)),
+        ])
+        .await;
+        let limits = FetchLimits {
+            max_pages: 1,
+            page_size: 1,
+        };
+
+        let (pools, tokens) = collect_pools(&url, &limits, &RetryPolicy::default())
+            .await
+            .unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert_eq!(pools.len(), 1);
+        assert_eq!(pools[0].address.as_deref(), Some("pool_1"));
+        assert_eq!(pools[0].fee_rate, Some(2500));
+        assert_eq!(tokens.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stops_at_last_reported_page() {
+        let (url, requests) = mock_server(vec![
+            (200, page_body("pool_1", 2)),
+            (200, page_body("pool_2", 2)),
+        ])
+        .await;
+        let limits = FetchLimits {
+            max_pages: 10,
+            page_size: 1,
+        };
+
+        let (pools, _) = collect_pools(&url, &limits, &RetryPolicy::default())
+            .await
+            .unwrap();
 
-    Ok(HashSet::new())
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(pools.len(), 2);
+    }
 }
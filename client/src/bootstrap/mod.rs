@@ -1,34 +1,159 @@
-use anyhow::Result;
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use solana_client::nonblocking::rpc_client::RpcClient;
 use tokio::fs::create_dir_all;
 
+use crate::{
+    config::{Cluster, Config},
+    decoders::DexPrograms,
+};
+
+pub mod decimals;
+pub mod limits;
+pub mod matching_pairs;
 pub mod meteora;
+#[cfg(test)]
+mod mock_server;
+pub mod onchain;
 pub mod orca;
+mod output;
 pub mod pool_schema;
 pub mod raydium;
+pub mod retry;
 
-pub async fn update_all(data_folder_path: &str, is_test: bool) -> Result<()> {
+// rpc_client is shared by every fetcher that reads accounts, None builds one from config.rpc_url
+pub async fn update_all(
+    config: &Config,
+    rpc_client: Option<Arc<RpcClient>>,
+    data_folder_path: &str,
+    limits: &limits::BootstrapLimits,
+    retry: &retry::RetryPolicy,
+    merge: bool,
+) -> Result<()> {
     create_dir_all(data_folder_path).await?;
+    let rpc_client = rpc_client.unwrap_or_else(|| Arc::new(RpcClient::new(config.rpc_url.clone())));
 
     // let orca_bootstrap_task = tokio::spawn(async { orca::fetch_pools(data_folter_path, is_test).await.unwrap() });
     // let raydium_bootstrap_task = tokio::spawn(async { raydium::fetch_pools(data_folter_path, is_test).await.unwrap() });
 
-    let (_, _) = tokio::try_join!(
-        orca::fetch_pools(data_folder_path, is_test),
-        raydium::fetch_pools(data_folder_path, is_test),
+    if limits.source == limits::PoolSource::Onchain {
+        let programs = DexPrograms::for_cluster(config.cluster);
+        return onchain::fetch_pools(
+            data_folder_path,
+            rpc_client,
+            &programs,
+            limits,
+            config.account_chunk_size,
+            merge,
+        )
+        .await;
+    }
+    // the DEX APIs only index mainnet pools
+    if config.cluster != Cluster::Mainnet {
+        bail!(
+            "No pool API for {:?}, run setup with --source onchain",
+            config.cluster
+        );
+    }
+
+    let token_allow_list = limits.token_allow_list.as_ref();
+    let (_, _, _) = tokio::try_join!(
+        orca::fetch_pools(
+            data_folder_path,
+            &limits.orca,
+            retry,
+            token_allow_list,
+            merge,
+        ),
+        raydium::fetch_pools(
+            data_folder_path,
+            Arc::clone(&rpc_client),
+            &limits.raydium,
+            retry,
+            token_allow_list,
+            merge,
+        ),
+        meteora::fetch_pools(
+            data_folder_path,
+            &limits.meteora,
+            retry,
+            token_allow_list,
+            merge,
+        ),
     )?;
+    decimals::backfill_pool_files(
+        data_folder_path,
+        rpc_client.as_ref(),
+        config.account_chunk_size,
+    )
+    .await?;
 
-    // orca_tokens.extend(raydium_tokens);
-    // let all_tokens = orca_tokens;
+    Ok(())
+}
 
-    // let file = File::create("./cached-blockchain-data/tokens.json").await?;
-    // let mut writer = BufWriter::new(file);
-    // writer.write_all(b"{\"all_tokens\":").await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootstrap::{
+        limits::{BootstrapLimits, FetchLimits, PoolSource},
+        pool_schema::StoredPools,
+        retry::RetryPolicy,
+    };
 
-    // let all_tokens_json = serde_json::to_string(&all_tokens)?;
-    // writer.write_all(all_tokens_json.as_bytes()).await?;
+    #[tokio::test]
+    async fn test_update_all_uses_the_given_rpc_client() {
+        let data_folder = std::env::temp_dir().join(format!("update-all-{}", std::process::id()));
+        let data_folder = data_folder.to_str().unwrap();
+        // nothing listens here, so only the mock can answer
+        let config = Config {
+            rpc_url: "http://127.0.0.1:1".to_string(),
+            ..Config::default()
+        };
+        let limits = BootstrapLimits {
+            source: PoolSource::Onchain,
+            ..BootstrapLimits::all(FetchLimits {
+                max_pages: 1,
+                page_size: 10,
+            })
+        };
+        let retry = RetryPolicy::default();
 
-    // writer.write_all(b"}").await?;
-    // writer.flush().await?;
+        let mock = Arc::new(RpcClient::new_mock("succeeds".to_string()));
+        update_all(&config, Some(mock), data_folder, &limits, &retry, false)
+            .await
+            .unwrap();
 
-    Ok(())
+        // the mock's program account isn't a pool, so it is skipped
+        for file in ["orca_pools.json", "raydium_pools.json"] {
+            let raw = std::fs::read_to_string(format!("{}/{}", data_folder, file)).unwrap();
+            let stored: StoredPools = serde_json::from_str(&raw).unwrap();
+            assert!(stored.all_pools.is_empty());
+        }
+        assert!(
+            update_all(&config, None, data_folder, &limits, &retry, false)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_devnet_setup_needs_the_onchain_source() {
+        let data_folder =
+            std::env::temp_dir().join(format!("update-devnet-{}", std::process::id()));
+        let config = Config::for_cluster(Cluster::Devnet);
+        let mock = Arc::new(RpcClient::new_mock("succeeds".to_string()));
+
+        let result = update_all(
+            &config,
+            Some(mock),
+            data_folder.to_str().unwrap(),
+            &BootstrapLimits::default(),
+            &RetryPolicy::default(),
+            false,
+        )
+        .await;
+
+        assert!(result.unwrap_err().to_string().contains("--source onchain"));
+    }
 }
@@ -0,0 +1,254 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result, bail};
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use tracing::{info, warn};
+
+use super::{
+    limits::{BootstrapLimits, FetchLimits},
+    output::write_stored_pools,
+    pool_schema::{DexType, PoolInfo, PoolType, TokenInfo},
+};
+use crate::decoders::{self, DecodeError, DexPrograms, PoolStatic};
+
+// each program also owns configs, tick arrays and positions, the exact size keeps those out
+const WHIRLPOOL_LEN: u64 = 653;
+const RAYDIUM_POOL_STATE_LEN: u64 = 1544;
+
+type StaticDecoderFn = fn(&Account) -> Result<PoolStatic, DecodeError>;
+
+fn program_filters(data_size: u64, discriminator: [u8; 8]) -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::DataSize(data_size),
+        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(0, &discriminator)),
+    ]
+}
+
+// Meteora isn't covered, its pools still come from the HTTP API
+pub async fn fetch_pools(
+    data_folder_path: &str,
+    client: Arc<RpcClient>,
+    programs: &DexPrograms,
+    limits: &BootstrapLimits,
+    chunk_size: usize,
+    merge: bool,
+) -> Result<()> {
+    let orca_pools =
+        fetch_dex_pools(&client, programs, DexType::Orca, &limits.orca, chunk_size).await?;
+    write_stored_pools(
+        &format!("{}/orca_pools.json", data_folder_path),
+        orca_pools,
+        limits.token_allow_list.as_ref(),
+        merge,
+    )
+    .await?;
+
+    let raydium_pools = fetch_dex_pools(
+        &client,
+        programs,
+        DexType::Raydium,
+        &limits.raydium,
+        chunk_size,
+    )
+    .await?;
+    write_stored_pools(
+        &format!("{}/raydium_pools.json", data_folder_path),
+        raydium_pools,
+        limits.token_allow_list.as_ref(),
+        merge,
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn fetch_dex_pools(
+    client: &RpcClient,
+    programs: &DexPrograms,
+    dex: DexType,
+    limits: &FetchLimits,
+    chunk_size: usize,
+) -> Result<Vec<PoolInfo>> {
+    let (program, data_size, discriminator, decode): (
+        Option<Pubkey>,
+        u64,
+        [u8; 8],
+        StaticDecoderFn,
+    ) = match dex {
+        DexType::Orca => (
+            programs.orca,
+            WHIRLPOOL_LEN,
+            decoders::WHIRLPOOL_DISCRIMINATOR,
+            decoders::decode_orca_static,
+        ),
+        DexType::Raydium => (
+            programs.raydium,
+            RAYDIUM_POOL_STATE_LEN,
+            decoders::POOL_STATE_DISCRIMINATOR,
+            decoders::decode_raydium_static,
+        ),
+        _ => bail!("No on-chain pool layout for {:?}", dex),
+    };
+    // a DEX missing from the cluster just contributes no pools
+    let Some(program) = program else {
+        info!("{:?} isn't deployed on this cluster, skipping it", dex);
+        return Ok(Vec::new());
+    };
+
+    let config = RpcProgramAccountsConfig {
+        filters: Some(program_filters(data_size, discriminator)),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let accounts = client
+        .get_program_accounts_with_config(&program, config)
+        .await
+        .with_context(|| format!("Failed to fetch {:?} pool accounts", dex))?;
+
+    // the chain has no volume ordering like the APIs, so the cap keeps an arbitrary subset
+    let max_pools = limits.max_pages * limits.page_size;
+    let mut skipped = 0;
+    let mut pools: Vec<(Pubkey, PoolStatic)> = Vec::new();
+    for (address, account) in &accounts {
+        if pools.len() >= max_pools {
+            break;
+        }
+        match decode(account) {
+            Ok(pool) => pools.push((*address, pool)),
+            Err(_) => skipped += 1,
+        }
+    }
+    if skipped > 0 {
+        warn!("Skipped {} undecodable {:?} pool accounts", skipped, dex);
+    }
+
+    // mints carry the decimals, Raydium AmmConfigs carry the fee
+    let mints: HashSet<Pubkey> = pools
+        .iter()
+        .flat_map(|(_, pool)| [pool.token_mint_a, pool.token_mint_b])
+        .collect();
+    let decimals: HashMap<Pubkey, u8> =
+        fetch_accounts(client, mints.into_iter().collect(), chunk_size)
+            .await?
+            .into_iter()
+            .filter_map(|(mint, account)| {
+                Some((mint, decoders::decode_mint_decimals(&account).ok()?))
+            })
+            .collect();
+
+    let fee_configs: HashSet<Pubkey> = pools
+        .iter()
+        .filter(|(_, pool)| pool.fee_rate.is_none())
+        .map(|(_, pool)| pool.config)
+        .collect();
+    let fee_rates: HashMap<Pubkey, u32> =
+        fetch_accounts(client, fee_configs.into_iter().collect(), chunk_size)
+            .await?
+            .into_iter()
+            .filter_map(|(config, account)| {
+                Some((
+                    config,
+                    decoders::decode_raydium_trade_fee_rate(&account).ok()?,
+                ))
+            })
+            .collect();
+
+    let token = |mint: Pubkey| TokenInfo {
+        address: Some(mint.to_string()),
+        decimals: decimals.get(&mint).copied(),
+        name: None,
+        symbol: None,
+    };
+    let all_pools: Vec<PoolInfo> = pools
+        .into_iter()
+        .map(|(address, pool)| PoolInfo {
+            address: Some(address.to_string()),
+            fee_rate: pool
+                .fee_rate
+                .or_else(|| fee_rates.get(&pool.config).copied()),
+            pool_type: Some(PoolType::Concentrated),
+            dex: Some(dex),
+            tick_spacing: Some(pool.tick_spacing as u64),
+            token_a: Some(token(pool.token_mint_a)),
+            token_b: Some(token(pool.token_mint_b)),
+            token_vault_a: Some(pool.token_vault_a.to_string()),
+            token_vault_b: Some(pool.token_vault_b.to_string()),
+            config: Some(pool.config.to_string()),
+        })
+        .filter(|pool| pool.check().is_ok())
+        .collect();
+
+    info!(
+        "Read {} {:?} pools from {} program accounts",
+        all_pools.len(),
+        dex,
+        accounts.len()
+    );
+    Ok(all_pools)
+}
+
+async fn fetch_accounts(
+    client: &RpcClient,
+    addresses: Vec<Pubkey>,
+    chunk_size: usize,
+) -> Result<HashMap<Pubkey, Account>> {
+    let mut accounts = HashMap::new();
+
+    for chunk in addresses.chunks(chunk_size) {
+        let fetched = client
+            .get_multiple_accounts(chunk)
+            .await
+            .context("Failed to fetch accounts")?;
+        accounts.extend(
+            chunk
+                .iter()
+                .zip(fetched)
+                .filter_map(|(address, account)| Some((*address, account?))),
+        );
+    }
+
+    Ok(accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_program_filters() {
+        let filters = program_filters(WHIRLPOOL_LEN, decoders::WHIRLPOOL_DISCRIMINATOR);
+
+        assert_eq!(
+            filters,
+            vec![
+                RpcFilterType::DataSize(653),
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    0,
+                    &decoders::WHIRLPOOL_DISCRIMINATOR
+                )),
+            ]
+        );
+        let RpcFilterType::Memcmp(memcmp) = &filters[1] else {
+            panic!("expected a memcmp filter");
+        };
+        let mut data = decoders::WHIRLPOOL_DISCRIMINATOR.to_vec();
+        data.resize(653, 0);
+        assert!(memcmp.bytes_match(&data));
+
+        // a Raydium pool state must not pass the Orca filter
+        data[0..8].copy_from_slice(&decoders::POOL_STATE_DISCRIMINATOR);
+        assert!(!memcmp.bytes_match(&data));
+    }
+}
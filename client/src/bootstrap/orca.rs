@@ -4,12 +4,14 @@ use anyhow::{Context, Result};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use serde_json::Deserializer;
-use tokio::{
-    fs::File,
-    io::{AsyncWriteExt, BufWriter},
-};
+use tracing::warn;
 
-use super::pool_schema::{DexType, PoolInfo, PoolType, TokenInfo};
+use super::{
+    limits::FetchLimits,
+    output::write_stored_pools,
+    pool_schema::{DexType, PoolInfo, PoolType, TokenInfo},
+    retry::{RetryPolicy, get_with_retry},
+};
 #[derive(Debug, Serialize, Deserialize)]
 struct OrcaPool {
     address: Option<String>,
@@ -48,40 +50,49 @@ struct Cursor {
     _previous: Option<String>,
 }
 
-pub async fn fetch_pools(data_folder_path: &str, is_test: bool) -> Result<HashSet<TokenInfo>> {
-    let file = File::create(format!("{}/orca_pools.json", data_folder_path))
-        .await
-        .context("Failed to create Orca pools output file")?;
-    let mut writer = BufWriter::new(file);
-    writer
-        .write_all(b"{\"all_pools\":[")
-        .await
-        .context("Failed to write JSON header")?;
-
-    let mut first_item = true;
-    let client = reqwest::Client::new();
-    let mut url =
-        Url::parse("https://api.orca.so/v2/solana/pools?sortBy=volume24h&sortDirection=desc")
-            .context("Invalid Orca API URL")?;
-    let mut tokens = HashSet::new();
+const ORCA_POOLS_URL: &str = "https://api.orca.so/v2/solana/pools";
+
+pub async fn fetch_pools(
+    data_folder_path: &str,
+    limits: &FetchLimits,
+    retry: &RetryPolicy,
+    token_allow_list: Option<&HashSet<String>>,
+    merge: bool,
+) -> Result<HashSet<TokenInfo>> {
+    let base_url = Url::parse(ORCA_POOLS_URL).context("Invalid Orca API URL")?;
+    let (all_pools, tokens) = collect_pools(&base_url, limits, retry).await?;
+
+    write_stored_pools(
+        &format!("{}/orca_pools.json", data_folder_path),
+        all_pools,
+        token_allow_list,
+        merge,
+    )
+    .await?;
 
-    let max_iterations: usize = match is_test {
-        true => 1,
-        false => 10, // change for production
-    };
+    Ok(tokens)
+}
 
-    // 50 per page
-    for _ in 0..max_iterations {
-        let response = client
-            .get(url.clone())
-            .send()
-            .await
-            .context("HTTP request to Orca API failed")?;
+async fn collect_pools(
+    base_url: &Url,
+    limits: &FetchLimits,
+    retry: &RetryPolicy,
+) -> Result<(Vec<PoolInfo>, HashSet<TokenInfo>)> {
+    let mut all_pools = Vec::new();
+    let client = reqwest::Client::new();
+    let page_size = limits.page_size.to_string();
+    let mut url = base_url.clone();
+    url.query_pairs_mut()
+        .append_pair("sortBy", "volume24h")
+        .append_pair("sortDirection", "desc")
+        .append_pair("size", &page_size);
+    let mut tokens = HashSet::new();
+    let mut seen_cursors = HashSet::new();
 
-        let text = response
-            .text()
+    for _ in 0..limits.max_pages {
+        let text = get_with_retry(&client, &url, retry)
             .await
-            .context("Failed to read Orca API response body")?;
+            .context("Failed to fetch Orca pools page")?;
 
         let mut deserializer = Deserializer::from_str(&text);
         let deserialized_response: OrcaPoolsResponse =
@@ -107,45 +118,73 @@ pub async fn fetch_pools(data_folder_path: &str, is_test: bool) -> Result<HashSe
                 config: pool.config.clone(),
             };
 
-            if generic_pool.check().is_err() {
-                continue;
+            if generic_pool.check().is_ok() {
+                all_pools.push(generic_pool);
             }
-
-            if !first_item {
-                writer
-                    .write_all(b",")
-                    .await
-                    .context("Failed to write JSON separator")?;
-            }
-
-            let json =
-                serde_json::to_string(&generic_pool).context("Failed to serialize PoolInfo")?;
-
-            writer
-                .write_all(json.as_bytes())
-                .await
-                .context("Failed to write pool JSON")?;
-
-            first_item = false;
         }
 
         let next_page = match deserialized_response.meta.cursor.next {
             Some(ref n) if !n.is_empty() => n.clone(),
             _ => break,
         };
+        // a cursor handed out twice would serve the same pages until max_pages runs out
+        if !seen_cursors.insert(next_page.clone()) {
+            warn!("Orca API repeated cursor {}, stopping early", next_page);
+            break;
+        }
 
+        url = base_url.clone();
         url.query_pairs_mut()
-            .clear()
             .append_pair("sortBy", "volume24h")
             .append_pair("sortDirection", "desc")
+            .append_pair("size", &page_size)
             .append_pair("next", &next_page);
     }
 
-    writer
-        .write_all(b"]}")
-        .await
-        .context("Failed to write JSON footer")?;
-    writer.flush().await.context("Failed to flush writer")?;
+    Ok((all_pools, tokens))
+}
 
-    Ok(tokens)
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::bootstrap::mock_server::mock_server;
+
+    fn page_body(pool_address: &str, next: &str) -> String {
+        format!(
+            r#"{{"data":[{{
+                "address":"{}","feeRate":400,"poolType":"concentrated","tickSpacing":4,
+                "tokenA":{{"address":"So11111111111111111111111111111111111111112","decimals":9}},
+                "tokenB":{{"address":"EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v","decimals":6}},
+                "tokenVaultA":"vault_a","tokenVaultB":"vault_b","whirlpoolsConfig":"config"
+            }}],"meta":{{"cursor":{{"next":"{}"}}}}}}"#,
+            pool_address, next
+        )
+    }
+
+    #[tokio::test]
+    async fn test_repeated_cursor_stops_pagination() {
+        let (url, requests) = mock_server(vec![
+            (200, page_body("pool_1", "cursor_1")),
+            (200, page_body("pool_2", "cursor_1")),
+            (200, page_body("pool_2", "cursor_1")),
+        ])
+        .await;
+        let limits = FetchLimits {
+            max_pages: 10,
+            page_size: 1,
+        };
+
+        let (pools, _) = collect_pools(&url, &limits, &RetryPolicy::default())
+            .await
+            .unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        let addresses: Vec<_> = pools
+            .iter()
+            .map(|pool| pool.address.as_deref().unwrap())
+            .collect();
+        assert_eq!(addresses, vec!["pool_1", "pool_2"]);
+    }
 }
@@ -0,0 +1,225 @@
+use std::{collections::HashSet, io::ErrorKind, path::Path};
+
+use anyhow::{Context, Result};
+use tokio::fs::{read_to_string, rename, write};
+
+use super::pool_schema::{PoolInfo, StoredPools};
+use crate::metrics;
+
+pub async fn read_stored_pools(output_path: &str) -> Result<StoredPools> {
+    match read_to_string(output_path).await {
+        Ok(raw_json) => serde_json::from_str(&raw_json)
+            .with_context(|| format!("Failed to parse existing {}", output_path)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(StoredPools {
+            all_pools: Vec::new(),
+        }),
+        Err(e) => Err(e).with_context(|| format!("Failed to read existing {}", output_path)),
+    }
+}
+
+// serialized next to the real file and renamed over it, so readers never see a half-written file.
+// the allow list applies after merging, so pools kept from earlier runs are filtered too
+pub async fn write_stored_pools(
+    output_path: &str,
+    all_pools: Vec<PoolInfo>,
+    token_allow_list: Option<&HashSet<String>>,
+    merge: bool,
+) -> Result<()> {
+    let mut stored_pools = match merge {
+        true => read_stored_pools(output_path).await?.merge(all_pools),
+        false => StoredPools { all_pools },
+    };
+    // an API that serves a page twice lists its pools twice, the first listing is kept
+    let mut seen_addresses = HashSet::new();
+    stored_pools.all_pools.retain(|pool| {
+        pool.address
+            .as_ref()
+            .is_none_or(|address| seen_addresses.insert(address.clone()))
+    });
+    if let Some(tokens) = token_allow_list {
+        stored_pools
+            .all_pools
+            .retain(|pool| pool.trades_only(tokens));
+    }
+
+    if let Some(file) = Path::new(output_path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+    {
+        metrics::BOOTSTRAP_POOLS
+            .with_label_values(&[file])
+            .set(stored_pools.all_pools.len() as i64);
+    }
+
+    let partial_path = format!("{}.partial", output_path);
+    let json = serde_json::to_vec(&stored_pools).context("Failed to serialize StoredPools")?;
+
+    write(&partial_path, json)
+        .await
+        .with_context(|| format!("Failed to write {}", partial_path))?;
+    rename(&partial_path, output_path)
+        .await
+        .with_context(|| format!("Failed to move {} into place", partial_path))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootstrap::pool_schema::{DexType, PoolType, TokenInfo};
+
+    fn temp_output(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("output-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name).to_string_lossy().into_owned()
+    }
+
+    fn test_pool(address: &str) -> PoolInfo {
+        let token = |address: &str| TokenInfo {
+            address: Some(address.to_string()),
+            decimals: Some(6),
+            name: None,
+            symbol: None,
+        };
+        PoolInfo {
+            address: Some(address.to_string()),
+            fee_rate: Some(400),
+            pool_type: Some(PoolType::Concentrated),
+            dex: Some(DexType::Orca),
+            tick_spacing: Some(4),
+            token_a: Some(token("So11111111111111111111111111111111111111112")),
+            token_b: Some(token("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v")),
+            token_vault_a: Some("vault_a".to_string()),
+            token_vault_b: Some("vault_b".to_string()),
+            config: Some("config".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_written_file_parses_back() {
+        let output = temp_output("pools.json");
+        let pools = vec![test_pool("pool_1"), test_pool("pool_2")];
+
+        write_stored_pools(&output, pools, None, false)
+            .await
+            .unwrap();
+
+        let stored: StoredPools =
+            serde_json::from_str(&read_to_string(&output).await.unwrap()).unwrap();
+        let addresses: Vec<_> = stored
+            .all_pools
+            .iter()
+            .map(|pool| pool.address.as_deref().unwrap())
+            .collect();
+        assert_eq!(addresses, vec!["pool_1", "pool_2"]);
+        assert!(!std::path::Path::new(&format!("{}.partial", output)).exists());
+    }
+
+    #[tokio::test]
+    async fn test_empty_pool_list_is_valid_json() {
+        let output = temp_output("empty.json");
+
+        write_stored_pools(&output, Vec::new(), None, false)
+            .await
+            .unwrap();
+
+        let stored: StoredPools =
+            serde_json::from_str(&read_to_string(&output).await.unwrap()).unwrap();
+        assert!(stored.all_pools.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_merge_keeps_pools_from_previous_run() {
+        let output = temp_output("merge.json");
+        write_stored_pools(
+            &output,
+            vec![test_pool("pool_1"), test_pool("pool_2")],
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+
+        write_stored_pools(
+            &output,
+            vec![test_pool("pool_2"), test_pool("pool_3")],
+            None,
+            true,
+        )
+        .await
+        .unwrap();
+
+        let stored = read_stored_pools(&output).await.unwrap();
+        let addresses: Vec<_> = stored
+            .all_pools
+            .iter()
+            .map(|pool| pool.address.as_deref().unwrap())
+            .collect();
+        assert_eq!(addresses, vec!["pool_2", "pool_3", "pool_1"]);
+    }
+
+    #[tokio::test]
+    async fn test_merge_without_existing_file() {
+        let output = temp_output("merge_missing.json");
+        let _ = std::fs::remove_file(&output);
+
+        write_stored_pools(&output, vec![test_pool("pool_1")], None, true)
+            .await
+            .unwrap();
+
+        assert_eq!(read_stored_pools(&output).await.unwrap().all_pools.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_addresses_are_written_once() {
+        let output = temp_output("duplicates.json");
+        let mut repeated = test_pool("pool_1");
+        repeated.fee_rate = Some(100);
+
+        write_stored_pools(
+            &output,
+            vec![test_pool("pool_1"), test_pool("pool_2"), repeated],
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+
+        let stored = read_stored_pools(&output).await.unwrap();
+        let pools: Vec<_> = stored
+            .all_pools
+            .iter()
+            .map(|pool| (pool.address.as_deref().unwrap(), pool.fee_rate))
+            .collect();
+        assert_eq!(pools, vec![("pool_1", Some(400)), ("pool_2", Some(400))]);
+    }
+
+    #[tokio::test]
+    async fn test_allow_list_drops_pools_with_unlisted_tokens() {
+        let output = temp_output("allow_list.json");
+        let mut unlisted = test_pool("pool_2");
+        unlisted.token_b.as_mut().unwrap().address = Some("unlisted_mint".to_string());
+        let tokens = HashSet::from([
+            "So11111111111111111111111111111111111111112".to_string(),
+            "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string(),
+        ]);
+
+        write_stored_pools(
+            &output,
+            vec![test_pool("pool_1"), unlisted],
+            Some(&tokens),
+            false,
+        )
+        .await
+        .unwrap();
+
+        let stored = read_stored_pools(&output).await.unwrap();
+        let addresses: Vec<_> = stored
+            .all_pools
+            .iter()
+            .map(|pool| pool.address.as_deref().unwrap())
+            .collect();
+        assert_eq!(addresses, vec!["pool_1"]);
+    }
+}
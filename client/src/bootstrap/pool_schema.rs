@@ -1,9 +1,28 @@
-use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::File,
+    io::{BufReader, Read},
+};
 
+use anyhow::{anyhow, bail};
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+};
+
+use crate::get_all_pool_files;
+
+// reasons kept in a ValidationReport, the rest are only counted
+const MAX_REPORTED_ERRORS: usize = 5;
+
+// serialized by variant name into the cached pool files, so new variants can be
+// appended freely but existing ones must not be renamed
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DexType {
     Orca,
     Raydium,
+    Meteora,
     Unknown,
 }
 
@@ -40,52 +59,147 @@ pub struct StoredPools {
     pub all_pools: Vec<PoolInfo>,
 }
 
+impl StoredPools {
+    // fresh pools come first and replace stored ones with the same address,
+    // stored pools that dropped out of this fetch are kept after them
+    pub fn merge(self, fresh: Vec<PoolInfo>) -> StoredPools {
+        let fresh_addresses: HashSet<String> = fresh
+            .iter()
+            .filter_map(|pool| pool.address.clone())
+            .collect();
+
+        let mut all_pools = fresh;
+        all_pools.extend(self.all_pools.into_iter().filter(|pool| {
+            pool.address
+                .as_ref()
+                .is_some_and(|address| !fresh_addresses.contains(address))
+        }));
+        StoredPools { all_pools }
+    }
+}
+
+// walks a StoredPools document handing each pool to on_pool as soon as it's parsed,
+// so a large pool file is never held in memory as a whole Vec<PoolInfo>
+pub fn for_each_stored_pool<R: Read>(
+    reader: R,
+    on_pool: impl FnMut(PoolInfo),
+) -> serde_json::Result<()> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    deserializer.deserialize_map(StoredPoolsVisitor(on_pool))?;
+    deserializer.end()
+}
+
+struct StoredPoolsVisitor<F>(F);
+
+impl<'de, F: FnMut(PoolInfo)> Visitor<'de> for StoredPoolsVisitor<F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a stored pools object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "all_pools" {
+                map.next_value_seed(PoolSeq(&mut self.0))?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct PoolSeq<'a, F>(&'a mut F);
+
+impl<'de, F: FnMut(PoolInfo)> DeserializeSeed<'de> for PoolSeq<'_, F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(PoolInfo)> Visitor<'de> for PoolSeq<'_, F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of pools")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(pool) = seq.next_element::<PoolInfo>()? {
+            (self.0)(pool);
+        }
+        Ok(())
+    }
+}
+
 impl PoolInfo {
-    pub fn check(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn trades_only(&self, tokens: &HashSet<String>) -> bool {
+        [&self.token_a, &self.token_b].into_iter().all(|token| {
+            token
+                .as_ref()
+                .and_then(|token| token.address.as_ref())
+                .is_some_and(|address| tokens.contains(address))
+        })
+    }
+
+    pub fn check(&self) -> anyhow::Result<()> {
         // top-level fields
         if self.address.is_none() {
-            return Err("Missing Address".into());
+            bail!("Missing Address");
         }
         if self.fee_rate.is_none() {
-            return Err("Missing Fee Rate".into());
+            bail!("Missing Fee Rate");
         }
         if self.pool_type.is_none() {
-            return Err("Missing PoolType".into());
+            bail!("Missing PoolType");
         }
         if self.dex.is_none() {
-            return Err("Missing Dex Type".into());
-        }
-        if self.tick_spacing.is_none() {
-            return Err("Missing Tick Spacing".into());
+            bail!("Missing Dex Type");
         }
         if self.token_vault_a.is_none() {
-            return Err("Missing Token Vault A".into());
+            bail!("Missing Token Vault A");
         }
         if self.token_vault_b.is_none() {
-            return Err("Missing Token Vault B".into());
+            bail!("Missing Token Vault B");
         }
-        if self.config.is_none() {
-            return Err("Missing Config".into());
+
+        // Meteora DAMM v2 pools have no ticks and aren't always created from a config
+        if self.dex != Some(DexType::Meteora) {
+            if self.tick_spacing.is_none() {
+                bail!("Missing Tick Spacing");
+            }
+            if self.config.is_none() {
+                bail!("Missing Config");
+            }
         }
 
         // token A
-        let token_a = self.token_a.as_ref().ok_or("Missing Token A")?;
+        let token_a = self
+            .token_a
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing Token A"))?;
         if token_a.address.is_none() {
-            return Err("Missing Token A Address".into());
+            bail!("Missing Token A Address");
         }
         if token_a.decimals.is_none() {
-            return Err("Missing Token A Decimals".into());
+            bail!("Missing Token A Decimals");
         }
         // if token_a.name.is_none()    { return Err("Missing Token A Name".into()); }
         // if token_a.symbol.is_none()  { return Err("Missing Token A Symbol".into()); }
 
         // token B
-        let token_b = self.token_b.as_ref().ok_or("Missing Token B")?;
+        let token_b = self
+            .token_b
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing Token B"))?;
         if token_b.address.is_none() {
-            return Err("Missing Token B Address".into());
+            bail!("Missing Token B Address");
         }
         if token_b.decimals.is_none() {
-            return Err("Missing Token B Decimals".into());
+            bail!("Missing Token B Decimals");
         }
         // if token_b.name.is_none()    { return Err("Missing Token B Name".into()); }
         // if token_b.symbol.is_none()  { return Err("Missing Token B Symbol".into()); }
@@ -94,9 +208,215 @@ impl PoolInfo {
     }
 }
 
+// how the cached pool files hold up against the current PoolInfo schema
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub files: usize,
+    pub valid_pools: usize,
+    pub invalid_pools: usize,
+    // files that stopped parsing part way, their pools up to the error are still counted
+    pub unparseable_files: usize,
+    // the first few reasons, each prefixed with its file
+    pub errors: Vec<String>,
+}
+
+impl ValidationReport {
+    fn push_error(&mut self, error: String) {
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(error);
+        }
+    }
+}
+
+// parses every pool file in the folder and checks each pool, in file name order
+pub fn validate_pool_files(data_folder_path: &str) -> anyhow::Result<ValidationReport> {
+    let mut pool_files = get_all_pool_files(data_folder_path)?;
+    pool_files.sort();
+
+    let mut report = ValidationReport::default();
+    for pool_path in pool_files {
+        let reader = BufReader::new(File::open(&pool_path)?);
+        report.files += 1;
+
+        let mut checks = Vec::new();
+        let parsed = for_each_stored_pool(reader, |pool| {
+            checks.push(pool.check().map_err(|e| (pool.address, e)));
+        });
+        for check in checks {
+            match check {
+                Ok(()) => report.valid_pools += 1,
+                Err((address, e)) => {
+                    report.invalid_pools += 1;
+                    report.push_error(format!(
+                        "{}: pool {}: {}",
+                        pool_path.display(),
+                        address.as_deref().unwrap_or("without an address"),
+                        e
+                    ));
+                }
+            }
+        }
+        if let Err(e) = parsed {
+            report.unparseable_files += 1;
+            report.push_error(format!("{}: {}", pool_path.display(), e));
+        }
+    }
+
+    Ok(report)
+}
+
 #[derive(Debug)]
 pub struct PoolUpdate {
     pub new_liquidity: u128,
     pub new_sqrt_price: u128,
     pub new_current_tick_index: i32,
+    // slot the account was read at, decoders leave it 0 for the caller to fill in
+    pub slot: u64,
+    // set by decoders whose pool account carries them, update_edge keeps the old value on None
+    pub fee_rate: Option<u32>,
+    pub tick_spacing: Option<u16>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_reports_broken_pool_and_unparseable_file() {
+        let report = validate_pool_files("./tests/invalid_data").unwrap();
+
+        assert_eq!(report.files, 2);
+        // two good orca pools and the raydium pool read before the unknown dex
+        assert_eq!(report.valid_pools, 3);
+        assert_eq!(report.invalid_pools, 1);
+        assert_eq!(report.unparseable_files, 1);
+        assert_eq!(report.errors.len(), 2);
+        assert!(report.errors[0].contains("orca_pools.json"));
+        assert!(report.errors[0].contains("Missing Token Vault B"));
+        assert!(report.errors[1].contains("raydium_pools.json"));
+        assert!(report.errors[1].contains("unknown variant `Phoenix`"));
+
+        let report = validate_pool_files("./tests/test_data").unwrap();
+        assert_eq!((report.invalid_pools, report.unparseable_files), (0, 0));
+        assert!(report.errors.is_empty());
+    }
+
+    fn test_meteora_pool() -> PoolInfo {
+        PoolInfo {
+            address: Some("Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE".to_string()),
+            fee_rate: Some(2500),
+            pool_type: Some(PoolType::Concentrated),
+            dex: Some(DexType::Meteora),
+            tick_spacing: None,
+            token_a: Some(TokenInfo {
+                address: Some("So11111111111111111111111111111111111111112".to_string()),
+                decimals: Some(9),
+                name: None,
+                symbol: Some("SOL".to_string()),
+            }),
+            token_b: Some(TokenInfo {
+                address: Some("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string()),
+                decimals: Some(6),
+                name: None,
+                symbol: Some("USDC".to_string()),
+            }),
+            token_vault_a: Some("EUuUbDcafPrmVTD5M6qoJAoyyNbihBhugADAxRMn5he9".to_string()),
+            token_vault_b: Some("2WLWEuKDgkDUccTpbwYp1GToYktiSB1cXvreHUwiSUVP".to_string()),
+            config: None,
+        }
+    }
+
+    #[test]
+    fn test_meteora_pool_info_round_trips_through_serde() {
+        let pool = test_meteora_pool();
+
+        let json = serde_json::to_string(&pool).unwrap();
+        let deserialized: PoolInfo = serde_json::from_str(&json).unwrap();
+
+        assert!(json.contains("\"dex\":\"Meteora\""));
+        assert_eq!(deserialized, pool);
+    }
+
+    #[test]
+    fn test_meteora_pool_without_ticks_and_config_passes_check() {
+        assert!(test_meteora_pool().check().is_ok());
+    }
+
+    #[test]
+    fn test_orca_pool_without_tick_spacing_fails_check() {
+        let pool = PoolInfo {
+            dex: Some(DexType::Orca),
+            config: Some("2LecshUwdy9xi7meFgHtFJQNSKk4KdTrcpvaB56dP2NQ".to_string()),
+            ..test_meteora_pool()
+        };
+
+        assert_eq!(
+            pool.check().unwrap_err().to_string(),
+            "Missing Tick Spacing"
+        );
+    }
+
+    #[test]
+    fn test_merge_updates_overlap_and_keeps_dropped_pools() {
+        let pool = |address: &str, fee_rate: u32| PoolInfo {
+            address: Some(address.to_string()),
+            fee_rate: Some(fee_rate),
+            ..test_meteora_pool()
+        };
+        let stored = StoredPools {
+            all_pools: vec![pool("old", 100), pool("both", 100)],
+        };
+
+        let merged = stored.merge(vec![pool("both", 300), pool("new", 300)]);
+
+        let summary: Vec<_> = merged
+            .all_pools
+            .iter()
+            .map(|pool| (pool.address.as_deref().unwrap(), pool.fee_rate.unwrap()))
+            .collect();
+        assert_eq!(summary, vec![("both", 300), ("new", 300), ("old", 100)]);
+    }
+
+    #[test]
+    fn test_cached_files_written_before_meteora_still_deserialize() {
+        for file in ["orca_pools.json", "raydium_pools.json"] {
+            let raw_json = std::fs::read_to_string(format!("./tests/test_data/{}", file)).unwrap();
+            let stored: StoredPools = serde_json::from_str(&raw_json).unwrap();
+
+            assert!(!stored.all_pools.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_streamed_pools_match_slurped_pools() {
+        // a few megabytes of pools, plus a key the reader has to skip
+        let pools: Vec<PoolInfo> = (0..10_000)
+            .map(|i| PoolInfo {
+                address: Some(format!("pool_{}", i)),
+                fee_rate: Some(i),
+                ..test_meteora_pool()
+            })
+            .collect();
+        let mut json = serde_json::to_string(&StoredPools { all_pools: pools }).unwrap();
+        json.insert_str(1, "\"fetched_at\":[1,{\"nested\":true}],");
+        assert!(json.len() > 4_000_000);
+
+        let mut streamed = Vec::new();
+        for_each_stored_pool(json.as_bytes(), |pool| streamed.push(pool)).unwrap();
+
+        let slurped: StoredPools = serde_json::from_str(&json).unwrap();
+        assert_eq!(streamed, slurped.all_pools);
+    }
+
+    #[test]
+    fn test_streamed_pools_reject_malformed_file() {
+        let mut seen = 0;
+        let result = for_each_stored_pool(
+            r#"{"all_pools":[{"address":"a"},{"address":"#.as_bytes(),
+            |_| seen += 1,
+        );
+
+        assert!(result.is_err());
+        assert_eq!(seen, 1);
+    }
 }
@@ -1,4 +1,7 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use anyhow::{Context, Result};
 use reqwest::Url;
@@ -6,12 +9,15 @@ use serde::{Deserialize, Serialize};
 use serde_json::Deserializer;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
-use tokio::{
-    fs::File,
-    io::{AsyncWriteExt, BufWriter},
-};
+use tracing::warn;
 
-use super::pool_schema::{DexType, PoolInfo, PoolType, TokenInfo};
+use super::{
+    limits::FetchLimits,
+    output::write_stored_pools,
+    pool_schema::{DexType, PoolInfo, PoolType, TokenInfo},
+    retry::{RetryPolicy, get_with_retry},
+};
+use crate::decoders::decode_raydium_vaults;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct RaydiumPool {
@@ -56,56 +62,87 @@ struct RaydiumResponse {
     data: RaydiumData,
 }
 
-pub async fn fetch_pools(data_folder_path: &str, is_test: bool) -> Result<HashSet<TokenInfo>> {
-    let file = File::create(format!("{}/raydium_pools.json", data_folder_path))
-        .await
-        .context("Failed to create output file")?;
-    let mut writer = BufWriter::new(file);
-    writer
-        .write_all(b"{\"all_pools\":[")
-        .await
-        .context("Failed to write JSON header")?;
+const RAYDIUM_POOLS_URL: &str = "https://api-v3.raydium.io/pools/info/list";
+
+pub async fn fetch_pools(
+    data_folder_path: &str,
+    rpc_client: Arc<RpcClient>,
+    limits: &FetchLimits,
+    retry: &RetryPolicy,
+    token_allow_list: Option<&HashSet<String>>,
+    merge: bool,
+) -> Result<HashSet<TokenInfo>> {
+    let base_url = Url::parse(RAYDIUM_POOLS_URL).context("Invalid Raydium URL")?;
+    let (all_pools, tokens) = collect_pools(&base_url, &rpc_client, limits, retry).await?;
 
+    write_stored_pools(
+        &format!("{}/raydium_pools.json", data_folder_path),
+        all_pools,
+        token_allow_list,
+        merge,
+    )
+    .await?;
+
+    Ok(tokens)
+}
+
+async fn collect_pools(
+    base_url: &Url,
+    rpc_client: &RpcClient,
+    limits: &FetchLimits,
+    retry: &RetryPolicy,
+) -> Result<(Vec<PoolInfo>, HashSet<TokenInfo>)> {
     let client = reqwest::Client::new();
-    let mut page = 1;
-    let mut url = Url::parse("https://api-v3.raydium.io/pools/info/list?poolType=all&poolSortField=volume7d&sortType=desc&pageSize=100&page=1")
-        .context("Invalid Raydium URL")?;
-    let mut first_item = true;
-    let rpc_client = RpcClient::new("https://api.mainnet-beta.solana.com".to_string());
+    let mut all_pools = Vec::new();
     let mut tokens = HashSet::new();
+    let mut seen_pools = HashSet::new();
 
-    let max_iterations: usize = match is_test {
-        true => 1,
-        false => 5, // change for production
+    let mut next_page = match limits.max_pages {
+        0 => None,
+        _ => Some(fetch_page(&client, base_url, limits, 1, retry).await?),
     };
 
-    //100 per page
-    for _ in 0..max_iterations {
-        let response = client
-            .get(url.clone())
-            .send()
-            .await
-            .context("HTTP request failed")?;
-        let text = response
-            .text()
-            .await
-            .context("Failed to read response body")?;
-
-        let mut deserializer = Deserializer::from_str(&text);
-        let deserialized_response: RaydiumResponse =
-            serde_path_to_error::deserialize(&mut deserializer)
-                .context("Failed to deserialize Raydium response")?;
+    for page in 1..=limits.max_pages {
+        let Some(current_page) = next_page.take() else {
+            break;
+        };
 
-        let pools = deserialized_response.data.data;
+        let pools = current_page.data;
+        // a page of nothing but known pools is the API serving an earlier page again, every
+        // later page would repeat it too
+        let page_ids: Vec<&String> = pools.iter().filter_map(|pool| pool.id.as_ref()).collect();
+        let new_pools = page_ids
+            .iter()
+            .filter(|id| seen_pools.insert(id.to_string()))
+            .count();
+        if !page_ids.is_empty() && new_pools == 0 {
+            warn!("Raydium API repeated page {}, stopping early", page);
+            break;
+        }
         let pool_addresses: Vec<Pubkey> = pools
             .iter()
             .filter_map(|pool| pool.id.as_ref()?.parse().ok())
             .collect();
 
-        let vaults = fetch_vaults_batch(&rpc_client, pool_addresses).await?;
+        // the next page downloads while this page's vaults are fetched over RPC
+        let fetch_next = current_page.has_next_page && page < limits.max_pages;
+        let (vaults, following_page) =
+            tokio::join!(fetch_vaults_batch(rpc_client, pool_addresses), async {
+                match fetch_next {
+                    true => fetch_page(&client, base_url, limits, page + 1, retry)
+                        .await
+                        .map(Some),
+                    false => Ok(None),
+                }
+            });
+        let vaults = vaults?;
+        next_page = following_page?;
 
-        for (pool_index, pool) in pools.iter().enumerate() {
-            if let Some((token_a_vault, token_b_vault)) = vaults.get(&pool_index) {
+        for pool in &pools {
+            let pool_address = pool.id.as_ref().and_then(|id| id.parse::<Pubkey>().ok());
+            if let Some((token_a_vault, token_b_vault)) =
+                pool_address.and_then(|address| vaults.get(&address))
+            {
                 tokens.insert(TokenInfo {
                     address: pool.token_a.address.clone(),
                     decimals: pool.token_a.decimals,
@@ -149,74 +186,137 @@ pub async fn fetch_pools(data_folder_path: &str, is_test: bool) -> Result<HashSe
                 };
 
                 if generic_pool.check().is_ok() {
-                    if !first_item {
-                        writer.write_all(b",").await?;
-                    }
-                    let json = serde_json::to_string(&generic_pool)
-                        .context("Failed to serialize PoolInfo")?;
-                    writer
-                        .write_all(json.as_bytes())
-                        .await
-                        .context("Failed to write pool JSON")?;
-                    first_item = false;
+                    all_pools.push(generic_pool);
                 }
             }
         }
+    }
 
-        if !deserialized_response.data.has_next_page {
-            break;
-        }
+    Ok((all_pools, tokens))
+}
 
-        page += 1;
-        url.query_pairs_mut()
-            .clear()
-            .append_pair("poolType", "all")
-            .append_pair("poolSortField", "volume7d")
-            .append_pair("sortType", "desc")
-            .append_pair("pageSize", "100")
-            .append_pair("page", &page.to_string());
-    }
+async fn fetch_page(
+    client: &reqwest::Client,
+    base_url: &Url,
+    limits: &FetchLimits,
+    page: usize,
+    retry: &RetryPolicy,
+) -> Result<RaydiumData> {
+    let mut url = base_url.clone();
+    url.query_pairs_mut()
+        .append_pair("poolType", "all")
+        .append_pair("poolSortField", "volume7d")
+        .append_pair("sortType", "desc")
+        .append_pair("pageSize", &limits.page_size.to_string())
+        .append_pair("page", &page.to_string());
 
-    writer.write_all(b"]}").await?;
-    writer.flush().await?;
+    let text = get_with_retry(client, &url, retry)
+        .await
+        .context("Failed to fetch Raydium pools page")?;
 
-    Ok(tokens)
+    let mut deserializer = Deserializer::from_str(&text);
+    let deserialized_response: RaydiumResponse =
+        serde_path_to_error::deserialize(&mut deserializer)
+            .context("Failed to deserialize Raydium response")?;
+
+    Ok(deserialized_response.data)
 }
 
 async fn fetch_vaults_batch(
     client: &RpcClient,
     pool_addresses: Vec<Pubkey>,
-) -> Result<HashMap<usize, (Pubkey, Pubkey)>> {
+) -> Result<HashMap<Pubkey, (Pubkey, Pubkey)>> {
     let accounts = client
         .get_multiple_accounts(&pool_addresses)
         .await
         .context("Failed to fetch vault accounts")?;
 
     let mut vaults = HashMap::new();
+    let mut skipped: HashMap<String, usize> = HashMap::new();
 
-    for (i, account_opt) in accounts.into_iter().enumerate() {
-        if let Some(account) = account_opt {
-            let data = account.data;
-            if data.len() != 1544 {
-                continue;
-            }
+    for (address, account_opt) in pool_addresses.iter().zip(accounts) {
+        let decoded = match account_opt {
+            Some(account) => decode_raydium_vaults(&account).map_err(|e| e.to_string()),
+            None => Err("account missing".to_string()),
+        };
 
-            let token_a_vault = Pubkey::new_from_array(
-                data[137..169]
-                    .try_into()
-                    .context("Failed to parse token_a_vault")?,
-            );
-            let token_b_vault = Pubkey::new_from_array(
-                data[169..201]
-                    .try_into()
-                    .context("Failed to parse token_b_vault")?,
-            );
-
-            vaults.insert(i, (token_a_vault, token_b_vault));
-        } else {
-            eprintln!("Account {} missing (None)", i);
+        match decoded {
+            Ok(pool_vaults) => {
+                vaults.insert(*address, pool_vaults);
+            }
+            Err(reason) => *skipped.entry(reason).or_default() += 1,
         }
     }
 
+    if !skipped.is_empty() {
+        warn!(
+            "Skipped {} of {} Raydium pools while reading vaults: {:?}",
+            skipped.values().sum::<usize>(),
+            pool_addresses.len(),
+            skipped
+        );
+    }
+
     Ok(vaults)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+
+    use super::*;
+    use crate::bootstrap::mock_server::mock_server;
+
+    fn page_body(pool_address: &Pubkey) -> String {
+        json!({"data": {"hasNextPage": true, "data": [{
+            "id": pool_address.to_string(),
+            "type": "Concentrated",
+            "mintA": {"address": "So11111111111111111111111111111111111111112", "decimals": 9},
+            "mintB": {"address": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", "decimals": 6},
+        }]}})
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_repeated_page_stops_pagination() {
+        let pool = Pubkey::new_unique();
+        let (url, requests) = mock_server(vec![
+            (200, page_body(&pool)),
+            (200, page_body(&pool)),
+            (200, page_body(&Pubkey::new_unique())),
+        ])
+        .await;
+        // the first page's vaults are read once, the repeated page stops before its own read
+        let rpc_client = RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            HashMap::from([(
+                RpcRequest::GetMultipleAccounts,
+                json!({"context": {"slot": 1}, "value": [null]}),
+            )]),
+        );
+        let limits = FetchLimits {
+            max_pages: 10,
+            page_size: 1,
+        };
+
+        collect_pools(&url, &rpc_client, &limits, &RetryPolicy::default())
+            .await
+            .unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_vault_rpc_error_is_returned() {
+        // nothing listens here, the request fails instead of panicking
+        let client = RpcClient::new("http://127.0.0.1:1".to_string());
+
+        let result = fetch_vaults_batch(&client, vec![Pubkey::new_unique()]).await;
+
+        let error = result.unwrap_err();
+        assert!(error.to_string().contains("Failed to fetch vault accounts"));
+    }
+}
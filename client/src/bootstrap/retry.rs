@@ -0,0 +1,201 @@
+use std::{future::Future, time::Duration};
+
+use anyhow::{Context, Result, anyhow, bail};
+use reqwest::{Client, StatusCode, Url};
+use tracing::warn;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+// a hung request is cut off at the deadline and tried again with twice as long
+#[derive(Debug, Clone)]
+pub struct TimeoutPolicy {
+    pub max_attempts: u32,
+    pub initial_timeout: Duration,
+    pub max_timeout: Duration,
+}
+
+impl Default for TimeoutPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_timeout: Duration::from_secs(5),
+            max_timeout: Duration::from_secs(20),
+        }
+    }
+}
+
+// rate limits and server errors are worth another try, other client errors are not
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+pub async fn get_with_retry(client: &Client, url: &Url, policy: &RetryPolicy) -> Result<String> {
+    let mut backoff = policy.initial_backoff;
+    let mut attempt = 1;
+
+    loop {
+        let error = match client.get(url.clone()).send().await {
+            Ok(response) if response.status().is_success() => {
+                return response
+                    .text()
+                    .await
+                    .with_context(|| format!("Failed to read response body from {}", url));
+            }
+            Ok(response) if !is_retryable(response.status()) => {
+                bail!("{} returned status {}", url, response.status());
+            }
+            Ok(response) => anyhow::anyhow!("{} returned status {}", url, response.status()),
+            Err(e) => anyhow::Error::new(e).context(format!("HTTP request to {} failed", url)),
+        };
+
+        if attempt >= policy.max_attempts {
+            return Err(error.context(format!("Giving up after {} attempts", attempt)));
+        }
+
+        warn!(
+            "Attempt {}/{} failed: {:?}, retrying in {:?}",
+            attempt, policy.max_attempts, error, backoff
+        );
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(policy.max_backoff);
+        attempt += 1;
+    }
+}
+
+// errors are retried like timeouts, the last one is returned once the attempts run out
+pub async fn with_timeout<T, F, Fut>(policy: &TimeoutPolicy, mut request: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut timeout = policy.initial_timeout;
+    let mut attempt = 1;
+
+    loop {
+        let error = match tokio::time::timeout(timeout, request()).await {
+            Ok(Ok(value)) => return Ok(value),
+            Ok(Err(e)) => e,
+            Err(_) => anyhow!("Timed out after {:?}", timeout),
+        };
+
+        if attempt >= policy.max_attempts {
+            return Err(error.context(format!("Giving up after {} attempts", attempt)));
+        }
+
+        timeout = (timeout * 2).min(policy.max_timeout);
+        warn!(
+            "Attempt {}/{} failed: {:?}, retrying with a {:?} deadline",
+            attempt, policy.max_attempts, error, timeout
+        );
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootstrap::mock_server::mock_server;
+
+    fn responses(statuses: &[u16]) -> Vec<(u16, String)> {
+        statuses
+            .iter()
+            .map(|status| (*status, format!("{{\"status\":{}}}", status)))
+            .collect()
+    }
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+        }
+    }
+
+    fn fast_timeouts(max_attempts: u32) -> TimeoutPolicy {
+        TimeoutPolicy {
+            max_attempts,
+            initial_timeout: Duration::from_millis(20),
+            max_timeout: Duration::from_millis(40),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_batch_is_retried() {
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+
+        let result = with_timeout(&fast_timeouts(3), || {
+            let attempt = attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async move {
+                if attempt == 0 {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+                Ok(attempt)
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_hung_batch_gives_up() {
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+
+        let error = with_timeout(&fast_timeouts(2), || {
+            attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            std::future::pending::<Result<()>>()
+        })
+        .await
+        .unwrap_err();
+
+        assert!(format!("{:?}", error).contains("Timed out after 40ms"));
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retries_after_rate_limit() {
+        let (url, _) = mock_server(responses(&[429, 200])).await;
+
+        let body = get_with_retry(&Client::new(), &url, &fast_policy(3))
+            .await
+            .unwrap();
+
+        assert_eq!(body, "{\"status\":200}");
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let (url, _) = mock_server(responses(&[503, 503])).await;
+
+        let result = get_with_retry(&Client::new(), &url, &fast_policy(2)).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let (url, requests) = mock_server(responses(&[404, 200])).await;
+
+        let error = get_with_retry(&Client::new(), &url, &fast_policy(3))
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("404"));
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+}
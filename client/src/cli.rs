@@ -0,0 +1,336 @@
+use clap::{Args, Parser, Subcommand};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    bootstrap::limits::{BootstrapLimits, PoolSource},
+    config::{Cluster, Config, DEFAULT_ACCOUNT_CHUNK_SIZE, DEFAULT_DATA_DIR},
+    graph::{DEFAULT_MAX_CYCLES, DEFAULT_MIN_LOG_PROFIT},
+    opportunities::OutputFormat,
+};
+
+pub const DEFAULT_SHRED_ENDPOINT: &str = "http://127.0.0.1:9999";
+pub const DEFAULT_MAX_DEPTH: usize = 4;
+pub const DEFAULT_BATCH_TIMEOUT_MS: u64 = 5_000;
+// the base fee of a single signature transaction
+pub const DEFAULT_PRIORITY_FEE_LAMPORTS: u64 = 5_000;
+
+// flags win over the env vars, which win over the cluster's public endpoints
+#[derive(Debug, Parser)]
+#[command(name = "client", about = "Solana MEV bot")]
+pub struct Cli {
+    #[arg(long, global = true, env = "SOLANA_CLUSTER", value_enum, default_value_t = Cluster::Mainnet)]
+    pub cluster: Cluster,
+    #[arg(long, global = true, env = "SOLANA_RPC_URL")]
+    pub rpc_url: Option<String>,
+    #[arg(long, global = true, env = "SOLANA_WS_URL")]
+    pub ws_url: Option<String>,
+    #[arg(long, global = true, default_value = DEFAULT_DATA_DIR)]
+    pub data_dir: String,
+    /// Accounts per get_multiple_accounts call, at most 100 on the public endpoints
+    #[arg(long, global = true, env = "ACCOUNT_CHUNK_SIZE", default_value_t = DEFAULT_ACCOUNT_CHUNK_SIZE)]
+    pub account_chunk_size: usize,
+    /// Serve Prometheus metrics at /metrics on this port
+    #[arg(long, global = true, env = "METRICS_PORT")]
+    pub metrics_port: Option<u16>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+pub enum Command {
+    /// Fetch pool lists from every DEX into the data dir
+    Setup {
+        /// Caps the pages fetched from each DEX, defaults to the per-DEX production limits
+        #[arg(long)]
+        max_pages: Option<usize>,
+        /// Keep pools from earlier runs that aren't returned this time
+        #[arg(long)]
+        merge: bool,
+        /// Read pools from the DEX APIs or straight from the DEX programs over RPC
+        #[arg(long, value_enum, default_value_t = PoolSource::Api)]
+        source: PoolSource,
+        /// Only keep pools whose two mints are both in this comma separated list
+        #[arg(long, value_delimiter = ',')]
+        token_allow_list: Vec<String>,
+    },
+    /// Print entries from a shredstream proxy
+    Stream {
+        #[arg(long, default_value = DEFAULT_SHRED_ENDPOINT)]
+        shred_endpoint: String,
+    },
+    /// Build the graph and watch pools for arbitrage
+    Scan(ScanArgs),
+    /// Check the cached pool files against the current schema, fails on unparseable files
+    Validate,
+}
+
+// the scan flags, passed to scan as one value
+#[derive(Debug, Args, PartialEq)]
+pub struct ScanArgs {
+    #[arg(long, default_value_t = DEFAULT_MAX_DEPTH)]
+    pub max_depth: usize,
+    /// Stop the cycle search after this many cycles and scan the ones found so far
+    #[arg(long, default_value_t = DEFAULT_MAX_CYCLES)]
+    pub max_cycles: usize,
+    /// Deadline for the first try of each account batch, doubled on every retry
+    #[arg(long, default_value_t = DEFAULT_BATCH_TIMEOUT_MS)]
+    pub batch_timeout_ms: u64,
+    /// Report cycles above this log10 profit, pool fees are already taken out
+    #[arg(long, default_value_t = DEFAULT_MIN_LOG_PROFIT)]
+    pub min_log_profit: f64,
+    /// Lamports a bundle pays to land, taken out of the estimated profit of each opportunity
+    #[arg(long, default_value_t = DEFAULT_PRIORITY_FEE_LAMPORTS)]
+    pub priority_fee_lamports: u64,
+    /// Comma separated tokens the cycles start from, in priority order, defaults to WSOL
+    #[arg(long, value_delimiter = ',')]
+    pub base_tokens: Vec<Pubkey>,
+    /// Skip cycles through pools with less raw liquidity than this
+    #[arg(long, default_value_t = 0)]
+    pub min_liquidity: u128,
+    /// Search one pool per token pair, the deepest at startup, instead of every fee tier
+    #[arg(long)]
+    pub collapse_parallel_pools: bool,
+    /// Write opportunities to stdout, or as JSON lines or CSV for later analysis
+    #[arg(long, value_enum, default_value_t = OutputFormat::Stdout)]
+    pub output_format: OutputFormat,
+    /// File for the json and csv formats, defaults to opportunities.jsonl or .csv in the data dir
+    #[arg(long)]
+    pub output: Option<String>,
+}
+
+impl Cli {
+    pub fn config(&self) -> Config {
+        let defaults = Config::for_cluster(self.cluster);
+        Config {
+            rpc_url: self.rpc_url.clone().unwrap_or(defaults.rpc_url),
+            ws_url: self.ws_url.clone().unwrap_or(defaults.ws_url),
+            data_dir: self.data_dir.clone(),
+            account_chunk_size: self.account_chunk_size,
+            ..defaults
+        }
+    }
+}
+
+pub fn bootstrap_limits(
+    max_pages: Option<usize>,
+    source: PoolSource,
+    token_allow_list: &[String],
+) -> BootstrapLimits {
+    let mut limits = BootstrapLimits {
+        source,
+        // an empty list means the flag wasn't given
+        token_allow_list: (!token_allow_list.is_empty())
+            .then(|| token_allow_list.iter().cloned().collect()),
+        ..BootstrapLimits::default()
+    };
+    if let Some(max_pages) = max_pages {
+        limits.orca.max_pages = max_pages;
+        limits.raydium.max_pages = max_pages;
+        limits.meteora.max_pages = max_pages;
+    }
+    limits
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_scan_with_defaults() {
+        let cli = Cli::try_parse_from(["client", "scan"]).unwrap();
+
+        assert_eq!(
+            cli.command,
+            Command::Scan(ScanArgs {
+                max_depth: DEFAULT_MAX_DEPTH,
+                max_cycles: DEFAULT_MAX_CYCLES,
+                batch_timeout_ms: DEFAULT_BATCH_TIMEOUT_MS,
+                min_log_profit: DEFAULT_MIN_LOG_PROFIT,
+                priority_fee_lamports: DEFAULT_PRIORITY_FEE_LAMPORTS,
+                base_tokens: vec![],
+                min_liquidity: 0,
+                collapse_parallel_pools: false,
+                output_format: OutputFormat::Stdout,
+                output: None,
+            })
+        );
+        assert_eq!(cli.data_dir, DEFAULT_DATA_DIR);
+        assert_eq!(cli.config().account_chunk_size, DEFAULT_ACCOUNT_CHUNK_SIZE);
+        assert_eq!(cli.metrics_port, None);
+        assert_eq!(
+            cli.config().graph_snapshot_path(),
+            "./cached-blockchain-data/graph.bin"
+        );
+    }
+
+    #[test]
+    fn test_parse_global_flags_after_subcommand() {
+        let cli = Cli::try_parse_from([
+            "client",
+            "scan",
+            "--max-depth",
+            "3",
+            "--max-cycles",
+            "500",
+            "--min-log-profit",
+            "0.001",
+            "--priority-fee-lamports",
+            "25000",
+            "--min-liquidity",
+            "5000",
+            "--collapse-parallel-pools",
+            "--output-format",
+            "csv",
+            "--output",
+            "/tmp/opportunities.csv",
+            "--base-tokens",
+            "So11111111111111111111111111111111111111112,EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+            "--rpc-url",
+            "http://flag",
+            "--data-dir",
+            "/tmp/pools",
+            "--metrics-port",
+            "9100",
+            "--account-chunk-size",
+            "250",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cli.command,
+            Command::Scan(ScanArgs {
+                max_depth: 3,
+                max_cycles: 500,
+                batch_timeout_ms: DEFAULT_BATCH_TIMEOUT_MS,
+                min_log_profit: 0.001,
+                priority_fee_lamports: 25_000,
+                base_tokens: vec![
+                    Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap(),
+                    Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap(),
+                ],
+                min_liquidity: 5_000,
+                collapse_parallel_pools: true,
+                output_format: OutputFormat::Csv,
+                output: Some("/tmp/opportunities.csv".to_string()),
+            })
+        );
+        assert_eq!(cli.config().rpc_url, "http://flag");
+        assert_eq!(cli.config().data_dir, "/tmp/pools");
+        assert_eq!(cli.config().graph_snapshot_path(), "/tmp/pools/graph.bin");
+        assert_eq!(cli.metrics_port, Some(9100));
+        assert_eq!(cli.config().account_chunk_size, 250);
+        // over the public endpoint's limit only because --rpc-url is private
+        assert!(cli.config().validate().is_ok());
+    }
+
+    #[test]
+    fn test_devnet_cluster_switches_default_endpoints() {
+        let cli = Cli::try_parse_from(["client", "scan", "--cluster", "devnet"]).unwrap();
+
+        let config = cli.config();
+        assert_eq!(config.cluster, Cluster::Devnet);
+        assert_eq!(config.rpc_url, Cluster::Devnet.rpc_url());
+        assert_eq!(config.ws_url, Cluster::Devnet.ws_url());
+
+        let cli = Cli::try_parse_from([
+            "client",
+            "scan",
+            "--cluster",
+            "devnet",
+            "--rpc-url",
+            "http://flag",
+        ])
+        .unwrap();
+        assert_eq!(cli.config().rpc_url, "http://flag");
+        assert_eq!(cli.config().ws_url, Cluster::Devnet.ws_url());
+    }
+
+    #[test]
+    fn test_parse_stream_endpoint() {
+        let cli = Cli::try_parse_from(["client", "stream", "--shred-endpoint", "http://proxy:1"])
+            .unwrap();
+
+        assert_eq!(
+            cli.command,
+            Command::Stream {
+                shred_endpoint: "http://proxy:1".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_validate() {
+        let cli = Cli::try_parse_from(["client", "validate", "--data-dir", "/tmp/pools"]).unwrap();
+
+        assert_eq!(cli.command, Command::Validate);
+        assert_eq!(cli.config().data_dir, "/tmp/pools");
+    }
+
+    #[test]
+    fn test_parse_setup_page_limit() {
+        let cli = Cli::try_parse_from(["client", "setup", "--max-pages", "1"]).unwrap();
+
+        let Command::Setup {
+            max_pages,
+            merge,
+            source,
+            token_allow_list,
+        } = cli.command
+        else {
+            panic!("expected setup");
+        };
+        let limits = bootstrap_limits(max_pages, source, &token_allow_list);
+        assert!(!merge);
+        assert_eq!(limits.token_allow_list, None);
+        assert_eq!(limits.source, PoolSource::Api);
+        assert_eq!(limits.orca.max_pages, 1);
+        assert_eq!(limits.raydium.max_pages, 1);
+        assert_eq!(
+            limits.orca.page_size,
+            BootstrapLimits::default().orca.page_size
+        );
+    }
+
+    #[test]
+    fn test_parse_setup_onchain_source() {
+        let cli = Cli::try_parse_from(["client", "setup", "--source", "onchain"]).unwrap();
+
+        let Command::Setup { source, .. } = cli.command else {
+            panic!("expected setup");
+        };
+        assert_eq!(
+            bootstrap_limits(None, source, &[]).source,
+            PoolSource::Onchain
+        );
+        assert!(Cli::try_parse_from(["client", "setup", "--source", "jupiter"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_setup_token_allow_list() {
+        let cli = Cli::try_parse_from(["client", "setup", "--token-allow-list", "mint_a,mint_b"])
+            .unwrap();
+
+        let Command::Setup {
+            max_pages,
+            source,
+            token_allow_list,
+            ..
+        } = cli.command
+        else {
+            panic!("expected setup");
+        };
+        let limits = bootstrap_limits(max_pages, source, &token_allow_list);
+        assert_eq!(
+            limits.token_allow_list,
+            Some(["mint_a".to_string(), "mint_b".to_string()].into())
+        );
+    }
+
+    #[test]
+    fn test_rejects_missing_or_unknown_subcommand() {
+        assert!(Cli::try_parse_from(["client"]).is_err());
+        assert!(Cli::try_parse_from(["client", "trade"]).is_err());
+    }
+}
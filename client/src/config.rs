@@ -0,0 +1,129 @@
+use std::ops::Range;
+
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+
+pub const DEFAULT_RPC_URL: &str = "https://api.mainnet-beta.solana.com";
+pub const DEFAULT_WS_URL: &str = "wss://api.mainnet-beta.solana.com";
+pub const DEVNET_RPC_URL: &str = "https://api.devnet.solana.com";
+pub const DEVNET_WS_URL: &str = "wss://api.devnet.solana.com";
+pub const DEFAULT_DATA_DIR: &str = "./cached-blockchain-data";
+// the most keys the public endpoints accept in one get_multiple_accounts call
+pub const MAX_PUBLIC_ACCOUNT_CHUNK_SIZE: usize = 100;
+pub const DEFAULT_ACCOUNT_CHUNK_SIZE: usize = MAX_PUBLIC_ACCOUNT_CHUNK_SIZE;
+
+// picks the public endpoints and the DEX program ids, see decoders::DexPrograms
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Cluster {
+    #[default]
+    Mainnet,
+    Devnet,
+}
+
+impl Cluster {
+    pub fn rpc_url(self) -> &'static str {
+        match self {
+            Cluster::Mainnet => DEFAULT_RPC_URL,
+            Cluster::Devnet => DEVNET_RPC_URL,
+        }
+    }
+
+    pub fn ws_url(self) -> &'static str {
+        match self {
+            Cluster::Mainnet => DEFAULT_WS_URL,
+            Cluster::Devnet => DEVNET_WS_URL,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub cluster: Cluster,
+    pub rpc_url: String,
+    pub ws_url: String,
+    // pool files and the graph snapshot, one folder per dataset
+    pub data_dir: String,
+    // keys per get_multiple_accounts call, private RPCs may allow more than the public limit
+    pub account_chunk_size: usize,
+}
+
+impl Config {
+    pub fn for_cluster(cluster: Cluster) -> Self {
+        Config {
+            cluster,
+            rpc_url: cluster.rpc_url().to_string(),
+            ws_url: cluster.ws_url().to_string(),
+            data_dir: DEFAULT_DATA_DIR.to_string(),
+            account_chunk_size: DEFAULT_ACCOUNT_CHUNK_SIZE,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.account_chunk_size == 0 {
+            bail!("The account chunk size has to be at least 1");
+        }
+        let public_rpc = [Cluster::Mainnet, Cluster::Devnet]
+            .iter()
+            .any(|cluster| cluster.rpc_url() == self.rpc_url);
+        if public_rpc && self.account_chunk_size > MAX_PUBLIC_ACCOUNT_CHUNK_SIZE {
+            bail!(
+                "{} only accepts {} accounts per request, got a chunk size of {}",
+                self.rpc_url,
+                MAX_PUBLIC_ACCOUNT_CHUNK_SIZE,
+                self.account_chunk_size
+            );
+        }
+        Ok(())
+    }
+
+    pub fn graph_snapshot_path(&self) -> String {
+        format!("{}/graph.bin", self.data_dir)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::for_cluster(Cluster::default())
+    }
+}
+
+// index ranges of at most chunk_size keys covering 0..len, the last one may be shorter
+pub fn account_chunks(len: usize, chunk_size: usize) -> Vec<Range<usize>> {
+    (0..len)
+        .step_by(chunk_size)
+        .map(|start| start..(start + chunk_size).min(len))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_chunks_batch_count() {
+        assert!(account_chunks(0, 100).is_empty());
+        assert_eq!(account_chunks(100, 100), vec![0..100]);
+        assert_eq!(account_chunks(101, 100), vec![0..100, 100..101]);
+        assert_eq!(account_chunks(250, 100).len(), 3);
+        assert_eq!(account_chunks(250, 1).len(), 250);
+        assert_eq!(account_chunks(1_000, 250).len(), 4);
+        assert_eq!(account_chunks(1_001, 250).last(), Some(&(1_000..1_001)));
+    }
+
+    #[test]
+    fn test_validate_account_chunk_size() {
+        let mut config = Config::default();
+        assert!(config.validate().is_ok());
+
+        config.account_chunk_size = 0;
+        assert!(config.validate().is_err());
+
+        // the public endpoints cap a request at 100 keys
+        config.account_chunk_size = 101;
+        assert!(config.validate().is_err());
+        assert!(Config::for_cluster(Cluster::Devnet).validate().is_ok());
+
+        config.rpc_url = "https://private.rpc".to_string();
+        assert!(config.validate().is_ok());
+    }
+}
@@ -0,0 +1,75 @@
+use solana_sdk::{account::Account, pubkey::Pubkey};
+
+use super::DecodeError;
+
+// bincode ProgramState tag, then deactivation and extension slots, authority and padding
+const LOOKUP_TABLE_META_LEN: usize = 56;
+const PROGRAM_STATE_LOOKUP_TABLE: u32 = 1;
+
+pub fn decode_lookup_table(account: &Account) -> Result<Vec<Pubkey>, DecodeError> {
+    let data = &account.data;
+    if data.len() < LOOKUP_TABLE_META_LEN
+        || !(data.len() - LOOKUP_TABLE_META_LEN).is_multiple_of(size_of::<Pubkey>())
+    {
+        return Err(DecodeError::WrongLength {
+            expected: LOOKUP_TABLE_META_LEN,
+            got: data.len(),
+        });
+    }
+    // uninitialized tables have nothing to look up
+    if u32::from_le_bytes(data[0..4].try_into()?) != PROGRAM_STATE_LOOKUP_TABLE {
+        return Err(DecodeError::BadDiscriminator);
+    }
+
+    data[LOOKUP_TABLE_META_LEN..]
+        .chunks_exact(size_of::<Pubkey>())
+        .map(|address| Ok(Pubkey::new_from_array(address.try_into()?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoders::test_account;
+
+    fn lookup_table_account(program_state: u32, addresses: &[Pubkey]) -> Account {
+        let mut data = vec![0u8; LOOKUP_TABLE_META_LEN];
+        data[0..4].copy_from_slice(&program_state.to_le_bytes());
+        data[4..12].copy_from_slice(&u64::MAX.to_le_bytes());
+        for address in addresses {
+            data.extend_from_slice(address.as_ref());
+        }
+
+        test_account(Pubkey::new_unique(), data)
+    }
+
+    #[test]
+    fn test_decodes_addresses() {
+        let addresses = [Pubkey::new_unique(), Pubkey::new_unique()];
+
+        let decoded = decode_lookup_table(&lookup_table_account(1, &addresses)).unwrap();
+
+        assert_eq!(decoded, addresses);
+        assert!(
+            decode_lookup_table(&lookup_table_account(1, &[]))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_rejects_uninitialized_or_misaligned_table() {
+        let mut account = lookup_table_account(0, &[Pubkey::new_unique()]);
+        assert!(matches!(
+            decode_lookup_table(&account),
+            Err(DecodeError::BadDiscriminator)
+        ));
+
+        account.data[0] = 1;
+        account.data.pop();
+        assert!(matches!(
+            decode_lookup_table(&account),
+            Err(DecodeError::WrongLength { .. })
+        ));
+    }
+}
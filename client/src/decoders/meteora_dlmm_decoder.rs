@@ -0,0 +1,98 @@
+use solana_sdk::account::Account;
+use tracing::error;
+
+use super::DecodeError;
+use crate::bootstrap::pool_schema::PoolUpdate;
+
+pub const DISCRIMINATOR: [u8; 8] = [33, 11, 49, 98, 181, 101, 177, 13];
+
+const LB_PAIR_LEN: usize = 904;
+
+// DLMM has no ticks or sqrt price, the active bin trades at (1 + bin_step / 10_000)^active_id.
+// new_sqrt_price is the square root of that price in Q64.64, new_current_tick_index is the
+// same price expressed in 1.0001 ticks, and new_liquidity is 0 because liquidity lives in
+// the bin arrays, so the graph can price the pool but won't estimate amounts through it
+pub fn decode_meteora_dlmm_account(account: &Account) -> Result<PoolUpdate, DecodeError> {
+    if account.data.len() != LB_PAIR_LEN {
+        return Err(DecodeError::WrongLength {
+            expected: LB_PAIR_LEN,
+            got: account.data.len(),
+        });
+    }
+
+    let data = &account.data;
+    let discriminator: [u8; 8] = data[0..8].try_into()?;
+
+    if discriminator != DISCRIMINATOR {
+        error!("Discriminator: {:?}", discriminator);
+        return Err(DecodeError::BadDiscriminator);
+    }
+    // static and variable fee parameters take 8..72, bump and seeds 72..76
+
+    let active_id: i32 = i32::from_le_bytes(data[76..80].try_into()?);
+    let bin_step: u16 = u16::from_le_bytes(data[80..82].try_into()?);
+    // let token_x_mint = Pubkey::new_from_array(data[88..120].try_into()?);
+    // let token_y_mint = Pubkey::new_from_array(data[120..152].try_into()?);
+    // let reserve_x = Pubkey::new_from_array(data[152..184].try_into()?);
+    // let reserve_y = Pubkey::new_from_array(data[184..216].try_into()?);
+
+    let log_price = active_id as f64 * (1.0 + bin_step as f64 / 10_000.0).ln();
+    let sqrt_price = (log_price / 2.0).exp();
+
+    Ok(PoolUpdate {
+        new_liquidity: 0,
+        new_sqrt_price: (sqrt_price * 2f64.powi(64)) as u128,
+        new_current_tick_index: (log_price / 1.0001f64.ln()).floor() as i32,
+        slot: 0,
+        fee_rate: None,
+        tick_spacing: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::decoders::test_account;
+
+    fn lb_pair_account(active_id: i32, bin_step: u16) -> Account {
+        let mut data = vec![0u8; LB_PAIR_LEN];
+        data[0..8].copy_from_slice(&DISCRIMINATOR);
+        data[76..80].copy_from_slice(&active_id.to_le_bytes());
+        data[80..82].copy_from_slice(&bin_step.to_le_bytes());
+
+        test_account(Pubkey::new_unique(), data)
+    }
+
+    #[test]
+    fn test_decode_dlmm_active_bin() {
+        // a bin step of 1 basis point lines bins up with 1.0001 ticks
+        let update = decode_meteora_dlmm_account(&lb_pair_account(-200, 1)).unwrap();
+
+        let price = 1.0001f64.powi(-200);
+        let decoded_price = (update.new_sqrt_price as f64 / 2f64.powi(64)).powi(2);
+        assert!((decoded_price / price - 1.0).abs() < 1e-12);
+        assert!((-201..=-200).contains(&update.new_current_tick_index));
+        assert_eq!(update.new_liquidity, 0);
+    }
+
+    #[test]
+    fn test_decode_dlmm_zero_bin_is_price_one() {
+        let update = decode_meteora_dlmm_account(&lb_pair_account(0, 25)).unwrap();
+
+        assert_eq!(update.new_sqrt_price, 1u128 << 64);
+        assert_eq!(update.new_current_tick_index, 0);
+    }
+
+    #[test]
+    fn test_rejects_wrong_discriminator() {
+        let mut account = lb_pair_account(0, 25);
+        account.data[0] = 0;
+
+        assert!(matches!(
+            decode_meteora_dlmm_account(&account),
+            Err(DecodeError::BadDiscriminator)
+        ));
+    }
+}
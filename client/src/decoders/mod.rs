@@ -1,35 +1,460 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    array::TryFromSliceError,
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+};
 
-use anyhow::anyhow;
 use solana_sdk::{account::Account, pubkey::Pubkey};
-use tracing::info;
 
-use crate::bootstrap::pool_schema::PoolUpdate;
+use crate::{bootstrap::pool_schema::PoolUpdate, config::Cluster};
+mod lookup_table_decoder;
+mod meteora_dlmm_decoder;
 mod orca_decoder;
+mod raydium_cpmm_decoder;
 mod raydium_decoder;
+mod token_account_decoder;
 
-const RAYDIUM_OWNER: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
-const ORCA_OWNER: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
-type DecoderFn = fn(&Account) -> anyhow::Result<PoolUpdate>;
+pub use lookup_table_decoder::decode_lookup_table;
+pub use orca_decoder::{
+    DISCRIMINATOR as WHIRLPOOL_DISCRIMINATOR, InitializedTick, TICK_ARRAY_SIZE, TickArray,
+    decode_orca_static, decode_orca_tick_array,
+};
+pub use raydium_cpmm_decoder::{CpmmPoolState, decode_raydium_cpmm_account};
+pub use raydium_decoder::{
+    DISCRIMINATOR as POOL_STATE_DISCRIMINATOR, decode_raydium_static,
+    decode_raydium_trade_fee_rate, decode_raydium_vaults,
+};
+pub use token_account_decoder::{
+    TokenAccount, TokenProgram, decode_mint_decimals, decode_mint_transfer_fee_bps,
+    decode_token_account, decode_token_amount,
+};
+
+pub const RAYDIUM_OWNER: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+pub const ORCA_OWNER: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+const METEORA_DLMM_OWNER: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+// not in DECODERS, a CPMM pool needs its two vault balances before it can be priced
+pub const RAYDIUM_CPMM_OWNER: &str = "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C";
+// Raydium deploys separate programs to devnet, Orca and Meteora reuse their mainnet ids
+const RAYDIUM_DEVNET_OWNER: &str = "devi51mZmdwUJGU9hjN27vEz64Gps7uUefqxg27EAtH";
+const RAYDIUM_CPMM_DEVNET_OWNER: &str = "CPMDWBwJDtYax9qW7AyRuVC19Cc4L4Vcy4n2BHAbHkCW";
+pub type DecoderFn = fn(&Account) -> Result<PoolUpdate, DecodeError>;
+
+#[derive(Debug)]
+pub enum DecodeError {
+    // the owner isn't a DEX we decode, callers are expected to skip these
+    UnknownDex,
+    // a vault or mint that isn't owned by either token program
+    UnknownOwner,
+    // expected is the exact size, or the minimum for decoders that only read a prefix
+    WrongLength { expected: usize, got: usize },
+    BadDiscriminator,
+    Parse(TryFromSliceError),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownDex => write!(f, "Unknown DEX"),
+            DecodeError::UnknownOwner => write!(f, "Not owned by a token program"),
+            DecodeError::WrongLength { expected, got } => {
+                write!(
+                    f,
+                    "Account data has wrong length: expected {}, got {}",
+                    expected, got
+                )
+            }
+            DecodeError::BadDiscriminator => write!(f, "Wrong Discriminator Found"),
+            DecodeError::Parse(e) => write!(f, "Failed to parse account data: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// fields fixed when the pool is created, enough to rebuild a PoolInfo without a DEX API
+#[derive(Debug, PartialEq, Eq)]
+pub struct PoolStatic {
+    pub config: Pubkey,
+    pub tick_spacing: u16,
+    // Raydium keeps the fee in the AmmConfig account instead of the pool
+    pub fee_rate: Option<u32>,
+    pub token_mint_a: Pubkey,
+    pub token_mint_b: Pubkey,
+    pub token_vault_a: Pubkey,
+    pub token_vault_b: Pubkey,
+}
+
+impl From<TryFromSliceError> for DecodeError {
+    fn from(e: TryFromSliceError) -> Self {
+        DecodeError::Parse(e)
+    }
+}
 
 lazy_static::lazy_static! {
     static ref RAYDIUM_PUBKEY: Pubkey = Pubkey::from_str(RAYDIUM_OWNER).unwrap();
     static ref ORCA_PUBKEY: Pubkey = Pubkey::from_str(ORCA_OWNER).unwrap();
+    static ref METEORA_DLMM_PUBKEY: Pubkey = Pubkey::from_str(METEORA_DLMM_OWNER).unwrap();
+
+    static ref DECODERS: DecoderRegistry = DecoderRegistry::for_programs(&DexPrograms::for_cluster(Cluster::Mainnet));
+    static ref DEVNET_DECODERS: DecoderRegistry = DecoderRegistry::for_programs(&DexPrograms::for_cluster(Cluster::Devnet));
+}
+
+// the DEX programs deployed on a cluster, None where the DEX has no deployment there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DexPrograms {
+    pub orca: Option<Pubkey>,
+    pub raydium: Option<Pubkey>,
+    pub raydium_cpmm: Option<Pubkey>,
+    pub meteora_dlmm: Option<Pubkey>,
+}
+
+impl DexPrograms {
+    pub fn for_cluster(cluster: Cluster) -> Self {
+        let parse = |program: &str| Some(Pubkey::from_str(program).unwrap());
+        match cluster {
+            Cluster::Mainnet => DexPrograms {
+                orca: parse(ORCA_OWNER),
+                raydium: parse(RAYDIUM_OWNER),
+                raydium_cpmm: parse(RAYDIUM_CPMM_OWNER),
+                meteora_dlmm: parse(METEORA_DLMM_OWNER),
+            },
+            Cluster::Devnet => DexPrograms {
+                orca: parse(ORCA_OWNER),
+                raydium: parse(RAYDIUM_DEVNET_OWNER),
+                raydium_cpmm: parse(RAYDIUM_CPMM_DEVNET_OWNER),
+                meteora_dlmm: parse(METEORA_DLMM_OWNER),
+            },
+        }
+    }
+
+    pub fn available(&self) -> Vec<Pubkey> {
+        [
+            self.orca,
+            self.raydium,
+            self.raydium_cpmm,
+            self.meteora_dlmm,
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+// one program can own several account layouts, told apart by the anchor discriminator
+#[derive(Default)]
+pub struct DecoderRegistry {
+    decoders: HashMap<(Pubkey, [u8; 8]), DecoderFn>,
+    owners: HashSet<Pubkey>,
+}
+
+impl DecoderRegistry {
+    pub fn for_programs(programs: &DexPrograms) -> Self {
+        let mut registry = DecoderRegistry::default();
+        if let Some(raydium) = programs.raydium {
+            registry.register(
+                raydium,
+                raydium_decoder::DISCRIMINATOR,
+                raydium_decoder::decode_raydium_account,
+            );
+        }
+        if let Some(orca) = programs.orca {
+            registry.register(
+                orca,
+                orca_decoder::DISCRIMINATOR,
+                orca_decoder::decode_orca_account,
+            );
+        }
+        if let Some(meteora_dlmm) = programs.meteora_dlmm {
+            registry.register(
+                meteora_dlmm,
+                meteora_dlmm_decoder::DISCRIMINATOR,
+                meteora_dlmm_decoder::decode_meteora_dlmm_account,
+            );
+        }
+        registry
+    }
+
+    pub fn register(&mut self, owner: Pubkey, discriminator: [u8; 8], decoder: DecoderFn) {
+        self.decoders.insert((owner, discriminator), decoder);
+        self.owners.insert(owner);
+    }
+
+    pub fn decode(&self, account: &Account) -> Result<PoolUpdate, DecodeError> {
+        if !self.owners.contains(&account.owner) {
+            return Err(DecodeError::UnknownDex);
+        }
+
+        let discriminator: [u8; 8] = account
+            .data
+            .get(0..8)
+            .ok_or(DecodeError::WrongLength {
+                expected: 8,
+                got: account.data.len(),
+            })?
+            .try_into()?;
+
+        let decoder = self
+            .decoders
+            .get(&(account.owner, discriminator))
+            .ok_or(DecodeError::BadDiscriminator)?;
+        decoder(account)
+    }
+}
+
+pub fn decode_account(account: &Account) -> Result<PoolUpdate, DecodeError> {
+    DECODERS.decode(account)
+}
+
+pub fn decoders_for(cluster: Cluster) -> &'static DecoderRegistry {
+    match cluster {
+        Cluster::Mainnet => &DECODERS,
+        Cluster::Devnet => &DEVNET_DECODERS,
+    }
+}
+
+// the account the decoder tests lay their buffers out in
+#[cfg(test)]
+pub(crate) fn test_account(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: 0,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_owner() {
+        let result = decode_account(&test_account(Pubkey::new_unique(), vec![0; 653]));
+
+        assert!(matches!(result, Err(DecodeError::UnknownDex)));
+    }
+
+    #[test]
+    fn test_wrong_length() {
+        let mut data = orca_decoder::DISCRIMINATOR.to_vec();
+        data.resize(84, 0);
+        let result = decode_account(&test_account(*ORCA_PUBKEY, data));
+
+        assert!(matches!(
+            result,
+            Err(DecodeError::WrongLength {
+                expected: 85,
+                got: 84
+            })
+        ));
+    }
+
+    #[test]
+    fn test_bad_discriminator() {
+        let result = decode_account(&test_account(*RAYDIUM_PUBKEY, vec![0; 1544]));
+
+        assert!(matches!(result, Err(DecodeError::BadDiscriminator)));
+    }
+
+    #[test]
+    fn test_registry_dispatches_on_discriminator() {
+        fn decode_first(_: &Account) -> Result<PoolUpdate, DecodeError> {
+            Ok(PoolUpdate {
+                new_liquidity: 1,
+                new_sqrt_price: 0,
+                new_current_tick_index: 0,
+                slot: 0,
+                fee_rate: None,
+                tick_spacing: None,
+            })
+        }
+        fn decode_second(_: &Account) -> Result<PoolUpdate, DecodeError> {
+            Ok(PoolUpdate {
+                new_liquidity: 2,
+                new_sqrt_price: 0,
+                new_current_tick_index: 0,
+                slot: 0,
+                fee_rate: None,
+                tick_spacing: None,
+            })
+        }
+        let owner = Pubkey::new_unique();
+        let mut registry = DecoderRegistry::default();
+        registry.register(owner, [1; 8], decode_first);
+        registry.register(owner, [2; 8], decode_second);
+
+        let first = registry.decode(&test_account(owner, vec![1; 16])).unwrap();
+        let second = registry.decode(&test_account(owner, vec![2; 16])).unwrap();
+
+        assert_eq!(first.new_liquidity, 1);
+        assert_eq!(second.new_liquidity, 2);
+        assert!(matches!(
+            registry.decode(&test_account(owner, vec![3; 16])),
+            Err(DecodeError::BadDiscriminator)
+        ));
+        assert!(matches!(
+            registry.decode(&test_account(owner, vec![1; 4])),
+            Err(DecodeError::WrongLength {
+                expected: 8,
+                got: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn test_each_owner_dispatches_to_its_decoder() {
+        let dexes: [(Pubkey, [u8; 8], usize, DecoderFn); 3] = [
+            (
+                *ORCA_PUBKEY,
+                orca_decoder::DISCRIMINATOR,
+                653,
+                orca_decoder::decode_orca_account,
+            ),
+            (
+                *RAYDIUM_PUBKEY,
+                raydium_decoder::DISCRIMINATOR,
+                1544,
+                raydium_decoder::decode_raydium_account,
+            ),
+            (
+                *METEORA_DLMM_PUBKEY,
+                meteora_dlmm_decoder::DISCRIMINATOR,
+                904,
+                meteora_dlmm_decoder::decode_meteora_dlmm_account,
+            ),
+        ];
+
+        for (owner, discriminator, len, decoder) in dexes {
+            // distinct bytes everywhere, so decoders reading other offsets disagree
+            let mut data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            data[0..8].copy_from_slice(&discriminator);
+            let account = test_account(owner, data);
+
+            let dispatched = decode_account(&account).unwrap();
+
+            assert_eq!(
+                format!("{:?}", dispatched),
+                format!("{:?}", decoder(&account).unwrap())
+            );
+            for (other_owner, ..) in dexes.iter().filter(|dex| dex.0 != owner) {
+                let mut foreign = account.clone();
+                foreign.owner = *other_owner;
+                assert!(matches!(
+                    decode_account(&foreign),
+                    Err(DecodeError::BadDiscriminator)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_program_ids_per_cluster() {
+        let mainnet = DexPrograms::for_cluster(Cluster::Mainnet);
+        let devnet = DexPrograms::for_cluster(Cluster::Devnet);
+
+        assert_eq!(mainnet.orca, Some(*ORCA_PUBKEY));
+        assert_eq!(mainnet.raydium, Some(*RAYDIUM_PUBKEY));
+        assert_eq!(mainnet.available().len(), 4);
+        assert_eq!(devnet.orca, mainnet.orca);
+        assert_ne!(devnet.raydium, mainnet.raydium);
+        assert_ne!(devnet.raydium_cpmm, mainnet.raydium_cpmm);
+
+        let without_raydium = DexPrograms {
+            raydium: None,
+            ..devnet
+        };
+        assert_eq!(without_raydium.available().len(), 3);
+        assert!(
+            !without_raydium
+                .available()
+                .contains(&devnet.raydium.unwrap())
+        );
+    }
+
+    #[test]
+    fn test_devnet_registry_only_knows_devnet_owners() {
+        let mut data = raydium_decoder::DISCRIMINATOR.to_vec();
+        data.resize(1544, 0);
+        let devnet_raydium = DexPrograms::for_cluster(Cluster::Devnet).raydium.unwrap();
+
+        assert!(
+            decoders_for(Cluster::Devnet)
+                .decode(&test_account(devnet_raydium, data.clone()))
+                .is_ok()
+        );
+        assert!(matches!(
+            decoders_for(Cluster::Devnet).decode(&test_account(*RAYDIUM_PUBKEY, data.clone())),
+            Err(DecodeError::UnknownDex)
+        ));
+        assert!(matches!(
+            decoders_for(Cluster::Mainnet).decode(&test_account(devnet_raydium, data)),
+            Err(DecodeError::UnknownDex)
+        ));
+    }
+
+    // the same decoders the fuzz target in client/fuzz drives, over every length up to a pool
+    // account and around a tick array, behind each discriminator so the slicing is reached
+    #[test]
+    fn test_decoders_never_panic_on_truncated_or_garbage_data() {
+        let owners = [
+            *ORCA_PUBKEY,
+            *RAYDIUM_PUBKEY,
+            *METEORA_DLMM_PUBKEY,
+            TokenProgram::Token.id(),
+            TokenProgram::Token2022.id(),
+        ];
+        let prefixes: [&[u8]; 6] = [
+            &[],
+            &orca_decoder::DISCRIMINATOR,
+            &orca_decoder::TICK_ARRAY_DISCRIMINATOR,
+            &raydium_decoder::DISCRIMINATOR,
+            &meteora_dlmm_decoder::DISCRIMINATOR,
+            &[1, 0, 0, 0],
+        ];
+        // xorshift, so every run sees the same bytes
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next_byte = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        };
+
+        for prefix in prefixes {
+            for len in (0..=1_600).chain(9_980..=9_996) {
+                let mut data: Vec<u8> = (0..len).map(|_| next_byte()).collect();
+                let prefix_len = prefix.len().min(len);
+                data[..prefix_len].copy_from_slice(&prefix[..prefix_len]);
+                // token-2022 account type, so the extension list gets parsed
+                if len > 165 {
+                    data[165] = (len % 3) as u8;
+                }
+                let account = test_account(owners[len % owners.len()], data);
+
+                let _ = decode_account(&account);
+                let _ = decode_orca_static(&account);
+                let _ = decode_orca_tick_array(&account, len as u16);
+                let _ = decode_raydium_static(&account);
+                let _ = decode_raydium_vaults(&account);
+                let _ = decode_raydium_trade_fee_rate(&account);
+                let _ = decode_raydium_cpmm_account(&account);
+                let _ = decode_token_account(&account);
+                let _ = decode_mint_decimals(&account);
+                let _ = decode_mint_transfer_fee_bps(&account);
+                let _ = decode_lookup_table(&account);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_error_conversion() {
+        let slice_error = <[u8; 8]>::try_from(&[0u8; 3][..]).unwrap_err();
 
-    static ref DECODERS: HashMap<Pubkey, DecoderFn> = {
-        let mut m = HashMap::new();
-        m.insert(*RAYDIUM_PUBKEY, raydium_decoder::decode_raydium_account as DecoderFn);
-        m.insert(*ORCA_PUBKEY, orca_decoder::decode_orca_account as DecoderFn);
-        m
-    };
-}
-
-pub fn decode_account(account: &Account) -> anyhow::Result<PoolUpdate> {
-    if let Some(decoder) = DECODERS.get(&account.owner) {
-        let result: PoolUpdate = decoder(account)?;
-        Ok(result)
-    } else {
-        info!("Unknown DEX, skipping decoding");
-        Err(anyhow!("Unknown DEX"))
+        assert!(matches!(
+            DecodeError::from(slice_error),
+            DecodeError::Parse(_)
+        ));
     }
 }
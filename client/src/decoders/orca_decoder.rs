@@ -1,26 +1,79 @@
-use anyhow::{Result, anyhow};
-use solana_sdk::account::Account;
+use solana_sdk::{account::Account, pubkey::Pubkey};
 use tracing::error;
 
+use super::{DecodeError, PoolStatic};
 use crate::bootstrap::pool_schema::PoolUpdate;
 
-pub fn decode_orca_account(account: &Account) -> Result<PoolUpdate> {
-    if account.data.len() != 653 {
-        return Err(anyhow!("Account data has wrong length"));
+pub const DISCRIMINATOR: [u8; 8] = [63, 149, 209, 12, 225, 128, 99, 9];
+pub const TICK_ARRAY_DISCRIMINATOR: [u8; 8] = [69, 97, 189, 190, 110, 7, 66, 187];
+pub const TICK_ARRAY_SIZE: i32 = 88;
+
+// whirlpools are 653 bytes today, but only the prefix up to current_tick_index is read
+const MIN_WHIRLPOOL_LEN: usize = 85;
+// the static fields end with token_vault_b
+const MIN_WHIRLPOOL_STATIC_LEN: usize = 245;
+// initialized, liquidity_net, liquidity_gross, two fee growths and three reward growths
+const TICK_LEN: usize = 113;
+const TICKS_OFFSET: usize = 12;
+const TICK_ARRAY_WHIRLPOOL_OFFSET: usize = TICKS_OFFSET + TICK_ARRAY_SIZE as usize * TICK_LEN;
+const TICK_ARRAY_LEN: usize = TICK_ARRAY_WHIRLPOOL_OFFSET + 32;
+
+// liquidity_net is added when the price moves up through the tick and subtracted moving down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InitializedTick {
+    pub tick_index: i32,
+    pub liquidity_net: i128,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TickArray {
+    pub whirlpool: Pubkey,
+    pub start_tick_index: i32,
+    // only the initialized ticks, in ascending order
+    pub ticks: Vec<InitializedTick>,
+}
+
+fn check_whirlpool(data: &[u8], min_len: usize) -> Result<(), DecodeError> {
+    if data.len() < min_len {
+        return Err(DecodeError::WrongLength {
+            expected: min_len,
+            got: data.len(),
+        });
     }
 
-    let data = &account.data;
     let discriminator: [u8; 8] = data[0..8].try_into()?;
-
-    if discriminator != [63, 149, 209, 12, 225, 128, 99, 9] {
+    if discriminator != DISCRIMINATOR {
         error!("Discriminator: {:?}", discriminator);
-        return Err(anyhow!("Wrong Discriminator Found"));
+        return Err(DecodeError::BadDiscriminator);
     }
+    Ok(())
+}
+
+pub fn decode_orca_static(account: &Account) -> Result<PoolStatic, DecodeError> {
+    let data = &account.data;
+    check_whirlpool(data, MIN_WHIRLPOOL_STATIC_LEN)?;
+
+    // protocol fees owed sit between current_tick_index and token_mint_a
+    Ok(PoolStatic {
+        config: Pubkey::new_from_array(data[8..40].try_into()?),
+        tick_spacing: u16::from_le_bytes(data[41..43].try_into()?),
+        fee_rate: Some(u16::from_le_bytes(data[45..47].try_into()?) as u32),
+        token_mint_a: Pubkey::new_from_array(data[101..133].try_into()?),
+        token_vault_a: Pubkey::new_from_array(data[133..165].try_into()?),
+        // fee_growth_global_a 165..181
+        token_mint_b: Pubkey::new_from_array(data[181..213].try_into()?),
+        token_vault_b: Pubkey::new_from_array(data[213..245].try_into()?),
+    })
+}
+
+pub fn decode_orca_account(account: &Account) -> Result<PoolUpdate, DecodeError> {
+    let data = &account.data;
+    check_whirlpool(data, MIN_WHIRLPOOL_LEN)?;
     // let config = Pubkey::new_from_array(data[8..40].try_into()?);
     // let bump: u8 = data[40];
-    // let tick_spacing: [u8; 2] = [data[41], data[42]];
+    let tick_spacing = u16::from_le_bytes(data[41..43].try_into()?);
     //let fee_tier_index: [u8; 2] = [data[43], data[44]];
-    //let fee_rate : [u8; 2] = [data[45], data[46]];
+    let fee_rate = u16::from_le_bytes(data[45..47].try_into()?);
 
     //possible to do with unsafe in the future
     let liquidity: u128 = u128::from_le_bytes(data[49..65].try_into()?);
@@ -31,5 +84,202 @@ pub fn decode_orca_account(account: &Account) -> Result<PoolUpdate> {
         new_liquidity: liquidity,
         new_sqrt_price: sqrt_price,
         new_current_tick_index: current_tick_index,
+        slot: 0,
+        fee_rate: Some(fee_rate as u32),
+        tick_spacing: Some(tick_spacing),
+    })
+}
+
+// tick_spacing isn't stored in the array, so the tick indexes are filled in from the pool's
+pub fn decode_orca_tick_array(
+    account: &Account,
+    tick_spacing: u16,
+) -> Result<TickArray, DecodeError> {
+    let data = &account.data;
+    if data.len() != TICK_ARRAY_LEN {
+        return Err(DecodeError::WrongLength {
+            expected: TICK_ARRAY_LEN,
+            got: data.len(),
+        });
+    }
+    if data[0..8] != TICK_ARRAY_DISCRIMINATOR {
+        return Err(DecodeError::BadDiscriminator);
+    }
+
+    let start_tick_index = i32::from_le_bytes(data[8..12].try_into()?);
+    let mut ticks = Vec::new();
+    for (offset, tick) in data[TICKS_OFFSET..TICK_ARRAY_WHIRLPOOL_OFFSET]
+        .chunks_exact(TICK_LEN)
+        .enumerate()
+    {
+        if tick[0] == 0 {
+            continue;
+        }
+        ticks.push(InitializedTick {
+            tick_index: start_tick_index + offset as i32 * tick_spacing as i32,
+            liquidity_net: i128::from_le_bytes(tick[1..17].try_into()?),
+        });
+    }
+
+    Ok(TickArray {
+        whirlpool: Pubkey::new_from_array(data[TICK_ARRAY_WHIRLPOOL_OFFSET..].try_into()?),
+        start_tick_index,
+        ticks,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoders::test_account;
+
+    fn whirlpool_account(len: usize) -> Account {
+        let mut data = vec![0u8; len];
+        data[0..8].copy_from_slice(&DISCRIMINATOR);
+        data[49..65].copy_from_slice(&7u128.to_le_bytes());
+        data[65..81].copy_from_slice(&(1u128 << 64).to_le_bytes());
+        data[81..85].copy_from_slice(&(-5i32).to_le_bytes());
+
+        test_account(Pubkey::new_unique(), data)
+    }
+
+    #[test]
+    fn test_decodes_longer_account() {
+        for len in [MIN_WHIRLPOOL_LEN, 653, 1024] {
+            let update = decode_orca_account(&whirlpool_account(len)).unwrap();
+
+            assert_eq!(update.new_liquidity, 7);
+            assert_eq!(update.new_sqrt_price, 1u128 << 64);
+            assert_eq!(update.new_current_tick_index, -5);
+        }
+    }
+
+    #[test]
+    fn test_negative_tick_keeps_its_sign() {
+        // -443_636 is the lowest tick a whirlpool allows
+        for tick in [-1i32, -256, -65_536, -443_636] {
+            let mut account = whirlpool_account(653);
+            // set bytes around the tick, a shifted offset would read them into it
+            account.data[80] = 0xff;
+            account.data[85] = 0xff;
+            account.data[81..85].copy_from_slice(&tick.to_le_bytes());
+
+            let update = decode_orca_account(&account).unwrap();
+
+            assert_eq!(update.new_current_tick_index, tick);
+        }
+    }
+
+    #[test]
+    fn test_decodes_fee_rate_and_tick_spacing() {
+        let mut account = whirlpool_account(653);
+        account.data[41..43].copy_from_slice(&8u16.to_le_bytes());
+        // the fee tier index sits between the two and must not leak into either
+        account.data[43..45].copy_from_slice(&u16::MAX.to_le_bytes());
+        account.data[45..47].copy_from_slice(&500u16.to_le_bytes());
+
+        let update = decode_orca_account(&account).unwrap();
+
+        assert_eq!(update.tick_spacing, Some(8));
+        assert_eq!(update.fee_rate, Some(500));
+    }
+
+    #[test]
+    fn test_rejects_truncated_account() {
+        let mut account = whirlpool_account(653);
+        account.data.truncate(MIN_WHIRLPOOL_LEN - 1);
+
+        assert!(matches!(
+            decode_orca_account(&account),
+            Err(DecodeError::WrongLength { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decodes_static_fields() {
+        let keys: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+        let mut account = whirlpool_account(653);
+        account.data[8..40].copy_from_slice(keys[0].as_ref());
+        account.data[41..43].copy_from_slice(&64u16.to_le_bytes());
+        account.data[45..47].copy_from_slice(&3_000u16.to_le_bytes());
+        account.data[101..133].copy_from_slice(keys[1].as_ref());
+        account.data[133..165].copy_from_slice(keys[2].as_ref());
+        account.data[181..213].copy_from_slice(keys[3].as_ref());
+        account.data[213..245].copy_from_slice(keys[4].as_ref());
+
+        assert_eq!(
+            decode_orca_static(&account).unwrap(),
+            PoolStatic {
+                config: keys[0],
+                tick_spacing: 64,
+                fee_rate: Some(3_000),
+                token_mint_a: keys[1],
+                token_vault_a: keys[2],
+                token_mint_b: keys[3],
+                token_vault_b: keys[4],
+            }
+        );
+    }
+
+    #[test]
+    fn test_static_fields_need_full_prefix() {
+        assert!(matches!(
+            decode_orca_static(&whirlpool_account(MIN_WHIRLPOOL_LEN)),
+            Err(DecodeError::WrongLength {
+                expected: MIN_WHIRLPOOL_STATIC_LEN,
+                got: MIN_WHIRLPOOL_LEN
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decodes_initialized_ticks() {
+        let whirlpool = Pubkey::new_unique();
+        let mut data = vec![0u8; TICK_ARRAY_LEN];
+        data[0..8].copy_from_slice(&TICK_ARRAY_DISCRIMINATOR);
+        data[8..12].copy_from_slice(&(-5_632i32).to_le_bytes());
+        for (slot, liquidity_net) in [(3usize, 500i128), (87, -500)] {
+            let tick = TICKS_OFFSET + slot * TICK_LEN;
+            data[tick] = 1;
+            data[tick + 1..tick + 17].copy_from_slice(&liquidity_net.to_le_bytes());
+        }
+        data[TICK_ARRAY_WHIRLPOOL_OFFSET..].copy_from_slice(whirlpool.as_ref());
+        let account = test_account(Pubkey::new_unique(), data);
+
+        let tick_array = decode_orca_tick_array(&account, 64).unwrap();
+
+        assert_eq!(TICK_ARRAY_LEN, 9_988);
+        assert_eq!(
+            tick_array,
+            TickArray {
+                whirlpool,
+                start_tick_index: -5_632,
+                ticks: vec![
+                    InitializedTick {
+                        tick_index: -5_440,
+                        liquidity_net: 500
+                    },
+                    InitializedTick {
+                        tick_index: -64,
+                        liquidity_net: -500
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_tick_array_rejects_whirlpool_account() {
+        let mut account = whirlpool_account(TICK_ARRAY_LEN);
+
+        assert!(matches!(
+            decode_orca_tick_array(&account, 64),
+            Err(DecodeError::BadDiscriminator)
+        ));
+        account.data.truncate(653);
+        assert!(matches!(
+            decode_orca_tick_array(&account, 64),
+            Err(DecodeError::WrongLength { .. })
+        ));
+    }
+}
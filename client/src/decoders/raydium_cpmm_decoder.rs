@@ -0,0 +1,147 @@
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use tracing::error;
+
+use super::DecodeError;
+use crate::bootstrap::pool_schema::PoolUpdate;
+
+const CPMM_POOL_STATE_LEN: usize = 637;
+
+// the pool account only tracks fees owed to the protocol and fund,
+// the reserves are whatever the vaults hold on top of that
+#[derive(Debug, PartialEq, Eq)]
+pub struct CpmmPoolState {
+    pub token_0_vault: Pubkey,
+    pub token_1_vault: Pubkey,
+    pub status: u8,
+    pub protocol_fees_token_0: u64,
+    pub protocol_fees_token_1: u64,
+    pub fund_fees_token_0: u64,
+    pub fund_fees_token_1: u64,
+}
+
+impl CpmmPoolState {
+    pub fn reserves(&self, vault_0_balance: u64, vault_1_balance: u64) -> (u64, u64) {
+        (
+            vault_0_balance
+                .saturating_sub(self.protocol_fees_token_0)
+                .saturating_sub(self.fund_fees_token_0),
+            vault_1_balance
+                .saturating_sub(self.protocol_fees_token_1)
+                .saturating_sub(self.fund_fees_token_1),
+        )
+    }
+
+    // x * y = k behaves like a single full range CLMM position with L = sqrt(x * y) at sqrt(y / x)
+    pub fn pool_update(&self, vault_0_balance: u64, vault_1_balance: u64) -> Option<PoolUpdate> {
+        let (reserve_0, reserve_1) = self.reserves(vault_0_balance, vault_1_balance);
+        if reserve_0 == 0 || reserve_1 == 0 {
+            return None;
+        }
+
+        let sqrt_price = (reserve_1 as f64 / reserve_0 as f64).sqrt();
+        Some(PoolUpdate {
+            new_liquidity: ((reserve_0 as f64) * (reserve_1 as f64)).sqrt() as u128,
+            new_sqrt_price: (sqrt_price * 2f64.powi(64)) as u128,
+            new_current_tick_index: (2.0 * sqrt_price.ln() / 1.0001f64.ln()).floor() as i32,
+            slot: 0,
+            fee_rate: None,
+            tick_spacing: None,
+        })
+    }
+}
+
+pub fn decode_raydium_cpmm_account(account: &Account) -> Result<CpmmPoolState, DecodeError> {
+    if account.data.len() != CPMM_POOL_STATE_LEN {
+        return Err(DecodeError::WrongLength {
+            expected: CPMM_POOL_STATE_LEN,
+            got: account.data.len(),
+        });
+    }
+
+    let data = &account.data;
+    let discriminator: [u8; 8] = data[0..8].try_into()?;
+
+    // same account name as the CLMM PoolState, only the owner tells them apart
+    if discriminator != [247, 237, 227, 245, 215, 195, 222, 70] {
+        error!("Discriminator: {:?}", discriminator);
+        return Err(DecodeError::BadDiscriminator);
+    }
+
+    // let amm_config = Pubkey::new_from_array(data[8..40].try_into()?);
+    // let pool_creator = Pubkey::new_from_array(data[40..72].try_into()?);
+    let token_0_vault = Pubkey::new_from_array(data[72..104].try_into()?);
+    let token_1_vault = Pubkey::new_from_array(data[104..136].try_into()?);
+    // lp_mint, token mints, token programs and observation key sit between 136..328
+    // let auth_bump: u8 = data[328];
+    let status: u8 = data[329];
+    // let lp_supply = u64::from_le_bytes(data[333..341].try_into()?);
+
+    Ok(CpmmPoolState {
+        token_0_vault,
+        token_1_vault,
+        status,
+        protocol_fees_token_0: u64::from_le_bytes(data[341..349].try_into()?),
+        protocol_fees_token_1: u64::from_le_bytes(data[349..357].try_into()?),
+        fund_fees_token_0: u64::from_le_bytes(data[357..365].try_into()?),
+        fund_fees_token_1: u64::from_le_bytes(data[365..373].try_into()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoders::test_account;
+
+    // laid out as raydium-cp-swap's PoolState
+    fn cpmm_account(vault_0: Pubkey, vault_1: Pubkey, fees: [u64; 4]) -> Account {
+        let mut data = vec![0u8; CPMM_POOL_STATE_LEN];
+        data[0..8].copy_from_slice(&[247, 237, 227, 245, 215, 195, 222, 70]);
+        data[72..104].copy_from_slice(vault_0.as_ref());
+        data[104..136].copy_from_slice(vault_1.as_ref());
+        for (i, fee) in fees.iter().enumerate() {
+            let start = 341 + i * 8;
+            data[start..start + 8].copy_from_slice(&fee.to_le_bytes());
+        }
+
+        test_account(Pubkey::new_unique(), data)
+    }
+
+    #[test]
+    fn test_decode_cpmm_account() {
+        let (vault_0, vault_1) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let account = cpmm_account(vault_0, vault_1, [10, 20, 1, 2]);
+
+        let state = decode_raydium_cpmm_account(&account).unwrap();
+
+        assert_eq!(state.token_0_vault, vault_0);
+        assert_eq!(state.token_1_vault, vault_1);
+        assert_eq!(state.reserves(1_011, 4_022), (1_000, 4_000));
+    }
+
+    #[test]
+    fn test_cpmm_pool_update_matches_constant_product() {
+        let account = cpmm_account(Pubkey::new_unique(), Pubkey::new_unique(), [0; 4]);
+        let state = decode_raydium_cpmm_account(&account).unwrap();
+
+        let update = state.pool_update(1_000_000, 4_000_000).unwrap();
+
+        assert_eq!(update.new_liquidity, 2_000_000);
+        assert_eq!(update.new_sqrt_price, 2u128 << 64);
+        assert_eq!(update.new_current_tick_index, 13_863);
+        assert!(state.pool_update(0, 4_000_000).is_none());
+    }
+
+    #[test]
+    fn test_rejects_clmm_sized_account() {
+        let mut account = cpmm_account(Pubkey::new_unique(), Pubkey::new_unique(), [0; 4]);
+        account.data.resize(1544, 0);
+
+        assert!(matches!(
+            decode_raydium_cpmm_account(&account),
+            Err(DecodeError::WrongLength {
+                expected: CPMM_POOL_STATE_LEN,
+                got: 1544
+            })
+        ));
+    }
+}
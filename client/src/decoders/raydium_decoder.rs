@@ -1,31 +1,225 @@
-use anyhow::{Result, anyhow};
-use solana_sdk::account::Account;
+use std::ops::Range;
+
+use solana_sdk::{account::Account, pubkey::Pubkey};
 use tracing::error;
 
+use super::{DecodeError, PoolStatic};
 use crate::bootstrap::pool_schema::PoolUpdate;
 
-pub fn decode_raydium_account(account: &Account) -> Result<PoolUpdate> {
-    if account.data.len() != 1544 {
-        return Err(anyhow!("Account data has wrong length"));
+pub const DISCRIMINATOR: [u8; 8] = [247, 237, 227, 245, 215, 195, 222, 70];
+
+// byte ranges of the CLMM PoolState fields read by this crate
+pub struct PoolStateLayout;
+
+impl PoolStateLayout {
+    // bump 8
+    pub const AMM_CONFIG: Range<usize> = 9..41;
+    // owner 41..73
+    pub const TOKEN_MINT_0: Range<usize> = 73..105;
+    pub const TOKEN_MINT_1: Range<usize> = 105..137;
+    pub const TOKEN_VAULT_0: Range<usize> = 137..169;
+    pub const TOKEN_VAULT_1: Range<usize> = 169..201;
+    // observation_key 201..233, mint decimals 233..235
+    pub const TICK_SPACING: Range<usize> = 235..237;
+    pub const LIQUIDITY: Range<usize> = 237..253;
+    pub const SQRT_PRICE_X64: Range<usize> = 253..269;
+    pub const TICK_CURRENT: Range<usize> = 269..273;
+}
+
+// pool states are 1544 bytes today, but only the prefix up to tick_current is read
+const MIN_POOL_STATE_LEN: usize = PoolStateLayout::TICK_CURRENT.end;
+
+fn check_pool_state(data: &[u8]) -> Result<(), DecodeError> {
+    if data.len() < MIN_POOL_STATE_LEN {
+        return Err(DecodeError::WrongLength {
+            expected: MIN_POOL_STATE_LEN,
+            got: data.len(),
+        });
     }
 
-    let data = &account.data;
     let discriminator: [u8; 8] = data[0..8].try_into()?;
-
-    if discriminator != [247, 237, 227, 245, 215, 195, 222, 70] {
+    if discriminator != DISCRIMINATOR {
         error!("Discriminator: {:?}", discriminator);
-        return Err(anyhow!("Wrong Discriminator Found"));
+        return Err(DecodeError::BadDiscriminator);
     }
+    Ok(())
+}
+
+pub fn decode_raydium_vaults(account: &Account) -> Result<(Pubkey, Pubkey), DecodeError> {
+    let data = &account.data;
+    check_pool_state(data)?;
+
+    Ok((
+        Pubkey::new_from_array(data[PoolStateLayout::TOKEN_VAULT_0].try_into()?),
+        Pubkey::new_from_array(data[PoolStateLayout::TOKEN_VAULT_1].try_into()?),
+    ))
+}
 
-    //let bump: u8 = data[8];
+pub fn decode_raydium_static(account: &Account) -> Result<PoolStatic, DecodeError> {
+    let data = &account.data;
+    check_pool_state(data)?;
+
+    Ok(PoolStatic {
+        config: Pubkey::new_from_array(data[PoolStateLayout::AMM_CONFIG].try_into()?),
+        tick_spacing: u16::from_le_bytes(data[PoolStateLayout::TICK_SPACING].try_into()?),
+        fee_rate: None,
+        token_mint_a: Pubkey::new_from_array(data[PoolStateLayout::TOKEN_MINT_0].try_into()?),
+        token_mint_b: Pubkey::new_from_array(data[PoolStateLayout::TOKEN_MINT_1].try_into()?),
+        token_vault_a: Pubkey::new_from_array(data[PoolStateLayout::TOKEN_VAULT_0].try_into()?),
+        token_vault_b: Pubkey::new_from_array(data[PoolStateLayout::TOKEN_VAULT_1].try_into()?),
+    })
+}
 
-    let liquidty: u128 = u128::from_le_bytes(data[237..253].try_into()?);
-    let sqrt_price: u128 = u128::from_le_bytes(data[253..269].try_into()?);
-    let current_tick_index: i32 = i32::from_le_bytes([data[269], data[270], data[271], data[272]]);
+// AmmConfig: bump 8, index 9..11, owner 11..43, protocol_fee_rate 43..47, trade_fee_rate 47..51
+const AMM_CONFIG_TRADE_FEE_RATE: Range<usize> = 47..51;
+
+pub fn decode_raydium_trade_fee_rate(account: &Account) -> Result<u32, DecodeError> {
+    let data = account
+        .data
+        .get(AMM_CONFIG_TRADE_FEE_RATE)
+        .ok_or(DecodeError::WrongLength {
+            expected: AMM_CONFIG_TRADE_FEE_RATE.end,
+            got: account.data.len(),
+        })?;
+
+    Ok(u32::from_le_bytes(data.try_into()?))
+}
+
+pub fn decode_raydium_account(account: &Account) -> Result<PoolUpdate, DecodeError> {
+    let data = &account.data;
+    check_pool_state(data)?;
+
+    let liquidty: u128 = u128::from_le_bytes(data[PoolStateLayout::LIQUIDITY].try_into()?);
+    let sqrt_price: u128 = u128::from_le_bytes(data[PoolStateLayout::SQRT_PRICE_X64].try_into()?);
+    let current_tick_index: i32 =
+        i32::from_le_bytes(data[PoolStateLayout::TICK_CURRENT].try_into()?);
 
     Ok(PoolUpdate {
         new_liquidity: liquidty,
         new_sqrt_price: sqrt_price,
         new_current_tick_index: current_tick_index,
+        slot: 0,
+        fee_rate: None,
+        tick_spacing: None,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::decoders::test_account;
+
+    fn pool_state_account(len: usize) -> Account {
+        let mut data = vec![0u8; len];
+        data[0..8].copy_from_slice(&DISCRIMINATOR);
+        data[237..253].copy_from_slice(&42u128.to_le_bytes());
+        data[253..269].copy_from_slice(&(3u128 << 63).to_le_bytes());
+        data[269..273].copy_from_slice(&1234i32.to_le_bytes());
+
+        test_account(Pubkey::new_unique(), data)
+    }
+
+    #[test]
+    fn test_decodes_longer_account() {
+        for len in [MIN_POOL_STATE_LEN, 1544, 2048] {
+            let update = decode_raydium_account(&pool_state_account(len)).unwrap();
+
+            assert_eq!(update.new_liquidity, 42);
+            assert_eq!(update.new_sqrt_price, 3u128 << 63);
+            assert_eq!(update.new_current_tick_index, 1234);
+        }
+    }
+
+    #[test]
+    fn test_negative_tick_keeps_its_sign() {
+        // -443_636 is the lowest tick a Raydium CLMM pool allows
+        for tick in [-1i32, -256, -65_536, -443_636] {
+            let mut account = pool_state_account(1544);
+            // set bytes around the tick, a shifted offset would read them into it
+            account.data[268] = 0xff;
+            account.data[273] = 0xff;
+            account.data[PoolStateLayout::TICK_CURRENT].copy_from_slice(&tick.to_le_bytes());
+
+            let update = decode_raydium_account(&account).unwrap();
+
+            assert_eq!(update.new_current_tick_index, tick);
+        }
+    }
+
+    #[test]
+    fn test_decodes_vaults() {
+        let (vault_0, vault_1) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut account = pool_state_account(1544);
+        account.data[PoolStateLayout::TOKEN_VAULT_0].copy_from_slice(vault_0.as_ref());
+        account.data[PoolStateLayout::TOKEN_VAULT_1].copy_from_slice(vault_1.as_ref());
+
+        assert_eq!(decode_raydium_vaults(&account).unwrap(), (vault_0, vault_1));
+    }
+
+    #[test]
+    fn test_decodes_static_fields() {
+        let keys: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+        let mut account = pool_state_account(1544);
+        account.data[PoolStateLayout::AMM_CONFIG].copy_from_slice(keys[0].as_ref());
+        account.data[PoolStateLayout::TOKEN_MINT_0].copy_from_slice(keys[1].as_ref());
+        account.data[PoolStateLayout::TOKEN_MINT_1].copy_from_slice(keys[2].as_ref());
+        account.data[PoolStateLayout::TOKEN_VAULT_0].copy_from_slice(keys[3].as_ref());
+        account.data[PoolStateLayout::TOKEN_VAULT_1].copy_from_slice(keys[4].as_ref());
+        account.data[PoolStateLayout::TICK_SPACING].copy_from_slice(&10u16.to_le_bytes());
+
+        assert_eq!(
+            decode_raydium_static(&account).unwrap(),
+            PoolStatic {
+                config: keys[0],
+                tick_spacing: 10,
+                fee_rate: None,
+                token_mint_a: keys[1],
+                token_mint_b: keys[2],
+                token_vault_a: keys[3],
+                token_vault_b: keys[4],
+            }
+        );
+    }
+
+    #[test]
+    fn test_decodes_trade_fee_rate() {
+        let mut account = pool_state_account(MIN_POOL_STATE_LEN);
+        account.data.truncate(117);
+        account.data[AMM_CONFIG_TRADE_FEE_RATE].copy_from_slice(&2_500u32.to_le_bytes());
+
+        assert_eq!(decode_raydium_trade_fee_rate(&account).unwrap(), 2_500);
+
+        account.data.truncate(50);
+        assert!(matches!(
+            decode_raydium_trade_fee_rate(&account),
+            Err(DecodeError::WrongLength {
+                expected: 51,
+                got: 50
+            })
+        ));
+    }
+
+    #[test]
+    fn test_vaults_require_discriminator() {
+        let mut account = pool_state_account(1544);
+        account.data[0] = 0;
+
+        assert!(matches!(
+            decode_raydium_vaults(&account),
+            Err(DecodeError::BadDiscriminator)
+        ));
+    }
+
+    #[test]
+    fn test_rejects_truncated_account() {
+        let mut account = pool_state_account(1544);
+        account.data.truncate(MIN_POOL_STATE_LEN - 1);
+
+        assert!(matches!(
+            decode_raydium_account(&account),
+            Err(DecodeError::WrongLength { .. })
+        ));
+    }
+}
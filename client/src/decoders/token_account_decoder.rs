@@ -0,0 +1,324 @@
+use std::str::FromStr;
+
+use solana_sdk::{account::Account, pubkey::Pubkey};
+
+use super::DecodeError;
+
+pub const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+pub const TOKEN_2022_PROGRAM: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+
+// base SPL token account size, token-2022 accounts append extensions after it
+const TOKEN_ACCOUNT_LEN: usize = 165;
+// token-2022 marks what follows the base layout with an account type byte
+const ACCOUNT_TYPE_MINT: u8 = 1;
+const ACCOUNT_TYPE_ACCOUNT: u8 = 2;
+const EXTENSION_UNINITIALIZED: u16 = 0;
+const EXTENSION_TRANSFER_FEE_CONFIG: u16 = 1;
+const EXTENSION_TRANSFER_FEE_AMOUNT: u16 = 2;
+// TransferFeeConfig: two authorities and withheld_amount 0..72, then the older and newer
+// TransferFee { epoch, maximum_fee, transfer_fee_basis_points }, 18 bytes each
+const OLDER_TRANSFER_FEE_BPS: usize = 88;
+const NEWER_TRANSFER_FEE_BPS: usize = 106;
+const TRANSFER_FEE_CONFIG_LEN: usize = 108;
+// same for mints, mint_authority 0..36 and supply 36..44 come before decimals
+const MINT_LEN: usize = 82;
+const MINT_DECIMALS: usize = 44;
+
+lazy_static::lazy_static! {
+    static ref TOKEN_PROGRAM_ID: Pubkey = Pubkey::from_str(TOKEN_PROGRAM).unwrap();
+    static ref TOKEN_2022_PROGRAM_ID: Pubkey = Pubkey::from_str(TOKEN_2022_PROGRAM).unwrap();
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TokenProgram {
+    #[default]
+    Token,
+    Token2022,
+}
+
+impl TokenProgram {
+    pub fn from_owner(owner: &Pubkey) -> Option<Self> {
+        if *owner == *TOKEN_PROGRAM_ID {
+            Some(TokenProgram::Token)
+        } else if *owner == *TOKEN_2022_PROGRAM_ID {
+            Some(TokenProgram::Token2022)
+        } else {
+            None
+        }
+    }
+
+    pub fn id(&self) -> Pubkey {
+        match self {
+            TokenProgram::Token => *TOKEN_PROGRAM_ID,
+            TokenProgram::Token2022 => *TOKEN_2022_PROGRAM_ID,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TokenAccount {
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub program: TokenProgram,
+    // transfer fees held back in this account, only set when the TransferFeeAmount extension is present
+    pub withheld_transfer_fee: Option<u64>,
+}
+
+pub fn decode_token_account(account: &Account) -> Result<TokenAccount, DecodeError> {
+    let program = TokenProgram::from_owner(&account.owner).ok_or(DecodeError::UnknownOwner)?;
+    let amount = decode_token_amount(account)?;
+    let data = &account.data;
+
+    let mut withheld_transfer_fee = None;
+    if program == TokenProgram::Token2022 && data.len() > TOKEN_ACCOUNT_LEN {
+        if data[TOKEN_ACCOUNT_LEN] != ACCOUNT_TYPE_ACCOUNT {
+            return Err(DecodeError::BadDiscriminator);
+        }
+        for (extension_type, value) in extensions(&data[TOKEN_ACCOUNT_LEN + 1..])? {
+            if extension_type == EXTENSION_TRANSFER_FEE_AMOUNT {
+                withheld_transfer_fee = Some(u64::from_le_bytes(
+                    value
+                        .get(0..8)
+                        .ok_or(DecodeError::WrongLength {
+                            expected: 8,
+                            got: value.len(),
+                        })?
+                        .try_into()?,
+                ));
+            }
+        }
+    }
+
+    Ok(TokenAccount {
+        mint: Pubkey::new_from_array(data[0..32].try_into()?),
+        amount,
+        program,
+        withheld_transfer_fee,
+    })
+}
+
+// token-2022 extensions are a (u16 type, u16 length, value) list, padded with zeroes at the end
+fn extensions(mut data: &[u8]) -> Result<Vec<(u16, &[u8])>, DecodeError> {
+    let mut extensions = Vec::new();
+
+    while data.len() >= 4 {
+        let extension_type = u16::from_le_bytes(data[0..2].try_into()?);
+        if extension_type == EXTENSION_UNINITIALIZED {
+            break;
+        }
+        let length = u16::from_le_bytes(data[2..4].try_into()?) as usize;
+        let value = data.get(4..4 + length).ok_or(DecodeError::WrongLength {
+            expected: 4 + length,
+            got: data.len(),
+        })?;
+
+        extensions.push((extension_type, value));
+        data = &data[4 + length..];
+    }
+
+    Ok(extensions)
+}
+
+pub fn decode_token_amount(account: &Account) -> Result<u64, DecodeError> {
+    if account.data.len() < TOKEN_ACCOUNT_LEN {
+        return Err(DecodeError::WrongLength {
+            expected: TOKEN_ACCOUNT_LEN,
+            got: account.data.len(),
+        });
+    }
+
+    // mint 0..32, owner 32..64, amount 64..72
+    Ok(u64::from_le_bytes(account.data[64..72].try_into()?))
+}
+
+pub fn decode_mint_decimals(account: &Account) -> Result<u8, DecodeError> {
+    if account.data.len() < MINT_LEN {
+        return Err(DecodeError::WrongLength {
+            expected: MINT_LEN,
+            got: account.data.len(),
+        });
+    }
+
+    Ok(account.data[MINT_DECIMALS])
+}
+
+// the newer fee only applies from its epoch on, the higher of the two is assumed so
+// profit is never overestimated around a fee change. 0 for mints without the extension
+pub fn decode_mint_transfer_fee_bps(account: &Account) -> Result<u16, DecodeError> {
+    decode_mint_decimals(account)?;
+    let data = &account.data;
+    if data.len() <= TOKEN_ACCOUNT_LEN {
+        return Ok(0);
+    }
+    // mints are padded up to the token account size so the account type byte lines up
+    if data[TOKEN_ACCOUNT_LEN] != ACCOUNT_TYPE_MINT {
+        return Err(DecodeError::BadDiscriminator);
+    }
+
+    for (extension_type, value) in extensions(&data[TOKEN_ACCOUNT_LEN + 1..])? {
+        if extension_type != EXTENSION_TRANSFER_FEE_CONFIG {
+            continue;
+        }
+        if value.len() < TRANSFER_FEE_CONFIG_LEN {
+            return Err(DecodeError::WrongLength {
+                expected: TRANSFER_FEE_CONFIG_LEN,
+                got: value.len(),
+            });
+        }
+        let older = u16::from_le_bytes(
+            value[OLDER_TRANSFER_FEE_BPS..OLDER_TRANSFER_FEE_BPS + 2].try_into()?,
+        );
+        let newer = u16::from_le_bytes(
+            value[NEWER_TRANSFER_FEE_BPS..NEWER_TRANSFER_FEE_BPS + 2].try_into()?,
+        );
+        return Ok(older.max(newer));
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoders::test_account;
+
+    fn token_account(len: usize, amount: u64) -> Account {
+        let mut data = vec![0u8; len];
+        data[0..32].copy_from_slice(Pubkey::new_unique().as_ref());
+        data[32..64].copy_from_slice(Pubkey::new_unique().as_ref());
+        data[64..72].copy_from_slice(&amount.to_le_bytes());
+
+        test_account(Pubkey::new_unique(), data)
+    }
+
+    #[test]
+    fn test_decode_token_amount() {
+        assert_eq!(
+            decode_token_amount(&token_account(TOKEN_ACCOUNT_LEN, 123_456_789)).unwrap(),
+            123_456_789
+        );
+        // token-2022 account with extensions
+        assert_eq!(decode_token_amount(&token_account(182, 42)).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_decode_token_amount_rejects_short_buffer() {
+        let mut account = token_account(TOKEN_ACCOUNT_LEN, 1);
+        account.data.truncate(72);
+
+        assert!(matches!(
+            decode_token_amount(&account),
+            Err(DecodeError::WrongLength {
+                expected: TOKEN_ACCOUNT_LEN,
+                got: 72
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decode_mint_decimals() {
+        let mut account = token_account(MINT_LEN, 0);
+        account.data[MINT_DECIMALS] = 6;
+
+        assert_eq!(decode_mint_decimals(&account).unwrap(), 6);
+
+        account.data.truncate(MINT_DECIMALS);
+        assert!(matches!(
+            decode_mint_decimals(&account),
+            Err(DecodeError::WrongLength { .. })
+        ));
+    }
+
+    // a token-2022 vault with a TransferFeeAmount extension and trailing padding
+    fn token_2022_account(amount: u64, withheld: u64) -> Account {
+        let mut account = token_account(TOKEN_ACCOUNT_LEN, amount);
+        account.owner = *TOKEN_2022_PROGRAM_ID;
+        account.data.push(ACCOUNT_TYPE_ACCOUNT);
+        account
+            .data
+            .extend_from_slice(&EXTENSION_TRANSFER_FEE_AMOUNT.to_le_bytes());
+        account.data.extend_from_slice(&8u16.to_le_bytes());
+        account.data.extend_from_slice(&withheld.to_le_bytes());
+        account.data.extend_from_slice(&[0; 6]);
+        account
+    }
+
+    #[test]
+    fn test_decode_token_2022_account_with_transfer_fee_extension() {
+        let account = token_2022_account(5_000, 12);
+
+        let decoded = decode_token_account(&account).unwrap();
+
+        assert_eq!(decoded.amount, 5_000);
+        assert_eq!(decoded.program, TokenProgram::Token2022);
+        assert_eq!(decoded.withheld_transfer_fee, Some(12));
+        assert_eq!(decoded.mint.as_ref(), &account.data[0..32]);
+    }
+
+    #[test]
+    fn test_decode_classic_token_account() {
+        let mut account = token_account(TOKEN_ACCOUNT_LEN, 7);
+        account.owner = *TOKEN_PROGRAM_ID;
+
+        let decoded = decode_token_account(&account).unwrap();
+
+        assert_eq!(decoded.program, TokenProgram::Token);
+        assert_eq!(decoded.withheld_transfer_fee, None);
+        assert_eq!(
+            TokenProgram::from_owner(&decoded.program.id()),
+            Some(decoded.program)
+        );
+    }
+
+    #[test]
+    fn test_decode_token_account_rejects_other_owners_and_bad_extensions() {
+        assert!(matches!(
+            decode_token_account(&token_account(TOKEN_ACCOUNT_LEN, 1)),
+            Err(DecodeError::UnknownOwner)
+        ));
+
+        // extension claims more bytes than the account holds
+        let mut account = token_2022_account(1, 1);
+        account.data.truncate(TOKEN_ACCOUNT_LEN + 1 + 4 + 4);
+        assert!(matches!(
+            decode_token_account(&account),
+            Err(DecodeError::WrongLength { .. })
+        ));
+    }
+
+    fn token_2022_mint(older_bps: u16, newer_bps: u16) -> Account {
+        let mut account = token_account(TOKEN_ACCOUNT_LEN, 0);
+        account.owner = *TOKEN_2022_PROGRAM_ID;
+        account.data[MINT_DECIMALS] = 6;
+        account.data.push(ACCOUNT_TYPE_MINT);
+
+        let mut config = vec![0u8; TRANSFER_FEE_CONFIG_LEN];
+        config[OLDER_TRANSFER_FEE_BPS..OLDER_TRANSFER_FEE_BPS + 2]
+            .copy_from_slice(&older_bps.to_le_bytes());
+        config[NEWER_TRANSFER_FEE_BPS..NEWER_TRANSFER_FEE_BPS + 2]
+            .copy_from_slice(&newer_bps.to_le_bytes());
+        account
+            .data
+            .extend_from_slice(&EXTENSION_TRANSFER_FEE_CONFIG.to_le_bytes());
+        account
+            .data
+            .extend_from_slice(&(TRANSFER_FEE_CONFIG_LEN as u16).to_le_bytes());
+        account.data.extend_from_slice(&config);
+        account
+    }
+
+    #[test]
+    fn test_decode_mint_transfer_fee_bps() {
+        assert_eq!(
+            decode_mint_transfer_fee_bps(&token_2022_mint(50, 100)).unwrap(),
+            100
+        );
+        assert_eq!(
+            decode_mint_transfer_fee_bps(&token_2022_mint(100, 0)).unwrap(),
+            100
+        );
+
+        let mut classic_mint = token_account(MINT_LEN, 0);
+        classic_mint.owner = *TOKEN_PROGRAM_ID;
+        assert_eq!(decode_mint_transfer_fee_bps(&classic_mint).unwrap(), 0);
+    }
+}
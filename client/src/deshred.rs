@@ -1,32 +1,382 @@
+use std::{collections::HashSet, time::Duration};
+
+use anyhow::Result;
+use futures::{Stream, StreamExt};
 use jito_protos::shredstream::{
-    SubscribeEntriesRequest, shredstream_proxy_client::ShredstreamProxyClient,
+    Entry as SlotEntry, SubscribeEntriesRequest, shredstream_proxy_client::ShredstreamProxyClient,
 };
+use solana_entry::entry::Entry;
+use solana_sdk::{pubkey::Pubkey, transaction::VersionedTransaction};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::metrics;
+
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+// slots per failure rate check, and the share of them that may fail before it warns
+const DESERIALIZE_WINDOW: u64 = 1_000;
+const DESERIALIZE_FAILURE_THRESHOLD: f64 = 0.01;
+
+// undecodable slots over a window of slots. a steady failure rate usually means solana_entry
+// doesn't match the proxy's version, so every slot is lost without any other error
+#[derive(Debug, Default)]
+struct DeserializeStats {
+    slots: u64,
+    failures: u64,
+}
+
+impl DeserializeStats {
+    // counts one slot, and once the window is full returns its failure rate if that crossed
+    // the threshold
+    fn record(&mut self, failed: bool) -> Option<f64> {
+        self.slots += 1;
+        if failed {
+            self.failures += 1;
+            metrics::SHRED_DESERIALIZE_FAILURES.inc();
+        }
+        if self.slots < DESERIALIZE_WINDOW {
+            return None;
+        }
+
+        let rate = self.failures as f64 / self.slots as f64;
+        *self = Self::default();
+        (rate > DESERIALIZE_FAILURE_THRESHOLD).then_some(rate)
+    }
+}
+
+// runs until shutdown is cancelled, reconnecting whenever the proxy drops the stream. handler
+// gets each slot's decoded entries, a slot whose entries don't deserialize is skipped
+pub async fn stream_entries(
+    endpoint: &str,
+    max_backoff: Duration,
+    shutdown: CancellationToken,
+    mut handler: impl FnMut(u64, &[Entry]),
+) -> Result<()> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut last_slot: Option<u64> = None;
+    let mut stats = DeserializeStats::default();
 
-pub async fn deshred() -> Result<(), std::io::Error> {
-    let mut client = ShredstreamProxyClient::connect("http://127.0.0.1:9999")
+    while !shutdown.is_cancelled() {
+        let slot_before = last_slot;
+
+        if let Err(e) = consume_stream(
+            endpoint,
+            &mut last_slot,
+            &mut stats,
+            &shutdown,
+            &mut handler,
+        )
         .await
-        .unwrap();
-    let mut stream = client
+        {
+            warn!("Shredstream connection to {} failed: {:?}", endpoint, e);
+        } else if !shutdown.is_cancelled() {
+            warn!("Shredstream from {} ended", endpoint);
+        }
+        if shutdown.is_cancelled() {
+            break;
+        }
+
+        // only back off further while the proxy keeps failing without delivering anything
+        if last_slot != slot_before {
+            backoff = INITIAL_BACKOFF;
+        }
+        info!("Reconnecting to shredstream in {:?}", backoff);
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = shutdown.cancelled() => break,
+        }
+        backoff = (backoff * 2).min(max_backoff);
+    }
+
+    info!("Shredstream stopped, last slot {:?}", last_slot);
+    Ok(())
+}
+
+// transactions in the entries that name one of the pools, the only ones worth decoding. only
+// static keys are checked, a pool reached solely through a lookup table is missed
+pub fn pool_transactions<'a>(
+    entries: &'a [Entry],
+    pools: &'a HashSet<Pubkey>,
+) -> impl Iterator<Item = &'a VersionedTransaction> {
+    entries
+        .iter()
+        .flat_map(|entry| &entry.transactions)
+        .filter(|transaction| {
+            transaction
+                .message
+                .static_account_keys()
+                .iter()
+                .any(|key| pools.contains(key))
+        })
+}
+
+async fn consume_stream(
+    endpoint: &str,
+    last_slot: &mut Option<u64>,
+    stats: &mut DeserializeStats,
+    shutdown: &CancellationToken,
+    handler: &mut impl FnMut(u64, &[Entry]),
+) -> Result<()> {
+    let mut client = tokio::select! {
+        client = ShredstreamProxyClient::connect(endpoint.to_string()) => client?,
+        _ = shutdown.cancelled() => return Ok(()),
+    };
+    let stream = client
         .subscribe_entries(SubscribeEntriesRequest {})
-        .await
-        .unwrap()
+        .await?
         .into_inner();
 
-    while let Some(slot_entry) = stream.message().await.unwrap() {
-        let entries =
-            match bincode::deserialize::<Vec<solana_entry::entry::Entry>>(&slot_entry.entries) {
-                Ok(e) => e,
-                Err(e) => {
-                    println!("Deserialization failed with err: {e}");
-                    continue;
-                }
-            };
-        println!(
-            "slot {}, entries: {}, transactions: {}",
-            slot_entry.slot,
-            entries.len(),
-            entries.iter().map(|e| e.transactions.len()).sum::<usize>()
+    process_stream(stream, last_slot, stats, shutdown, handler).await
+}
+
+async fn process_stream<S, E>(
+    mut stream: S,
+    last_slot: &mut Option<u64>,
+    stats: &mut DeserializeStats,
+    shutdown: &CancellationToken,
+    handler: &mut impl FnMut(u64, &[Entry]),
+) -> Result<()>
+where
+    S: Stream<Item = Result<SlotEntry, E>> + Unpin,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let mut resumed = last_slot.is_none();
+
+    loop {
+        let slot_entry = tokio::select! {
+            message = stream.next() => match message {
+                Some(slot_entry) => slot_entry?,
+                None => return Ok(()),
+            },
+            _ = shutdown.cancelled() => return Ok(()),
+        };
+
+        if !resumed && let Some(previous_slot) = *last_slot {
+            info!(
+                "Resumed shredstream at slot {}, missed {} slots",
+                slot_entry.slot,
+                slot_entry.slot.saturating_sub(previous_slot + 1)
+            );
+        }
+        resumed = true;
+        *last_slot = Some(slot_entry.slot);
+
+        let decoded = bincode::deserialize::<Vec<Entry>>(&slot_entry.entries);
+        if let Some(rate) = stats.record(decoded.is_err()) {
+            warn!(
+                "{:.1}% of the last {} slots failed to deserialize, check the solana_entry version",
+                rate * 100.0,
+                DESERIALIZE_WINDOW
+            );
+        }
+        let entries = match decoded {
+            Ok(e) => e,
+            Err(e) => {
+                // the rate check above warns, one line per slot would flood the log
+                debug!(
+                    "Failed to deserialize entries for slot {}: {}",
+                    slot_entry.slot, e
+                );
+                continue;
+            }
+        };
+        metrics::SHRED_ENTRIES.inc_by(entries.len() as u64);
+        metrics::TRANSACTIONS_SEEN.inc_by(
+            entries
+                .iter()
+                .map(|e| e.transactions.len() as u64)
+                .sum::<u64>(),
+        );
+        handler(slot_entry.slot, &entries);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::channel::mpsc;
+    use solana_sdk::message::{Message, VersionedMessage};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel_stops_an_idle_stream() {
+        let (sender, receiver) = mpsc::unbounded::<Result<SlotEntry, std::io::Error>>();
+        let shutdown = CancellationToken::new();
+        let mut last_slot = None;
+
+        sender
+            .unbounded_send(Ok(SlotEntry {
+                slot: 7,
+                entries: bincode::serialize(&Vec::<Entry>::new()).unwrap(),
+            }))
+            .unwrap();
+        let cancel = shutdown.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            cancel.cancel();
+        });
+
+        // the sender stays alive, so only the cancel can end the loop
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            process_stream(
+                receiver,
+                &mut last_slot,
+                &mut DeserializeStats::default(),
+                &shutdown,
+                &mut |_, _| {},
+            ),
+        )
+        .await
+        .expect("stream loop didn't stop after cancel")
+        .unwrap();
+
+        assert_eq!(last_slot, Some(7));
+        drop(sender);
+    }
+
+    #[tokio::test]
+    async fn test_handler_receives_decoded_entries() {
+        let entries: Vec<Entry> = (1..=3)
+            .map(|num_hashes| Entry {
+                num_hashes,
+                ..Entry::default()
+            })
+            .collect();
+        let stream = futures::stream::iter([
+            Ok::<_, std::io::Error>(SlotEntry {
+                slot: 9,
+                entries: bincode::serialize(&entries).unwrap(),
+            }),
+            // skipped, the handler never sees it
+            Ok(SlotEntry {
+                slot: 10,
+                entries: vec![0xff; 3],
+            }),
+        ]);
+        let mut received = Vec::new();
+
+        process_stream(
+            stream,
+            &mut None,
+            &mut DeserializeStats::default(),
+            &CancellationToken::new(),
+            &mut |slot, slot_entries: &[Entry]| received.push((slot, slot_entries.to_vec())),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(received, vec![(9, entries)]);
+    }
+
+    #[test]
+    fn test_pool_transactions_keeps_known_pools_only() {
+        let pool = Pubkey::new_unique();
+        let transaction = |keys: Vec<Pubkey>| VersionedTransaction {
+            signatures: Vec::new(),
+            message: VersionedMessage::Legacy(Message {
+                account_keys: keys,
+                ..Message::default()
+            }),
+        };
+        let entries = vec![
+            Entry {
+                transactions: vec![
+                    transaction(vec![Pubkey::new_unique(), pool]),
+                    transaction(vec![Pubkey::new_unique()]),
+                ],
+                ..Entry::default()
+            },
+            Entry {
+                transactions: vec![transaction(vec![pool, Pubkey::new_unique()])],
+                ..Entry::default()
+            },
+        ];
+
+        let pools = HashSet::from([pool]);
+        let matched: Vec<_> = pool_transactions(&entries, &pools).collect();
+
+        assert_eq!(matched.len(), 2);
+        assert!(
+            matched
+                .iter()
+                .all(|transaction| transaction.message.static_account_keys().contains(&pool))
+        );
+        assert_eq!(pool_transactions(&entries, &HashSet::new()).count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_buffer_counts_a_failure() {
+        let stream = futures::stream::iter([
+            Ok::<_, std::io::Error>(SlotEntry {
+                slot: 11,
+                entries: bincode::serialize(&Vec::<Entry>::new()).unwrap(),
+            }),
+            Ok(SlotEntry {
+                slot: 12,
+                entries: vec![0xff; 3],
+            }),
+        ]);
+        let mut stats = DeserializeStats::default();
+        let failures_before = metrics::SHRED_DESERIALIZE_FAILURES.get();
+
+        process_stream(
+            stream,
+            &mut None,
+            &mut stats,
+            &CancellationToken::new(),
+            &mut |_, _| {},
+        )
+        .await
+        .unwrap();
+
+        assert_eq!((stats.slots, stats.failures), (2, 1));
+        // other tests feed corrupt buffers too, so the shared counter only grows
+        assert!(metrics::SHRED_DESERIALIZE_FAILURES.get() > failures_before);
+    }
+
+    #[test]
+    fn test_failure_rate_is_reported_once_per_window() {
+        let mut stats = DeserializeStats::default();
+        let rates: Vec<Option<f64>> = (0..DESERIALIZE_WINDOW)
+            .map(|slot| stats.record(slot < 20))
+            .collect();
+        assert!(rates[..rates.len() - 1].iter().all(Option::is_none));
+        assert_eq!(rates.last(), Some(&Some(0.02)));
+        assert_eq!((stats.slots, stats.failures), (0, 0));
+
+        // under the threshold the window closes quietly
+        let rates: Vec<Option<f64>> = (0..DESERIALIZE_WINDOW)
+            .map(|slot| stats.record(slot < 5))
+            .collect();
+        assert!(rates.iter().all(Option::is_none));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_interrupts_reconnect_backoff() {
+        let shutdown = CancellationToken::new();
+        // nothing listens on port 1, the first connect fails at once and the cancel lands while
+        // stream_entries waits out the backoff
+        let cancel = shutdown.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancel.cancel();
+        });
+        let started = tokio::time::Instant::now();
+
+        stream_entries(
+            "http://127.0.0.1:1",
+            DEFAULT_MAX_BACKOFF,
+            shutdown,
+            |_, _| {},
+        )
+        .await
+        .unwrap();
+
+        assert!(
+            started.elapsed() < INITIAL_BACKOFF,
+            "deshred waited out the backoff after cancel"
         );
     }
-    Ok(())
 }
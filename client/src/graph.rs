@@ -1,22 +1,30 @@
 use std::{
-    collections::{HashMap, HashSet},
-    fs::{read_dir, read_to_string},
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::File,
+    io::{BufReader, BufWriter, Write},
     str::FromStr,
-    time::Instant,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
 use ethnum::U256;
-use solana_sdk::pubkey::Pubkey;
-use tracing::{info, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use tracing::{field, info_span, warn};
 
 use crate::{
-    bootstrap::pool_schema::{DexType, PoolInfo, PoolType, PoolUpdate, StoredPools, TokenInfo},
-    get_all_pool_files,
+    bootstrap::pool_schema::{
+        DexType, PoolInfo, PoolType, PoolUpdate, TokenInfo, for_each_stored_pool,
+    },
+    decoders::{TICK_ARRAY_SIZE, TickArray, TokenAccount, TokenProgram},
+    get_all_pool_files, metrics,
+    swap::{self, OrcaSwap},
 };
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Node {
     address: Pubkey,
     decimals: u8,
@@ -25,7 +33,7 @@ pub struct Node {
 }
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Edge {
     //static fields
     pub address: Pubkey,
@@ -41,19 +49,87 @@ pub struct Edge {
     decimals_lowest: u8,
     decimals_highest: u8,
     pub reversed: bool,
+    // known-bad pools stay in the graph but out of cycles, scans and updates
+    pub disabled: bool,
 
-    //dynamic fields
+    //dynamic fields, not persisted in graph snapshots
+    #[serde(skip)]
     pub sqrt_price: Option<u128>,
+    #[serde(skip)]
     liquidity: Option<u128>,
+    #[serde(skip)]
     current_tick_index: Option<i32>,
+    #[serde(skip)]
+    last_updated_slot: Option<u64>,
+    #[serde(skip)]
+    vault_balance_lowest: Option<u64>,
+    #[serde(skip)]
+    vault_balance_highest: Option<u64>,
+    // learned from the vault owners, so unknown until the vaults are first read
+    #[serde(skip)]
+    token_program_lowest: TokenProgram,
+    #[serde(skip)]
+    token_program_highest: TokenProgram,
+    // Token-2022 transfer fees, charged again on every transfer into or out of the pool
+    #[serde(skip)]
+    transfer_fee_bps_lowest: u16,
+    #[serde(skip)]
+    transfer_fee_bps_highest: u16,
+    // initialized ticks from the cached tick arrays, tick index to liquidity_net
+    #[serde(skip)]
+    ticks: BTreeMap<i32, i128>,
+    // ticks covered by the cached arrays, liquidity outside it is unknown
+    #[serde(skip)]
+    tick_coverage: Option<(i32, i32)>,
 }
 
+// Orca and Raydium both express fee_rate in hundredths of a basis point
+const FEE_RATE_DENOMINATOR: f64 = 1_000_000.0;
+const TRANSFER_FEE_DENOMINATOR: f64 = 10_000.0;
+
 impl Edge {
-    pub fn get_log_exchange_rate(&self, direct: bool) -> f64 {
-        self.get_exchange_rate(direct).log10()
+    pub fn get_log_exchange_rate(&self, direct: bool, with_fee: bool) -> Option<f64> {
+        let exchange_rate = if with_fee {
+            self.get_exchange_rate_with_fee(direct)?
+        } else {
+            self.get_exchange_rate(direct)?
+        };
+        // a 100% fee leaves nothing, and log10(0) is -inf
+        let log_rate = exchange_rate.log10();
+        log_rate.is_finite().then_some(log_rate)
+    }
+
+    pub fn get_exchange_rate_with_fee(&self, direct: bool) -> Option<f64> {
+        let (kept_in, kept_out) = self.transfer_fee_multipliers(direct);
+        Some(self.get_exchange_rate(direct)? * self.fee_multiplier() * kept_in * kept_out)
+    }
+
+    // fractions of the input reaching the pool and of the output reaching the caller
+    fn transfer_fee_multipliers(&self, direct: bool) -> (f64, f64) {
+        let kept = |bps: u16| (1.0 - bps as f64 / TRANSFER_FEE_DENOMINATOR).clamp(0.0, 1.0);
+        let (kept_lowest, kept_highest) = (
+            kept(self.transfer_fee_bps_lowest),
+            kept(self.transfer_fee_bps_highest),
+        );
+
+        match direct {
+            true => (kept_lowest, kept_highest),
+            false => (kept_highest, kept_lowest),
+        }
+    }
+
+    // fraction of the input kept after the pool fee, clamped so a bogus fee_rate can't go negative
+    fn fee_multiplier(&self) -> f64 {
+        (1.0 - self.fee_rate as f64 / FEE_RATE_DENOMINATOR).clamp(0.0, 1.0)
     }
 
-    pub fn get_exchange_rate(&self, direct: bool) -> f64 {
+    // None until the edge has received its first PoolUpdate, and for a rate that isn't a finite
+    // positive number. any non-zero u128 sqrt_price is in range: the price before decimals lies
+    // within 2^-128..2^128 and the decimal shift at most 10^255, well inside f64. a zero
+    // sqrt_price would make the price 0 and its reciprocal or log10 infinite
+    pub fn get_exchange_rate(&self, direct: bool) -> Option<f64> {
+        let sqrt_price = self.sqrt_price?;
+
         let decimals_diff: i32 = if self.reversed {
             self.decimals_highest as i32 - self.decimals_lowest as i32
         } else {
@@ -61,22 +137,25 @@ impl Edge {
         };
         let denominator = 10f64.powi(decimals_diff);
 
-        let scaled_price: U256 = U256::from(self.sqrt_price.unwrap());
+        let scaled_price: U256 = U256::from(sqrt_price);
         let squared: U256 = scaled_price * scaled_price;
 
+        // squared is Q128.128, the high half is the integer part of the price
         let high: U256 = squared >> 128;
         let low: U256 = squared & U256::from(u128::MAX);
-        let price_f64 = high.as_u128() as f64 * 2f64.powi(64) + low.as_u128() as f64;
+        let price_f64 = high.as_u128() as f64 * 2f64.powi(128) + low.as_u128() as f64;
 
         let price_f64 = price_f64 / 2f64.powi(128);
 
         let exchange_rate = price_f64 * denominator;
 
-        if self.reversed == direct {
+        let exchange_rate = if self.reversed == direct {
             1.0 / exchange_rate
         } else {
             exchange_rate
-        }
+        };
+        // an infinite or zero rate would poison every log sum it is added to
+        (exchange_rate.is_finite() && exchange_rate > 0.0).then_some(exchange_rate)
     }
 
     fn get_other_node(&self, this_token: usize) -> Option<usize> {
@@ -89,7 +168,6 @@ impl Edge {
         }
     }
 
-    #[allow(dead_code)]
     fn get_swap_direction(&self, token_in: usize) -> Option<bool> {
         if self.node_lowest == token_in {
             return Some(!self.reversed);
@@ -99,13 +177,273 @@ impl Edge {
 
         None
     }
+
+    // within-tick concentrated liquidity swap in raw token units, tick crossings are ignored.
+    // Returns the output amount and the unscaled sqrt price after the swap
+    fn swap_within_tick(&self, amount_in: f64, a_to_b: bool) -> Option<(f64, f64)> {
+        let liquidity = self.liquidity? as f64;
+        if liquidity == 0.0 {
+            return None;
+        }
+        let sqrt_price = self.sqrt_price? as f64 / 2f64.powi(64);
+        let amount_in = amount_in * self.fee_multiplier();
+
+        if a_to_b {
+            let new_sqrt_price = liquidity * sqrt_price / (liquidity + amount_in * sqrt_price);
+            Some((liquidity * (sqrt_price - new_sqrt_price), new_sqrt_price))
+        } else {
+            let new_sqrt_price = sqrt_price + amount_in / liquidity;
+            Some((
+                liquidity * (1.0 / sqrt_price - 1.0 / new_sqrt_price),
+                new_sqrt_price,
+            ))
+        }
+    }
+
+    fn estimate_amount_out(&self, amount_in: f64, a_to_b: bool) -> Option<f64> {
+        let (kept_in, kept_out) = self.transfer_fee_multipliers(a_to_b != self.reversed);
+        Some(self.swap_within_tick(amount_in * kept_in, a_to_b)?.0 * kept_out)
+    }
+
+    // both vaults valued in the given token's raw units at the current pool price
+    pub fn tvl_in(&self, token: usize) -> Option<u64> {
+        let balance_lowest = self.vault_balance_lowest? as f64;
+        let balance_highest = self.vault_balance_highest? as f64;
+        let lowest_per_highest = self.get_exchange_rate(false)?;
+
+        let raw_lowest_per_highest = lowest_per_highest
+            * 10f64.powi(self.decimals_lowest as i32 - self.decimals_highest as i32);
+        let tvl_lowest = balance_lowest + balance_highest * raw_lowest_per_highest;
+
+        if token == self.node_lowest {
+            Some(tvl_lowest as u64)
+        } else if token == self.node_highest {
+            Some((tvl_lowest / raw_lowest_per_highest) as u64)
+        } else {
+            None
+        }
+    }
+
+    // the program a swap has to pass for the given token's side of the pool
+    pub fn token_program(&self, token: usize) -> Option<TokenProgram> {
+        if token == self.node_lowest {
+            Some(self.token_program_lowest)
+        } else if token == self.node_highest {
+            Some(self.token_program_highest)
+        } else {
+            None
+        }
+    }
+
+    // every dynamic field a PoolUpdate sets has arrived
+    fn is_priced(&self) -> bool {
+        self.sqrt_price.is_some() && self.liquidity.is_some() && self.current_tick_index.is_some()
+    }
+
+    // a pool with an empty vault can't fill a swap in either direction worth routing through
+    fn has_empty_vault(&self) -> bool {
+        self.vault_balance_lowest == Some(0) || self.vault_balance_highest == Some(0)
+    }
+
+    // unscaled sqrt prices bounding the initialized tick range the pool currently trades in
+    fn current_tick_range(&self) -> Option<(f64, f64)> {
+        let tick_spacing = self.tick_spacing as i64;
+        let lower_tick = (self.current_tick_index? as i64).div_euclid(tick_spacing) * tick_spacing;
+        let sqrt_price_at = |tick: i64| 1.0001f64.powf(tick as f64 / 2.0);
+
+        Some((
+            sqrt_price_at(lower_tick),
+            sqrt_price_at(lower_tick + tick_spacing),
+        ))
+    }
+
+    // exact output for a swap that stays inside the current tick range, None if it would
+    // cross into a range whose liquidity we don't track. Pools without ticks are never capped
+    pub fn amount_out(&self, amount_in: u64, direct: bool) -> Option<u64> {
+        let a_to_b = direct != self.reversed;
+        let (kept_in, kept_out) = self.transfer_fee_multipliers(direct);
+        let (amount_out, new_sqrt_price) =
+            self.swap_within_tick(amount_in as f64 * kept_in, a_to_b)?;
+
+        if self.tick_spacing > 0 {
+            let (lower, upper) = self.current_tick_range()?;
+            let crosses_tick = match a_to_b {
+                true => new_sqrt_price < lower,
+                false => new_sqrt_price >= upper,
+            };
+            if crosses_tick {
+                return None;
+            }
+        }
+
+        Some((amount_out * kept_out).floor() as u64)
+    }
+
+    // arrays are replaced whole. One that was never created has no initialized ticks, so gaps
+    // between the loaded arrays are safe to count as covered
+    fn apply_tick_array(&mut self, tick_array: &TickArray) {
+        let start = tick_array.start_tick_index;
+        let end = start + self.tick_spacing as i32 * TICK_ARRAY_SIZE;
+
+        self.ticks
+            .retain(|tick_index, _| !(start..end).contains(tick_index));
+        self.ticks.extend(
+            tick_array
+                .ticks
+                .iter()
+                .map(|tick| (tick.tick_index, tick.liquidity_net)),
+        );
+        self.tick_coverage = Some(match self.tick_coverage {
+            Some((low, high)) => (low.min(start), high.max(end)),
+            None => (start, end),
+        });
+    }
+
+    // amount_out, or the tick by tick walk when the swap leaves the current tick range
+    pub fn amount_out_with_ticks(&self, amount_in: u64, direct: bool) -> Option<u64> {
+        self.amount_out(amount_in, direct)
+            .or_else(|| self.amount_out_across_ticks(amount_in, direct))
+    }
+
+    // amount_out that keeps swapping through initialized ticks, changing liquidity at each one.
+    // None if the swap runs past the ticks the cached arrays cover
+    pub fn amount_out_across_ticks(&self, amount_in: u64, direct: bool) -> Option<u64> {
+        let a_to_b = direct != self.reversed;
+        let (kept_in, kept_out) = self.transfer_fee_multipliers(direct);
+        let (coverage_low, coverage_high) = self.tick_coverage?;
+        let sqrt_price_at = |tick: i32| 1.0001f64.powf(tick as f64 / 2.0);
+
+        let mut liquidity = self.liquidity? as f64;
+        let mut sqrt_price = self.sqrt_price? as f64 / 2f64.powi(64);
+        let mut tick = self.current_tick_index?;
+        let mut remaining = amount_in as f64 * kept_in * self.fee_multiplier();
+        let mut amount_out = 0.0;
+
+        loop {
+            if tick < coverage_low || tick >= coverage_high {
+                return None;
+            }
+            // the next initialized tick in the swap direction, or the end of the cached range
+            let next_tick = match a_to_b {
+                true => self.ticks.range(coverage_low..=tick).next_back(),
+                false => self.ticks.range(tick + 1..coverage_high).next(),
+            };
+            let (boundary, liquidity_net) = match (next_tick, a_to_b) {
+                (Some((&tick_index, &liquidity_net)), _) => (tick_index, Some(liquidity_net)),
+                (None, true) => (coverage_low, None),
+                (None, false) => (coverage_high, None),
+            };
+            let boundary_sqrt_price = sqrt_price_at(boundary);
+
+            if a_to_b {
+                let needed = liquidity * (1.0 / boundary_sqrt_price - 1.0 / sqrt_price);
+                if remaining < needed {
+                    let new_sqrt_price =
+                        liquidity * sqrt_price / (liquidity + remaining * sqrt_price);
+                    amount_out += liquidity * (sqrt_price - new_sqrt_price);
+                    break;
+                }
+                amount_out += liquidity * (sqrt_price - boundary_sqrt_price);
+                remaining -= needed;
+                liquidity -= liquidity_net? as f64;
+                tick = boundary - 1;
+            } else {
+                let needed = liquidity * (boundary_sqrt_price - sqrt_price);
+                if remaining < needed {
+                    let new_sqrt_price = sqrt_price + remaining / liquidity;
+                    amount_out += liquidity * (1.0 / sqrt_price - 1.0 / new_sqrt_price);
+                    break;
+                }
+                amount_out += liquidity * (1.0 / sqrt_price - 1.0 / boundary_sqrt_price);
+                remaining -= needed;
+                liquidity += liquidity_net? as f64;
+                tick = boundary;
+            }
+            sqrt_price = boundary_sqrt_price;
+            if liquidity < 0.0 {
+                return None;
+            }
+        }
+
+        Some((amount_out * kept_out).floor() as u64)
+    }
 }
 
-#[derive(Debug, Default)]
-pub struct Graph {
-    wsol_address: Pubkey,
-    wsol_node: usize,
+// direction false means the cycle is traded from its last edge back to its first
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArbitrageOpportunity {
+    pub cycle: Vec<usize>,
+    pub direction: bool,
+    pub log_profit: f64,
+    pub pools: Vec<Pubkey>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphStats {
+    pub nodes: usize,
+    pub edges: usize,
+    // unique cycles, all_cycles lists each one under every token pair it touches
+    pub cycles: usize,
+    pub avg_degree: f64,
+    pub max_degree: usize,
+    // edges that have received at least one price update
+    pub priced_edges: usize,
+}
+
+// one hop of an exported cycle, in the cycle's forward order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedHop {
+    pub pool: String,
+    pub token_in: String,
+    pub token_out: String,
+    pub fee_rate: u32,
+}
+
+// log_profit and direction are those of the better direction, None while a pool is unpriced
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedCycle {
+    pub edges: Vec<usize>,
+    pub hops: Vec<ExportedHop>,
+    pub direction: Option<bool>,
+    pub log_profit: Option<f64>,
+}
+
+pub const DEFAULT_MAX_CYCLES: usize = 1_000_000;
+// log10 units, about 0.0023%. pool fees are already inside log_profit, so this only has to
+// cover rounding in the f64 math and the transaction fee, 5_000 lamports is 0.0005% of 1 SOL
+pub const DEFAULT_MIN_LOG_PROFIT: f64 = 1e-5;
+
+fn default_max_cycles() -> usize {
+    DEFAULT_MAX_CYCLES
+}
+
+const WSOL_ADDRESS: &str = "So11111111111111111111111111111111111111112";
 
+fn default_base_tokens() -> Vec<Pubkey> {
+    vec![Pubkey::from_str(WSOL_ADDRESS).unwrap()]
+}
+
+// bump whenever the layout of Graph, Node or Edge changes
+const GRAPH_SNAPSHOT_VERSION: u32 = 6;
+
+// the cycles under each all_cycles key while a search runs, a set so repeats are dropped in
+// constant time however many cycles share a pair
+type CycleSets = HashMap<String, HashSet<Vec<usize>>>;
+
+// sorted so a build stores the same lists whatever order the searches finished in
+fn into_cycle_lists(cycles: CycleSets) -> HashMap<String, Vec<Vec<usize>>> {
+    cycles
+        .into_iter()
+        .map(|(key, cycle_set)| {
+            let mut cycle_vec: Vec<Vec<usize>> = cycle_set.into_iter().collect();
+            cycle_vec.sort();
+            (key, cycle_vec)
+        })
+        .collect()
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Graph {
     pub nodes: Vec<Node>,
     pub edges: Vec<Edge>,
 
@@ -113,15 +451,40 @@ pub struct Graph {
     address_to_edge: HashMap<Pubkey, usize>,
     adjacency: HashMap<usize, HashSet<usize>>, // adjacent pools to the token
 
+    // cycles through the base tokens, WSOL unless set otherwise or built with build_cycles_from.
+    // keyed by "{smaller mint}-{larger mint}-{cycle length}" for every pool in the cycle, the
+    // pool's two mint addresses ordered as strings, so a cycle is listed once under each pair it
+    // trades. cycles are canonical edge index lists, each a walk from the first of
+    // cycles_start_nodes it passes through
     pub all_cycles: HashMap<String, Vec<Vec<usize>>>,
+    cycles_start_nodes: Vec<usize>,
+    // the max_depth of the last build, incremental updates search to the same depth
+    cycles_max_depth: usize,
+    // the max_cycles, collapse_parallel_pools and min_liquidity of the last build, which a
+    // snapshot has to match to be reused
+    cycles_max_cycles: usize,
+    cycles_collapsed: bool,
+    cycles_min_liquidity: u128,
+
+    // the search leaves out pools known to hold less than this, so build after the liquidity
+    // is read for it to shrink the cycles. cycles through pools below it, unknown liquidity
+    // counting as 0, aren't reported either
+    #[serde(skip)]
+    min_liquidity: u128,
+    // the search stops once it has found this many unique cycles and keeps what it has
+    #[serde(skip, default = "default_max_cycles")]
+    max_cycles: usize,
+    // the search takes one pool per token pair, the deepest, instead of every fee tier
+    #[serde(skip)]
+    collapse_parallel_pools: bool,
+    // tokens build_cycles starts from, earlier ones take the cycles that pass through several
+    #[serde(skip, default = "default_base_tokens")]
+    base_tokens: Vec<Pubkey>,
 }
 
 impl Graph {
     fn default() -> Self {
         Graph {
-            wsol_address: Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap(),
-            wsol_node: usize::MAX,
-
             nodes: vec![],
             edges: vec![],
 
@@ -130,6 +493,15 @@ impl Graph {
             adjacency: HashMap::new(),
 
             all_cycles: HashMap::new(),
+            cycles_start_nodes: vec![],
+            cycles_max_depth: 0,
+            cycles_max_cycles: 0,
+            cycles_collapsed: false,
+            cycles_min_liquidity: 0,
+            min_liquidity: 0,
+            max_cycles: DEFAULT_MAX_CYCLES,
+            collapse_parallel_pools: false,
+            base_tokens: default_base_tokens(),
             // nodes_to_edges: HashMap::new(),
         }
     }
@@ -137,7 +509,12 @@ impl Graph {
 
 impl Graph {
     fn insert_node(&mut self, token: TokenInfo) -> Result<usize> {
-        let token_address = Pubkey::from_str(&token.address.unwrap())?;
+        let token_address = Pubkey::from_str(
+            token
+                .address
+                .as_deref()
+                .ok_or_else(|| anyhow!("Token is missing an address"))?,
+        )?;
 
         if let Some(&existing_index) = self.address_to_node.get(&token_address) {
             return Ok(existing_index);
@@ -145,16 +522,14 @@ impl Graph {
 
         let node = Node {
             address: token_address,
-            decimals: token.decimals.unwrap(),
+            decimals: token
+                .decimals
+                .ok_or_else(|| anyhow!("Token {} is missing decimals", token_address))?,
             name: token.name.unwrap_or("Empty Name".to_string()),
             symbol: token.symbol.unwrap_or("Empty Symbol".to_string()),
         };
         let index = self.nodes.len();
 
-        if token_address == self.wsol_address {
-            self.wsol_node = index;
-        }
-
         self.nodes.push(node);
         self.address_to_node.insert(token_address, index);
         self.adjacency.insert(index, HashSet::new());
@@ -168,306 +543,3109 @@ impl Graph {
         node0_index: usize,
         node1_index: usize,
     ) -> Result<usize> {
+        let address = Pubkey::from_str(pool.address.as_deref().unwrap_or_default())?;
+        // get_other_node and the cycle search assume every edge joins two different tokens
+        if node0_index == node1_index {
+            bail!(
+                "Pool {} has {} on both sides",
+                address,
+                self.nodes[node0_index].address
+            );
+        }
+
+        // the same pool can be listed in more than one cached file
+        if let Some(&existing_index) = self.address_to_edge.get(&address) {
+            warn!(
+                "Pool {} is already in the graph, skipping duplicate",
+                address
+            );
+            return Ok(existing_index);
+        }
+
+        let missing = |field: &str| anyhow!("Pool {} is missing {}", address, field);
+        let token_vault_a = pool.token_vault_a.ok_or_else(|| missing("token_vault_a"))?;
+        let token_vault_b = pool.token_vault_b.ok_or_else(|| missing("token_vault_b"))?;
+
         let (token_vault_lowest, token_vault_highest, idx_lowest, idx_highest, reversed) =
             if node0_index < node1_index {
                 (
-                    pool.token_vault_a.unwrap(),
-                    pool.token_vault_b.unwrap(),
+                    token_vault_a,
+                    token_vault_b,
                     node0_index,
                     node1_index,
                     false,
                 )
             } else {
-                (
-                    pool.token_vault_b.unwrap(),
-                    pool.token_vault_a.unwrap(),
-                    node1_index,
-                    node0_index,
-                    true,
-                )
+                (token_vault_b, token_vault_a, node1_index, node0_index, true)
             };
-        let address = Pubkey::from_str(&pool.address.unwrap())?;
         let edge = Edge {
             address,
-            fee_rate: pool.fee_rate.unwrap(),
-            pool_type: pool.pool_type.unwrap(),
-            dex: pool.dex.unwrap(),
-            tick_spacing: pool.tick_spacing.unwrap(),
+            fee_rate: pool.fee_rate.ok_or_else(|| missing("fee_rate"))?,
+            pool_type: pool.pool_type.ok_or_else(|| missing("pool_type"))?,
+            dex: pool.dex.ok_or_else(|| missing("dex"))?,
+            tick_spacing: pool.tick_spacing.unwrap_or_default(),
             token_vault_lowest: Pubkey::from_str(&token_vault_lowest)?,
             token_vault_highest: Pubkey::from_str(&token_vault_highest)?,
-            config: Pubkey::from_str(&pool.config.unwrap())?,
+            config: match pool.config {
+                Some(config) => Pubkey::from_str(&config)?,
+                None => Pubkey::default(),
+            },
             node_lowest: idx_lowest,
             node_highest: idx_highest,
             decimals_lowest: self.nodes[idx_lowest].decimals,
             decimals_highest: self.nodes[idx_highest].decimals,
             reversed,
+            disabled: false,
             sqrt_price: None,
             liquidity: None,
             current_tick_index: None,
+            last_updated_slot: None,
+            vault_balance_lowest: None,
+            vault_balance_highest: None,
+            token_program_lowest: TokenProgram::default(),
+            token_program_highest: TokenProgram::default(),
+            transfer_fee_bps_lowest: 0,
+            transfer_fee_bps_highest: 0,
+            ticks: BTreeMap::new(),
+            tick_coverage: None,
+        };
+
+        let index = self.edges.len();
+        self.edges.push(edge);
+        self.address_to_edge.insert(address, index);
+
+        self.adjacency.get_mut(&idx_lowest).unwrap().insert(index);
+        self.adjacency.get_mut(&idx_highest).unwrap().insert(index);
+
+        Ok(index)
+    }
+
+    fn insert_pool(&mut self, mut pool: PoolInfo) -> Result<usize> {
+        let token_a = pool
+            .token_a
+            .take()
+            .ok_or_else(|| anyhow!("Pool is missing token_a"))?;
+        let token_b = pool
+            .token_b
+            .take()
+            .ok_or_else(|| anyhow!("Pool is missing token_b"))?;
+
+        let node0_index = self.insert_node(token_a)?;
+        let node1_index = self.insert_node(token_b)?;
+
+        self.insert_edge(pool, node0_index, node1_index)
+    }
+
+    // updates read at an older slot than the edge's last one are rejected, so a late websocket
+    // notification can't overwrite fresher RPC data
+    pub fn update_edge(&mut self, address: &Pubkey, data: PoolUpdate) -> Result<()> {
+        if let Some(edge_index) = self.address_to_edge.get(address)
+            && let Some(edge) = self.edges.get_mut(*edge_index)
+        {
+            if edge.disabled {
+                return Ok(());
+            }
+            if let Some(last_updated_slot) = edge.last_updated_slot
+                && data.slot < last_updated_slot
+            {
+                bail!(
+                    "Update for {} from slot {} is older than slot {}",
+                    address,
+                    data.slot,
+                    last_updated_slot
+                );
+            }
+            edge.last_updated_slot = Some(data.slot);
+            edge.liquidity = Some(data.new_liquidity);
+            edge.sqrt_price = Some(data.new_sqrt_price);
+            edge.current_tick_index = Some(data.new_current_tick_index);
+            if let Some(fee_rate) = data.fee_rate {
+                edge.fee_rate = fee_rate;
+            }
+            // cached ticks were read at the old spacing, their indexes no longer line up
+            if let Some(tick_spacing) = data.tick_spacing
+                && tick_spacing as u64 != edge.tick_spacing
+            {
+                edge.tick_spacing = tick_spacing as u64;
+                edge.ticks.clear();
+                edge.tick_coverage = None;
+            }
+            return Ok(());
+        }
+        Err(anyhow!("Edge with address {} doesn't exist", address))
+    }
+
+    // applies every update it can and hands back the ones that failed
+    pub fn update_edges(
+        &mut self,
+        updates: impl IntoIterator<Item = (Pubkey, PoolUpdate)>,
+    ) -> Vec<(Pubkey, anyhow::Error)> {
+        updates
+            .into_iter()
+            .filter_map(|(address, data)| {
+                self.update_edge(&address, data).err().map(|e| (address, e))
+            })
+            .collect()
+    }
+
+    pub fn vault_addresses(&self) -> Vec<Pubkey> {
+        self.edges
+            .iter()
+            .flat_map(|edge| [edge.token_vault_lowest, edge.token_vault_highest])
+            .collect()
+    }
+
+    pub fn update_vaults(&mut self, vaults: &HashMap<Pubkey, TokenAccount>) {
+        for edge in &mut self.edges {
+            if let Some(vault) = vaults.get(&edge.token_vault_lowest) {
+                edge.vault_balance_lowest = Some(vault.amount);
+                edge.token_program_lowest = vault.program;
+            }
+            if let Some(vault) = vaults.get(&edge.token_vault_highest) {
+                edge.vault_balance_highest = Some(vault.amount);
+                edge.token_program_highest = vault.program;
+            }
+        }
+    }
+
+    // transfer fee bps keyed by mint, mints missing from the map keep their last known fee
+    pub fn update_transfer_fees(&mut self, fees: &HashMap<Pubkey, u16>) {
+        for edge in &mut self.edges {
+            if let Some(&bps) = fees.get(&self.nodes[edge.node_lowest].address) {
+                edge.transfer_fee_bps_lowest = bps;
+            }
+            if let Some(&bps) = fees.get(&self.nodes[edge.node_highest].address) {
+                edge.transfer_fee_bps_highest = bps;
+            }
+        }
+    }
+
+    // the Orca tick arrays a swap either way would touch around the current tick, with the
+    // pool's tick spacing, which decoding them needs
+    pub fn tick_array_addresses(&self) -> HashMap<Pubkey, u16> {
+        let mut addresses = HashMap::new();
+        for edge in &self.edges {
+            let (Some(current_tick_index), Ok(tick_spacing)) =
+                (edge.current_tick_index, u16::try_from(edge.tick_spacing))
+            else {
+                continue;
+            };
+            if edge.dex != DexType::Orca || tick_spacing == 0 {
+                continue;
+            }
+            for a_to_b in [true, false] {
+                for address in swap::tick_array_addresses(
+                    &edge.address,
+                    tick_spacing,
+                    current_tick_index,
+                    a_to_b,
+                ) {
+                    addresses.insert(address, tick_spacing);
+                }
+            }
+        }
+        addresses
+    }
+
+    // arrays for pools that aren't in the graph are ignored
+    pub fn update_tick_arrays(&mut self, tick_arrays: &[TickArray]) {
+        for tick_array in tick_arrays {
+            if let Some(&edge_index) = self.address_to_edge.get(&tick_array.whirlpool) {
+                self.edges[edge_index].apply_tick_array(tick_array);
+            }
+        }
+    }
+
+    // keeps the pool out of the cycle search and the scans until it is enabled again. cycles
+    // already built through it stay stored but aren't scanned
+    pub fn disable_edge(&mut self, address: &Pubkey) -> Result<()> {
+        self.set_edge_disabled(address, true)
+    }
+
+    pub fn enable_edge(&mut self, address: &Pubkey) -> Result<()> {
+        self.set_edge_disabled(address, false)
+    }
+
+    fn set_edge_disabled(&mut self, address: &Pubkey, disabled: bool) -> Result<()> {
+        let edge_index = *self
+            .address_to_edge
+            .get(address)
+            .ok_or_else(|| anyhow!("Edge with address {} doesn't exist", address))?;
+        self.edges[edge_index].disabled = disabled;
+        Ok(())
+    }
+
+    // moves the pool price as an observed swap would, until the next account refresh overwrites it
+    pub fn apply_swap(&mut self, address: &Pubkey, amount_in: u64, a_to_b: bool) -> Result<()> {
+        let edge_index = *self
+            .address_to_edge
+            .get(address)
+            .ok_or_else(|| anyhow!("Edge with address {} doesn't exist", address))?;
+        let edge = &mut self.edges[edge_index];
+        // like update_edge, a disabled pool keeps the state it had when it was disabled
+        if edge.disabled {
+            return Ok(());
+        }
+
+        let (_, new_sqrt_price) = edge
+            .swap_within_tick(amount_in as f64, a_to_b)
+            .ok_or_else(|| anyhow!("Edge with address {} isn't priced yet", address))?;
+
+        edge.sqrt_price = Some((new_sqrt_price * 2f64.powi(64)) as u128);
+        // price = 1.0001^tick
+        edge.current_tick_index = Some((2.0 * new_sqrt_price.ln() / 1.0001f64.ln()).floor() as i32);
+        Ok(())
+    }
+
+    pub fn build_graph(data_folder_path: &str) -> Result<Self> {
+        let pool_files = get_all_pool_files(data_folder_path)?;
+        let span = info_span!(
+            "build_graph",
+            data_folder_path,
+            pool_files = pool_files.len(),
+            nodes = field::Empty,
+            edges = field::Empty,
+            skipped_files = field::Empty,
+        )
+        .entered();
+
+        let mut graph = Graph::default();
+        let mut skipped_files = 0;
+        for pool_path in pool_files {
+            let reader = BufReader::new(File::open(&pool_path)?);
+
+            // a setup interrupted mid-write leaves a truncated file. the pools read before the
+            // error stay in the graph and the other files are still read
+            let parsed = for_each_stored_pool(reader, |pool| {
+                if let Err(e) = graph.insert_pool(pool) {
+                    warn!("Failed to insert the pool: {:?}", e);
+                }
+            });
+            if let Err(e) = parsed {
+                warn!("Skipping the rest of {}: {}", pool_path.display(), e);
+                skipped_files += 1;
+            }
+        }
+        if skipped_files > 0 {
+            warn!("{} pool files were malformed", skipped_files);
+        }
+
+        span.record("nodes", graph.nodes.len());
+        span.record("edges", graph.edges.len());
+        span.record("skipped_files", skipped_files);
+        Ok(graph)
+    }
+
+    // (node_lowest, node_highest) symbols of the pool
+    pub fn edge_pair_symbols(&self, edge_index: usize) -> Option<(&str, &str)> {
+        let edge = self.edges.get(edge_index)?;
+        Some((
+            &self.nodes[edge.node_lowest].symbol,
+            &self.nodes[edge.node_highest].symbol,
+        ))
+    }
+
+    // token symbols along the cycle walked forward from its base token, ending back on it.
+    // None if the cycle isn't a walk from a base token
+    pub fn cycle_symbols(&self, cycle: &[usize]) -> Option<Vec<&str>> {
+        let mut current_node = self.cycle_start(cycle)?;
+        let mut symbols = vec![self.nodes[current_node].symbol.as_str()];
+        for &edge_index in cycle {
+            current_node = self.edges.get(edge_index)?.get_other_node(current_node)?;
+            symbols.push(&self.nodes[current_node].symbol);
+        }
+        Some(symbols)
+    }
+
+    // e.g. "WSOL -> USDC -> RAY -> WSOL"
+    pub fn describe_cycle(&self, cycle: &[usize]) -> String {
+        match self.cycle_symbols(cycle) {
+            Some(symbols) => symbols.join(" -> "),
+            // not a walk from the start, the edge indexes are all there is to show
+            None => format!("{:?}", cycle),
+        }
+    }
+
+    pub fn stats(&self) -> GraphStats {
+        let degrees = self.adjacency.values().map(HashSet::len);
+        let unique_cycles: HashSet<&Vec<usize>> = self.all_cycles.values().flatten().collect();
+
+        GraphStats {
+            nodes: self.nodes.len(),
+            edges: self.edges.len(),
+            cycles: unique_cycles.len(),
+            avg_degree: match self.nodes.len() {
+                0 => 0.0,
+                nodes => degrees.clone().sum::<usize>() as f64 / nodes as f64,
+            },
+            max_degree: degrees.max().unwrap_or(0),
+            priced_edges: self
+                .edges
+                .iter()
+                .filter(|edge| edge.sqrt_price.is_some())
+                .count(),
+        }
+    }
+
+    // fraction of pools that have received a PoolUpdate, 0 for an empty graph
+    pub fn coverage(&self) -> f64 {
+        match self.edges.len() {
+            0 => 0.0,
+            edges => {
+                self.edges.iter().filter(|edge| edge.is_priced()).count() as f64 / edges as f64
+            }
+        }
+    }
+
+    // pools still waiting for their first PoolUpdate
+    pub fn stale_edges(&self) -> Vec<Pubkey> {
+        self.edges
+            .iter()
+            .filter(|edge| !edge.is_priced())
+            .map(|edge| edge.address)
+            .collect()
+    }
+
+    pub fn node_index(&self, address: &Pubkey) -> Option<usize> {
+        self.address_to_node.get(address).copied()
+    }
+
+    pub fn edge_index(&self, address: &Pubkey) -> Option<usize> {
+        self.address_to_edge.get(address).copied()
+    }
+
+    /// Tokens one enabled pool away from `node`, each listed once however many pools trade
+    /// the pair, and none for an index outside the graph. With `node_index` and
+    /// `edges_between` it is enough for a path search of your own:
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    ///
+    /// use client::graph::Graph;
+    /// use solana_sdk::pubkey::Pubkey;
+    ///
+    /// let graph = Graph::build_graph("./tests/test_data").unwrap();
+    /// let wsol: Pubkey = "So11111111111111111111111111111111111111112"
+    ///     .parse()
+    ///     .unwrap();
+    /// let start = graph.node_index(&wsol).unwrap();
+    ///
+    /// // every token within two hops of WSOL, breadth first
+    /// let mut seen = HashSet::from([start]);
+    /// let mut frontier = vec![start];
+    /// for _ in 0..2 {
+    ///     frontier = frontier
+    ///         .iter()
+    ///         .flat_map(|&node| graph.neighbors(node))
+    ///         .filter(|&node| seen.insert(node))
+    ///         .collect();
+    /// }
+    ///
+    /// assert!(graph.neighbors(start).all(|node| seen.contains(&node)));
+    /// assert!(seen.len() > graph.neighbors(start).count() + 1);
+    /// ```
+    pub fn neighbors(&self, node: usize) -> impl Iterator<Item = usize> {
+        let neighbors: HashSet<usize> = self
+            .adjacency
+            .get(&node)
+            .into_iter()
+            .flatten()
+            .filter(|&&edge_index| !self.edges[edge_index].disabled)
+            .filter_map(|&edge_index| self.edges[edge_index].get_other_node(node))
+            .collect();
+        neighbors.into_iter()
+    }
+
+    pub fn has_edge(&self, address: &Pubkey) -> bool {
+        self.address_to_edge.contains_key(address)
+    }
+
+    // every pool address in the graph, for membership checks that don't hold on to the graph
+    pub fn pool_addresses(&self) -> HashSet<Pubkey> {
+        self.address_to_edge.keys().copied().collect()
+    }
+
+    // indices of every pool trading the a/b pair, empty for unknown tokens or a == b
+    pub fn edges_between(&self, a: &Pubkey, b: &Pubkey) -> Vec<usize> {
+        let (Some(node_a), Some(node_b)) =
+            (self.address_to_node.get(a), self.address_to_node.get(b))
+        else {
+            return Vec::new();
         };
+        if node_a == node_b {
+            return Vec::new();
+        }
+
+        let mut edges: Vec<usize> = self.adjacency[node_a]
+            .intersection(&self.adjacency[node_b])
+            .copied()
+            .collect();
+        edges.sort_unstable();
+        edges
+    }
+
+    // WSOL per whole token along the fewest priced hops to WSOL, taking the deepest pool where
+    // hops of the same length compete. mid prices, pool fees aren't taken out. None for unknown
+    // tokens and when no path of priced, enabled pools reaches WSOL
+    pub fn price_in_wsol(&self, token: &Pubkey) -> Option<f64> {
+        let start = self.node_index(token)?;
+        let wsol = self.node_index(&Pubkey::from_str(WSOL_ADDRESS).unwrap())?;
+
+        // breadth first, so the edge a node is first reached through lies on a shortest path
+        let mut reached_by: HashMap<usize, usize> = HashMap::new();
+        let mut visited = HashSet::from([start]);
+        let mut frontier = vec![start];
+        while !frontier.is_empty() && !visited.contains(&wsol) {
+            let mut layer: HashMap<usize, usize> = HashMap::new();
+            for &node in &frontier {
+                for &edge_index in self.adjacency.get(&node).into_iter().flatten() {
+                    let edge = &self.edges[edge_index];
+                    if edge.disabled || !edge.is_priced() {
+                        continue;
+                    }
+                    let Some(other_node) = edge.get_other_node(node) else {
+                        continue;
+                    };
+                    if visited.contains(&other_node) {
+                        continue;
+                    }
+                    // ties go to the lower index so the path doesn't depend on hash order
+                    let depth = |index: usize| (self.edges[index].liquidity, Reverse(index));
+                    layer
+                        .entry(other_node)
+                        .and_modify(|best| {
+                            if depth(edge_index) > depth(*best) {
+                                *best = edge_index;
+                            }
+                        })
+                        .or_insert(edge_index);
+                }
+            }
+
+            frontier = layer.keys().copied().collect();
+            visited.extend(layer.keys());
+            reached_by.extend(layer);
+        }
+
+        // walked back from WSOL, each hop priced in the direction the token is sold
+        let mut price = 1.0;
+        let mut node = wsol;
+        while node != start {
+            let edge = &self.edges[*reached_by.get(&node)?];
+            let previous_node = edge.get_other_node(node)?;
+            price *= edge.get_exchange_rate(previous_node == edge.node_lowest)?;
+            node = previous_node;
+        }
+        Some(price)
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(&mut writer, &GRAPH_SNAPSHOT_VERSION)?;
+        bincode::serialize_into(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let version: u32 = bincode::deserialize_from(&mut reader)?;
+        if version != GRAPH_SNAPSHOT_VERSION {
+            return Err(anyhow!(
+                "Graph snapshot version {} doesn't match expected version {}",
+                version,
+                GRAPH_SNAPSHOT_VERSION
+            ));
+        }
+        Ok(bincode::deserialize_from(&mut reader)?)
+    }
+
+    // every unique cycle once, sorted so exports from different runs diff cleanly
+    pub fn export_cycles(&self, path: &str) -> Result<()> {
+        let mut unique_cycles: Vec<&Vec<usize>> = self
+            .all_cycles
+            .values()
+            .flatten()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        unique_cycles.sort();
+
+        let mut exported = Vec::with_capacity(unique_cycles.len());
+        for cycle in unique_cycles {
+            let mut current_node = self
+                .cycle_start(cycle)
+                .with_context(|| format!("Cycle {:?} doesn't pass a base token", cycle))?;
+            let mut hops = Vec::with_capacity(cycle.len());
+            for &edge_index in cycle {
+                let edge = &self.edges[edge_index];
+                let next_node = edge.get_other_node(current_node).with_context(|| {
+                    format!("Cycle {:?} isn't a walk from the start token", cycle)
+                })?;
+                hops.push(ExportedHop {
+                    pool: edge.address.to_string(),
+                    token_in: self.nodes[current_node].symbol.clone(),
+                    token_out: self.nodes[next_node].symbol.clone(),
+                    fee_rate: edge.fee_rate,
+                });
+                current_node = next_node;
+            }
+
+            let evaluation = self.evaluate_cycle(cycle);
+            exported.push(ExportedCycle {
+                edges: cycle.clone(),
+                hops,
+                direction: evaluation.map(|(direction, _)| direction),
+                log_profit: evaluation.map(|(_, log_profit)| log_profit),
+            });
+        }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &exported)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn set_min_liquidity(&mut self, min_liquidity: u128) {
+        self.min_liquidity = min_liquidity;
+    }
+
+    pub fn set_max_cycles(&mut self, max_cycles: usize) {
+        self.max_cycles = max_cycles;
+    }
+
+    // cycles then go through one pool per pair, the one to trade can be picked again at
+    // execution time
+    pub fn set_collapse_parallel_pools(&mut self, collapse_parallel_pools: bool) {
+        self.collapse_parallel_pools = collapse_parallel_pools;
+    }
+
+    // in priority order, a cycle through several of them is anchored at the first
+    pub fn set_base_tokens(&mut self, base_tokens: Vec<Pubkey>) {
+        self.base_tokens = base_tokens;
+    }
+
+    // pools the cycle search leaves out. a pool whose liquidity hasn't been read yet stays in,
+    // is_tradable keeps it out of the scans until it is
+    fn is_skipped(&self, edge_index: usize) -> bool {
+        let edge = &self.edges[edge_index];
+        edge.disabled
+            || edge
+                .liquidity
+                .is_some_and(|liquidity| liquidity < self.min_liquidity)
+    }
+
+    // the edge bitmap every search starts from. collapsing parallel pools marks all but the
+    // deepest pool of each pair as used, the lowest fee and then the lowest index break ties
+    fn search_bitmap(&self) -> Vec<bool> {
+        if !self.collapse_parallel_pools {
+            return vec![false; self.edges.len()];
+        }
+
+        let depth = |edge: &Edge| (edge.liquidity.unwrap_or(0), Reverse(edge.fee_rate));
+        let mut deepest: HashMap<(usize, usize), usize> = HashMap::new();
+        for (edge_index, edge) in self.edges.iter().enumerate() {
+            if self.is_skipped(edge_index) {
+                continue;
+            }
+            deepest
+                .entry((edge.node_lowest, edge.node_highest))
+                .and_modify(|best_index| {
+                    if depth(edge) > depth(&self.edges[*best_index]) {
+                        *best_index = edge_index;
+                    }
+                })
+                .or_insert(edge_index);
+        }
+
+        let mut bitmap = vec![true; self.edges.len()];
+        for edge_index in deepest.into_values() {
+            bitmap[edge_index] = false;
+        }
+        bitmap
+    }
+
+    // base tokens without a pool have no cycles to anchor
+    fn base_token_nodes(&self) -> Vec<usize> {
+        self.base_tokens
+            .iter()
+            .filter_map(|token| self.address_to_node.get(token).copied())
+            .collect()
+    }
+
+    // whether the stored cycles are what build_cycles would find with the current settings, a
+    // loaded snapshot built with another depth, cap, base tokens, collapse or minimum liquidity
+    // has to be rebuilt
+    pub fn has_cycles_for(&self, max_depth: usize) -> bool {
+        !self.cycles_start_nodes.is_empty()
+            && self.cycles_start_nodes == self.base_token_nodes()
+            && self.cycles_max_depth == max_depth
+            && self.cycles_max_cycles == self.max_cycles
+            && self.cycles_collapsed == self.collapse_parallel_pools
+            && self.cycles_min_liquidity == self.min_liquidity
+    }
+
+    pub fn build_cycles(&mut self, max_depth: usize) -> Result<()> {
+        let start_nodes = self.base_token_nodes();
+        if start_nodes.is_empty() {
+            bail!(
+                "No pool in the graph trades a base token ({:?}), there is no start for the cycles",
+                self.base_tokens
+            );
+        }
+        self.build_cycles_from_nodes(start_nodes, max_depth)
+    }
+
+    pub fn build_cycles_from(&mut self, start_token: Pubkey, max_depth: usize) -> Result<()> {
+        let start_node = *self
+            .address_to_node
+            .get(&start_token)
+            .ok_or_else(|| anyhow!("Token {} is not in the graph", start_token))?;
+        self.build_cycles_from_nodes(vec![start_node], max_depth)
+    }
+
+    fn build_cycles_from_nodes(&mut self, start_nodes: Vec<usize>, max_depth: usize) -> Result<()> {
+        let start_tokens: Vec<Pubkey> = start_nodes
+            .iter()
+            .map(|&node| self.nodes[node].address)
+            .collect();
+        let span = info_span!(
+            "build_cycles",
+            ?start_tokens,
+            max_depth,
+            cycles = field::Empty,
+        )
+        .entered();
+
+        self.all_cycles = self.find_cycles_parallel(&start_nodes, max_depth);
+        self.cycles_start_nodes = start_nodes;
+        self.cycles_max_depth = max_depth;
+        self.cycles_max_cycles = self.max_cycles;
+        self.cycles_collapsed = self.collapse_parallel_pools;
+        self.cycles_min_liquidity = self.min_liquidity;
+
+        let cycles = self.stats().cycles;
+        span.record("cycles", cycles);
+        metrics::CYCLES.set(cycles as i64);
+        if cycles >= self.max_cycles {
+            warn!(
+                "Cycle search stopped at the cap of {} cycles, results are partial",
+                self.max_cycles
+            );
+        }
+
+        Ok(())
+    }
+
+    // adds the pool and the cycles through it without redoing the whole search. only closed
+    // walks out over the new pool are searched, each stored under the first start node it
+    // passes like a full build. the result matches a rebuild unless the search hits max_cycles
+    // or the pool brings in a base token that had no pools at the last build
+    pub fn add_pool_incremental(&mut self, pool: PoolInfo) -> Result<()> {
+        let edge_index = self.insert_pool(pool)?;
+        // nothing to maintain before the first build_cycles
+        if self.cycles_start_nodes.is_empty() || self.is_skipped(edge_index) {
+            return Ok(());
+        }
+        // the new pool can take over its pair, which changes cycles that don't touch it
+        if self.collapse_parallel_pools {
+            return self
+                .build_cycles_from_nodes(self.cycles_start_nodes.clone(), self.cycles_max_depth);
+        }
+
+        let edge = &self.edges[edge_index];
+        let (from_node, to_node) = (edge.node_lowest, edge.node_highest);
+        let mut visited_edges: Vec<bool> = vec![false; self.edges.len()]; // bitmap
+        visited_edges[edge_index] = true;
+        let mut path = vec![edge_index];
+        let mut cycles = CycleSets::new();
+
+        self.dfs_iterative(
+            from_node,
+            to_node,
+            &[],
+            &mut visited_edges,
+            &mut path,
+            self.cycles_max_depth,
+            &mut |path| {
+                // a full build never finds a walk that misses every start node either
+                let Some(start_node) = self.cycle_start(path) else {
+                    return false;
+                };
+                let canonical =
+                    Self::canonicalize(path, |candidate| self.is_walk_from(candidate, start_node))
+                        .unwrap();
+                self.record_cycle(&canonical, start_node, &mut cycles)
+            },
+            &AtomicUsize::new(0),
+        );
+
+        for (key, cycle_vec) in into_cycle_lists(cycles) {
+            let merged_vec = self.all_cycles.entry(key).or_default();
+            let known: HashSet<&Vec<usize>> = merged_vec.iter().collect();
+            let new_cycles: Vec<Vec<usize>> = cycle_vec
+                .into_iter()
+                .filter(|cycle| !known.contains(cycle))
+                .collect();
+            merged_vec.extend(new_cycles);
+        }
+        metrics::CYCLES.set(self.stats().cycles as i64);
+        Ok(())
+    }
+
+    // disables the pool and drops the cycles through it. the edge stays in place so the indexes
+    // in the other cycles stay valid, enable_edge and a build_cycles bring it back
+    pub fn remove_pool_incremental(&mut self, address: &Pubkey) -> Result<()> {
+        self.disable_edge(address)?;
+        let edge_index = self.address_to_edge[address];
+
+        self.all_cycles.retain(|_, cycle_vec| {
+            cycle_vec.retain(|cycle| !cycle.contains(&edge_index));
+            !cycle_vec.is_empty()
+        });
+        metrics::CYCLES.set(self.stats().cycles as i64);
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn find_cycles_serial(
+        &self,
+        start_nodes: &[usize],
+        max_depth: usize,
+    ) -> HashMap<String, Vec<Vec<usize>>> {
+        let mut visited_edges: Vec<bool> = self.search_bitmap();
+        let mut path: Vec<usize> = Vec::with_capacity(max_depth);
+        let mut cycles = CycleSets::new();
+        let found_cycles = AtomicUsize::new(0);
+
+        for (index, &start_node) in start_nodes.iter().enumerate() {
+            self.dfs_iterative(
+                start_node,
+                start_node,
+                &start_nodes[..index],
+                &mut visited_edges,
+                &mut path,
+                max_depth,
+                &mut |path| self.record_cycle(path, start_node, &mut cycles),
+                &found_cycles,
+            );
+        }
+
+        into_cycle_lists(cycles)
+    }
+
+    // every first hop out of each start node is explored by its own rayon task with its own
+    // bitmap. a search never enters the start nodes before its own, the cycles through them
+    // belong to the earlier search
+    fn find_cycles_parallel(
+        &self,
+        start_nodes: &[usize],
+        max_depth: usize,
+    ) -> HashMap<String, Vec<Vec<usize>>> {
+        if max_depth == 0 {
+            return HashMap::new();
+        }
+
+        let bitmap = self.search_bitmap();
+        let first_edges: Vec<(usize, usize)> = start_nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(index, &start_node)| {
+                let (skipped_nodes, bitmap) = (&start_nodes[..index], &bitmap);
+                self.adjacency[&start_node]
+                    .iter()
+                    .copied()
+                    .filter(move |&edge_index| {
+                        let other_node = self.edges[edge_index].get_other_node(start_node);
+                        !bitmap[edge_index]
+                            && !self.is_skipped(edge_index)
+                            && other_node.is_some_and(|node| !skipped_nodes.contains(&node))
+                    })
+                    .map(move |edge_index| (index, edge_index))
+            })
+            .collect();
+        let found_cycles = AtomicUsize::new(0);
+
+        let cycles = first_edges
+            .par_iter()
+            .map(|&(index, edge_index)| {
+                let start_node = start_nodes[index];
+                let mut visited_edges: Vec<bool> = bitmap.clone();
+                let mut path: Vec<usize> = Vec::with_capacity(max_depth);
+                let mut cycles = CycleSets::new();
+
+                // a single edge can't close a cycle, so the search continues from its other end
+                let other_node = self.edges[edge_index].get_other_node(start_node).unwrap();
+                visited_edges[edge_index] = true;
+                path.push(edge_index);
+
+                self.dfs_iterative(
+                    start_node,
+                    other_node,
+                    &start_nodes[..index],
+                    &mut visited_edges,
+                    &mut path,
+                    max_depth,
+                    &mut |path| self.record_cycle(path, start_node, &mut cycles),
+                    &found_cycles,
+                );
+
+                cycles
+            })
+            .reduce(CycleSets::new, |mut merged, cycles| {
+                for (key, cycle_set) in cycles {
+                    merged.entry(key).or_default().extend(cycle_set);
+                }
+                merged
+            });
+        into_cycle_lists(cycles)
+    }
+
+    // explicit stack instead of recursion, so a deep max_depth can't overflow the thread
+    // stack. every closed walk back to start_node goes to record, which returns true when it
+    // stored a new cycle. found_cycles is shared between searches and ends all of them at
+    // max_cycles
+    #[allow(clippy::too_many_arguments)]
+    fn dfs_iterative(
+        &self,
+        start_node: usize,
+        current_node: usize,
+        skipped_nodes: &[usize],
+        visited_edges: &mut [bool],
+        path: &mut Vec<usize>,
+        max_depth: usize,
+        record: &mut impl FnMut(&[usize]) -> bool,
+        found_cycles: &AtomicUsize,
+    ) {
+        if path.len() >= max_depth {
+            return;
+        }
+
+        let mut stack = vec![(current_node, self.adjacency[&current_node].iter())];
+        while let Some((node, edges)) = stack.last_mut() {
+            if found_cycles.load(Ordering::Relaxed) >= self.max_cycles {
+                break;
+            }
+
+            let node = *node;
+            let Some(&edge_index) = edges.next() else {
+                stack.pop();
+                // every frame but the first was entered over the last edge on the path
+                if !stack.is_empty() {
+                    let edge_index = path.pop().unwrap();
+                    visited_edges[edge_index] = false;
+                }
+                continue;
+            };
+            if visited_edges[edge_index] || self.is_skipped(edge_index) {
+                continue;
+            }
+
+            let other_node = self.edges[edge_index].get_other_node(node).unwrap();
+            if skipped_nodes.contains(&other_node) {
+                continue;
+            }
+            visited_edges[edge_index] = true;
+            path.push(edge_index);
+
+            if other_node == start_node && path.len() >= 2 && record(path) {
+                found_cycles.fetch_add(1, Ordering::Relaxed);
+            }
+
+            if path.len() < max_depth {
+                stack.push((other_node, self.adjacency[&other_node].iter()));
+            } else {
+                path.pop();
+                visited_edges[edge_index] = false;
+            }
+        }
+
+        // stopping early leaves the edges into the remaining frames on the path
+        for _ in 1..stack.len() {
+            let edge_index = path.pop().unwrap();
+            visited_edges[edge_index] = false;
+        }
+    }
+
+    // the search walks every cycle once per direction and rotation through start_node. only the
+    // traversal that equals the canonical form stores it, under every pair it trades, so each
+    // cycle is stored and counted once across all searches. true when it was stored
+    fn record_cycle(&self, path: &[usize], start_node: usize, cycles: &mut CycleSets) -> bool {
+        // the path itself is a walk out of start_node, so a candidate always exists
+        let canonical =
+            Self::canonicalize(path, |candidate| self.is_walk_from(candidate, start_node)).unwrap();
+        if path != canonical {
+            return false;
+        }
+
+        for &pool_index in &canonical {
+            let key = self.cycle_key(pool_index, canonical.len());
+            // a cycle trading the same pair twice is still listed once under it
+            cycles.entry(key).or_default().insert(canonical.clone());
+        }
+
+        true
+    }
+
+    // the all_cycles key of the pool's token pair for cycles of this length
+    fn cycle_key(&self, edge_index: usize, cycle_length: usize) -> String {
+        let edge = &self.edges[edge_index];
+        let addr_low = self.nodes[edge.node_lowest].address.to_string();
+        let addr_high = self.nodes[edge.node_highest].address.to_string();
+
+        // Ensure lexicographic ordering
+        let (left, right) = if addr_low < addr_high {
+            (addr_low, addr_high)
+        } else {
+            (addr_high, addr_low)
+        };
+        format!("{}-{}-{}", left, right, cycle_length)
+    }
+
+    // smallest rotation or reflection of the cycle accepted by is_valid, so every traversal of
+    // the same closed walk maps to one form. None if no candidate is valid
+    fn canonicalize(cycle: &[usize], is_valid: impl Fn(&[usize]) -> bool) -> Option<Vec<usize>> {
+        if cycle.is_empty() {
+            return Some(Vec::new());
+        }
+
+        let reversed: Vec<usize> = cycle.iter().rev().copied().collect();
+        [cycle, &reversed]
+            .into_iter()
+            .flat_map(|orientation| {
+                (0..orientation.len()).map(move |shift| {
+                    let mut candidate = orientation.to_vec();
+                    candidate.rotate_left(shift);
+                    candidate
+                })
+            })
+            .filter(|candidate| is_valid(candidate))
+            .min()
+    }
+
+    fn is_walk_from(&self, cycle: &[usize], start_node: usize) -> bool {
+        let mut current_node = start_node;
+        for &edge_index in cycle {
+            match self.edges[edge_index].get_other_node(current_node) {
+                Some(other_node) => current_node = other_node,
+                None => return false,
+            }
+        }
+        current_node == start_node
+    }
+
+    // the base token the cycle is anchored at, the first start node any of its pools trades
+    fn cycle_start(&self, cycle: &[usize]) -> Option<usize> {
+        self.cycles_start_nodes.iter().copied().find(|&node| {
+            cycle.iter().any(|&edge_index| {
+                self.edges
+                    .get(edge_index)
+                    .is_some_and(|edge| edge.get_other_node(node).is_some())
+            })
+        })
+    }
+
+    #[inline]
+    pub fn check_cycle(&self, cycle: &mut [usize]) -> bool {
+        let Some(start_node) = self.cycle_start(cycle) else {
+            return false;
+        };
+        let cycle_len = cycle.len();
+        let mut need_change = false;
+        let mut last_node: usize = start_node;
+        let mut problematic_edge_index: usize = cycle_len; // set to unreal index
+
+        for (index, pool) in cycle.iter().enumerate() {
+            let edge = &self.edges[*pool];
+            match edge.get_other_node(last_node) {
+                Some(other_node) => last_node = other_node,
+                None => {
+                    need_change = true;
+                    problematic_edge_index = index;
+                    break;
+                }
+            }
+        }
+        if !need_change && last_node != start_node {
+            problematic_edge_index = cycle_len - 1;
+            need_change = true;
+            metrics::MISORIENTED_CYCLES.inc();
+        }
+
+        if need_change {
+            // info!(%problematic_edge_index, "Wrong Edge Index");
+            // println!("Cycle before rotation: {:?}", &cycle);
+            if problematic_edge_index < cycle_len && problematic_edge_index > 0 {
+                cycle.rotate_left(1);
+            } else if problematic_edge_index == 0 {
+                cycle.rotate_left(cycle_len - 1);
+            }
+            // println!("Cycle after rotation: {:?}", &cycle);
+        }
+
+        need_change
+    }
+
+    // log10 of the fee-adjusted round trip rate, None if any edge isn't priced yet
+    fn cycle_log_profit(&self, cycle: &[usize], direction: bool) -> Option<f64> {
+        let mut current_node = self.cycle_start(cycle)?;
+        let mut log_profit = 0.0;
+
+        let mut trade = |edge_index: usize| -> Option<()> {
+            let edge = &self.edges[edge_index];
+            log_profit += edge.get_log_exchange_rate(current_node == edge.node_lowest, true)?;
+            current_node = edge.get_other_node(current_node)?;
+            Some(())
+        };
+        if direction {
+            cycle.iter().try_for_each(|&edge_index| trade(edge_index))?;
+        } else {
+            cycle
+                .iter()
+                .rev()
+                .try_for_each(|&edge_index| trade(edge_index))?;
+        }
+
+        Some(log_profit)
+    }
+
+    // the more profitable direction of the cycle and its log profit, None if any pool is unpriced
+    pub fn evaluate_cycle(&self, cycle: &[usize]) -> Option<(bool, f64)> {
+        let forward = self.cycle_log_profit(cycle, true)?;
+        let backward = self.cycle_log_profit(cycle, false)?;
+
+        match forward >= backward {
+            true => Some((true, forward)),
+            false => Some((false, backward)),
+        }
+    }
+
+    // cycles whose better direction has a log profit above min_log_profit, most profitable first
+    pub fn find_arbitrage_cycles(&self, min_log_profit: f64) -> Vec<ArbitrageOpportunity> {
+        let span =
+            info_span!("scan", cycles = field::Empty, opportunities = field::Empty,).entered();
+
+        // every cycle is stored once per token pair it touches
+        let unique_cycles: HashSet<&Vec<usize>> = self
+            .all_cycles
+            .values()
+            .flatten()
+            .filter(|cycle| self.is_tradable(cycle))
+            .collect();
+        span.record("cycles", unique_cycles.len());
+
+        let opportunities = self.opportunities_in(unique_cycles, min_log_profit);
+        span.record("opportunities", opportunities.len());
+        opportunities
+    }
+
+    // the same for the cycles through one pool, read from its pair's entries so a price update
+    // doesn't have to walk every cycle. empty for pools the graph doesn't know
+    pub fn find_arbitrage_cycles_through(
+        &self,
+        address: &Pubkey,
+        min_log_profit: f64,
+    ) -> Vec<ArbitrageOpportunity> {
+        let Some(&edge_index) = self.address_to_edge.get(address) else {
+            return Vec::new();
+        };
+
+        let cycles: HashSet<&Vec<usize>> = (1..=self.cycles_max_depth)
+            .filter_map(|length| self.all_cycles.get(&self.cycle_key(edge_index, length)))
+            .flatten()
+            .filter(|cycle| cycle.contains(&edge_index) && self.is_tradable(cycle))
+            .collect();
+        self.opportunities_in(cycles, min_log_profit)
+    }
+
+    fn is_tradable(&self, cycle: &[usize]) -> bool {
+        cycle.iter().all(|&index| {
+            let edge = &self.edges[index];
+            !edge.disabled
+                && !edge.has_empty_vault()
+                && edge.liquidity.unwrap_or(0) >= self.min_liquidity
+        })
+    }
+
+    fn opportunities_in(
+        &self,
+        cycles: HashSet<&Vec<usize>>,
+        min_log_profit: f64,
+    ) -> Vec<ArbitrageOpportunity> {
+        let mut opportunities: Vec<ArbitrageOpportunity> = cycles
+            .into_iter()
+            .filter_map(|cycle| {
+                let (direction, log_profit) = self.evaluate_cycle(cycle)?;
+                (log_profit > min_log_profit).then(|| ArbitrageOpportunity {
+                    cycle: cycle.clone(),
+                    direction,
+                    log_profit,
+                    pools: cycle
+                        .iter()
+                        .map(|&index| self.edges[index].address)
+                        .collect(),
+                })
+            })
+            .collect();
+
+        opportunities.sort_by(|a, b| b.log_profit.total_cmp(&a.log_profit));
+        opportunities
+    }
+
+    // base token received back after sending amount_in around the cycle
+    fn simulate_cycle(&self, cycle: &[usize], amount_in: f64) -> Option<f64> {
+        let start_node = self.cycle_start(cycle)?;
+        let mut current_node = start_node;
+        let mut amount = amount_in;
+
+        for &edge_index in cycle {
+            let edge = &self.edges[edge_index];
+            let a_to_b = edge.get_swap_direction(current_node)?;
+            amount = edge.estimate_amount_out(amount, a_to_b)?;
+            current_node = edge.get_other_node(current_node)?;
+        }
+
+        if current_node != start_node {
+            return None;
+        }
+        Some(amount)
+    }
+
+    pub fn optimal_input_amount(&self, cycle: &[usize]) -> Option<u64> {
+        let profit = |amount_in: f64| -> Option<f64> {
+            Some(self.simulate_cycle(cycle, amount_in)? - amount_in)
+        };
+
+        // profit is concave in the input for within-tick swaps, so a ternary search finds the peak
+        let mut low: f64 = 0.0;
+        let mut high: f64 = u64::MAX as f64;
+        for _ in 0..200 {
+            let mid_low = low + (high - low) / 3.0;
+            let mid_high = high - (high - low) / 3.0;
+            if profit(mid_low)? < profit(mid_high)? {
+                low = mid_low;
+            } else {
+                high = mid_high;
+            }
+        }
+
+        let amount_in = ((low + high) / 2.0).floor();
+        if amount_in < 1.0 || profit(amount_in)? <= 0.0 {
+            return None;
+        }
+        Some(amount_in as u64)
+    }
+
+    // what comes back to the base token, in lamports when the cycle starts at WSOL
+    fn cycle_amount_out(&self, cycle: &[usize], direction: bool, amount_in: u64) -> Option<u64> {
+        let start_node = self.cycle_start(cycle)?;
+        let mut current_node = start_node;
+        let mut amount = amount_in;
+
+        let mut trade = |edge_index: usize| -> Option<()> {
+            let edge = &self.edges[edge_index];
+            amount = edge.amount_out_with_ticks(amount, current_node == edge.node_lowest)?;
+            current_node = edge.get_other_node(current_node)?;
+            Some(())
+        };
+        if direction {
+            cycle.iter().try_for_each(|&edge_index| trade(edge_index))?;
+        } else {
+            cycle
+                .iter()
+                .rev()
+                .try_for_each(|&edge_index| trade(edge_index))?;
+        }
+
+        (current_node == start_node).then_some(amount)
+    }
+
+    // profit of trading amount_in around the cycle once the transaction fee is paid, negative
+    // when it loses. None if a hop runs past the ticks the cached arrays cover, and for cycles
+    // anchored at another base token, whose amounts aren't lamports
+    pub fn net_profit_lamports(
+        &self,
+        cycle: &[usize],
+        direction: bool,
+        amount_in: u64,
+        priority_fee_lamports: u64,
+    ) -> Option<i64> {
+        let start_node = self.cycle_start(cycle)?;
+        if self.nodes[start_node].address != Pubkey::from_str(WSOL_ADDRESS).unwrap() {
+            return None;
+        }
+        let amount_out = self.cycle_amount_out(cycle, direction, amount_in)?;
+        // u64 amounts can pass i64::MAX, None if the profit doesn't fit
+        i64::try_from(amount_out as i128 - amount_in as i128 - priority_fee_lamports as i128).ok()
+    }
+
+    // one swap per hop, each spending the estimated output of the hop before it. Only the last
+    // hop sets a minimum output, amount_in, so the bundle fails rather than trade at a loss.
+    // Orca is the only DEX with an instruction builder so far
+    pub fn build_swap_instructions(
+        &self,
+        cycle: &[usize],
+        direction: bool,
+        amount_in: u64,
+        authority: &Pubkey,
+    ) -> Result<Vec<Instruction>> {
+        let hops: Vec<usize> = match direction {
+            true => cycle.to_vec(),
+            false => cycle.iter().rev().copied().collect(),
+        };
+
+        let mut current_node = self
+            .cycle_start(cycle)
+            .with_context(|| format!("Cycle {:?} doesn't pass a base token", cycle))?;
+        let mut amount = amount_in;
+        let mut instructions = Vec::with_capacity(hops.len());
+        for (hop, &edge_index) in hops.iter().enumerate() {
+            let edge = self
+                .edges
+                .get(edge_index)
+                .with_context(|| format!("No pool at edge index {}", edge_index))?;
+            if edge.dex != DexType::Orca {
+                bail!(
+                    "No swap instruction for {:?} pool {}",
+                    edge.dex,
+                    edge.address
+                );
+            }
+            let a_to_b = edge.get_swap_direction(current_node).with_context(|| {
+                format!("Pool {} doesn't trade hop {}'s input", edge.address, hop)
+            })?;
+            let amount_out = edge
+                .amount_out_with_ticks(amount, current_node == edge.node_lowest)
+                .with_context(|| {
+                    format!("Can't price hop {} through pool {}", hop, edge.address)
+                })?;
+
+            let lowest = (
+                self.nodes[edge.node_lowest].address,
+                edge.token_vault_lowest,
+                edge.token_program_lowest,
+            );
+            let highest = (
+                self.nodes[edge.node_highest].address,
+                edge.token_vault_highest,
+                edge.token_program_highest,
+            );
+            let (side_a, side_b) = match edge.reversed {
+                true => (highest, lowest),
+                false => (lowest, highest),
+            };
+            let swap = OrcaSwap {
+                whirlpool: edge.address,
+                tick_spacing: u16::try_from(edge.tick_spacing).with_context(|| {
+                    format!("Pool {} has an invalid tick spacing", edge.address)
+                })?,
+                current_tick_index: edge
+                    .current_tick_index
+                    .with_context(|| format!("Pool {} has no current tick", edge.address))?,
+                token_mint_a: side_a.0,
+                token_mint_b: side_b.0,
+                token_vault_a: side_a.1,
+                token_vault_b: side_b.1,
+                token_program_a: side_a.2,
+                token_program_b: side_b.2,
+                amount,
+                other_amount_threshold: match hop + 1 == hops.len() {
+                    true => amount_in,
+                    false => 0,
+                },
+                a_to_b,
+            };
+            instructions.push(swap::orca_swap_v2(&swap, authority));
+
+            amount = amount_out;
+            current_node = edge.get_other_node(current_node).with_context(|| {
+                format!("Pool {} doesn't trade hop {}'s input", edge.address, hop)
+            })?;
+        }
+
+        Ok(instructions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        vec,
+    };
+
+    use solana_sdk::instruction::AccountMeta;
+    use tracing::{
+        Subscriber,
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+    };
+    use tracing_subscriber::{
+        Layer,
+        layer::{self, SubscriberExt},
+        registry::LookupSpan,
+    };
+
+    use super::*;
+    use crate::decoders::{InitializedTick, ORCA_OWNER};
+
+    // span name -> field -> value, filled in as spans are created and recorded
+    #[derive(Clone, Default)]
+    struct SpanRecorder(Arc<Mutex<HashMap<String, HashMap<String, String>>>>);
+
+    struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanRecorder {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: layer::Context<'_, S>) {
+            let mut spans = self.0.lock().unwrap();
+            let fields = spans
+                .entry(attrs.metadata().name().to_string())
+                .or_default();
+            attrs.record(&mut FieldVisitor(fields));
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, ctx: layer::Context<'_, S>) {
+            let Some(span) = ctx.span(id) else {
+                return;
+            };
+            let mut spans = self.0.lock().unwrap();
+            let fields = spans.entry(span.name().to_string()).or_default();
+            values.record(&mut FieldVisitor(fields));
+        }
+    }
+
+    fn test_edge(sqrt_price: Option<u128>, fee_rate: u32) -> Edge {
+        Edge {
+            address: Pubkey::new_unique(),
+            fee_rate,
+            pool_type: PoolType::Concentrated,
+            dex: DexType::Orca,
+            tick_spacing: 64,
+            token_vault_lowest: Pubkey::new_unique(),
+            token_vault_highest: Pubkey::new_unique(),
+            config: Pubkey::new_unique(),
+            node_lowest: 0,
+            node_highest: 1,
+            decimals_lowest: 9,
+            decimals_highest: 9,
+            reversed: false,
+            disabled: false,
+            sqrt_price,
+            liquidity: None,
+            current_tick_index: None,
+            last_updated_slot: None,
+            vault_balance_lowest: None,
+            vault_balance_highest: None,
+            token_program_lowest: TokenProgram::default(),
+            token_program_highest: TokenProgram::default(),
+            transfer_fee_bps_lowest: 0,
+            transfer_fee_bps_highest: 0,
+            ticks: BTreeMap::new(),
+            tick_coverage: None,
+        }
+    }
+
+    #[test]
+    fn test_tvl_in_values_both_vaults_in_either_token() {
+        // 0.25 highest per lowest
+        let mut edge = test_edge(Some(1u128 << 63), 400);
+        edge.vault_balance_lowest = Some(1_000);
+        edge.vault_balance_highest = Some(400);
+
+        assert_eq!(edge.tvl_in(0), Some(2_600));
+        assert_eq!(edge.tvl_in(1), Some(650));
+        assert_eq!(edge.tvl_in(2), None);
+    }
+
+    #[test]
+    fn test_tvl_in_without_balances_returns_none() {
+        let edge = test_edge(Some(1u128 << 63), 400);
+
+        assert_eq!(edge.tvl_in(0), None);
+    }
+
+    #[test]
+    fn test_get_exchange_rate_with_fee_applies_fee_rate() {
+        let edge = test_edge(Some(1u128 << 64), 2500);
+
+        let raw = edge.get_exchange_rate(true).unwrap();
+        let with_fee = edge.get_exchange_rate_with_fee(true).unwrap();
+
+        assert!((with_fee / raw - 0.9975).abs() < 1e-12);
+        assert!(
+            (edge.get_log_exchange_rate(true, true).unwrap() - (raw * 0.9975).log10()).abs()
+                < 1e-12,
+            "Expected the log rate to include the fee"
+        );
+    }
+
+    #[test]
+    fn test_get_exchange_rate_with_zero_fee_matches_raw_rate() {
+        let edge = test_edge(Some(1u128 << 64), 0);
+
+        assert_eq!(
+            edge.get_exchange_rate_with_fee(false),
+            edge.get_exchange_rate(false)
+        );
+    }
+
+    #[test]
+    fn test_get_exchange_rate_with_huge_fee_is_clamped_to_zero() {
+        let edge = test_edge(Some(1u128 << 64), 5_000_000);
+
+        assert_eq!(edge.get_exchange_rate_with_fee(true), Some(0.0));
+    }
+
+    // SOL (9 decimals) is node_lowest and USDC (6 decimals) node_highest, at 150 USDC per SOL
+    fn test_sol_usdc_edge(reversed: bool) -> Edge {
+        // the pool's own price is token b per token a in raw units
+        let raw_price: f64 = match reversed {
+            false => 150.0 * 1e6 / 1e9,
+            true => 1e9 / (150.0 * 1e6),
+        };
+        Edge {
+            decimals_lowest: 9,
+            decimals_highest: 6,
+            reversed,
+            ..test_edge(Some((raw_price.sqrt() * 2f64.powi(64)) as u128), 0)
+        }
+    }
+
+    #[test]
+    fn test_get_exchange_rate_matches_clmm_price() {
+        for reversed in [false, true] {
+            let edge = test_sol_usdc_edge(reversed);
+
+            // direct trades node_lowest for node_highest
+            let usdc_per_sol = edge.get_exchange_rate(true).unwrap();
+            let sol_per_usdc = edge.get_exchange_rate(false).unwrap();
+
+            assert!(
+                (usdc_per_sol / 150.0 - 1.0).abs() < 1e-9,
+                "reversed {}: {}",
+                reversed,
+                usdc_per_sol
+            );
+            assert!(
+                (sol_per_usdc * 150.0 - 1.0).abs() < 1e-9,
+                "reversed {}: {}",
+                reversed,
+                sol_per_usdc
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_exchange_rate_above_one_keeps_integer_part() {
+        // a sqrt_price past 2^64, so the square spills into the high 128 bits
+        let edge = test_edge(Some(3u128 << 64), 0);
+
+        assert_eq!(edge.get_exchange_rate(true), Some(9.0));
+        assert_eq!(edge.get_exchange_rate(false), Some(1.0 / 9.0));
+    }
+
+    #[test]
+    fn test_get_exchange_rate_at_max_sqrt_price_stays_finite() {
+        let edge = Edge {
+            decimals_lowest: u8::MAX,
+            decimals_highest: 0,
+            ..test_edge(Some(u128::MAX), 0)
+        };
+
+        let direct = edge.get_exchange_rate(true).unwrap();
+        let inverse = edge.get_exchange_rate(false).unwrap();
+        // (2^128 - 1)^2 / 2^128 rounds to 2^128 in f64
+        let expected = 2f64.powi(128) * 1e255;
+        assert!((direct - expected).abs() / expected < 1e-12);
+        assert!((direct * inverse - 1.0).abs() < 1e-12);
+        assert!(edge.get_log_exchange_rate(false, true).unwrap().is_finite());
+    }
+
+    #[test]
+    fn test_non_finite_exchange_rates_return_none() {
+        let edge = test_edge(Some(0), 0);
+        assert_eq!(edge.get_exchange_rate(true), None);
+        assert_eq!(edge.get_exchange_rate(false), None);
+        assert_eq!(edge.get_log_exchange_rate(true, false), None);
+
+        // a 100% fee prices the output at 0
+        let edge = test_edge(Some(1u128 << 64), 1_000_000);
+        assert_eq!(edge.get_exchange_rate_with_fee(true), Some(0.0));
+        assert_eq!(edge.get_log_exchange_rate(true, true), None);
+    }
+
+    #[test]
+    fn test_get_exchange_rate_without_sqrt_price_returns_none() {
+        let edge = test_edge(None, 400);
+
+        assert!(edge.get_exchange_rate(true).is_none());
+        assert!(edge.get_exchange_rate_with_fee(false).is_none());
+        assert!(edge.get_log_exchange_rate(true, true).is_none());
+    }
+
+    // mid-range pool: tick 32 of the [0, 64) range, 0.04% fee
+    fn test_priced_edge(liquidity: u128) -> Edge {
+        let mut edge = test_edge(Some((1.0001f64.powi(16) * 2f64.powi(64)) as u128), 400);
+        edge.liquidity = Some(liquidity);
+        edge.current_tick_index = Some(32);
+        edge
+    }
+
+    #[test]
+    fn test_amount_out_within_tick_matches_clmm_math() {
+        let edge = test_priced_edge(1_000_000_000_000);
+
+        // a -> b: sqrt_p' = L * sqrt_p / (L + dx * sqrt_p), dy = L * (sqrt_p - sqrt_p')
+        assert_eq!(edge.amount_out(1_000, true), Some(1_002));
+        // b -> a: sqrt_p' = sqrt_p + dy / L, dx = L * (1 / sqrt_p - 1 / sqrt_p')
+        assert_eq!(edge.amount_out(1_000, false), Some(996));
+    }
+
+    #[test]
+    fn test_amount_out_follows_reversed_token_order() {
+        let mut edge = test_priced_edge(1_000_000_000_000);
+        edge.reversed = true;
+
+        assert_eq!(edge.amount_out(1_000, true), Some(996));
+        assert_eq!(edge.amount_out(1_000, false), Some(1_002));
+    }
+
+    #[test]
+    fn test_amount_out_crossing_tick_returns_none() {
+        let edge = test_priced_edge(1_000_000_000_000);
+
+        // pushes sqrt price to ~0.9917, below the tick 0 boundary
+        assert_eq!(edge.amount_out(10_000_000_000, true), None);
+        assert!(edge.amount_out(10_000_000_000, false).is_none());
+    }
+
+    fn test_tick_array(start_tick_index: i32, ticks: &[(i32, i128)]) -> TickArray {
+        TickArray {
+            whirlpool: Pubkey::new_unique(),
+            start_tick_index,
+            ticks: ticks
+                .iter()
+                .map(|&(tick_index, liquidity_net)| InitializedTick {
+                    tick_index,
+                    liquidity_net,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_amount_out_across_ticks_crosses_one_tick() {
+        let mut edge = test_priced_edge(1_000_000_000_000);
+        // half the liquidity ends at tick 0, the arrays cover ticks -5632..5632
+        edge.apply_tick_array(&test_tick_array(-5_632, &[]));
+        edge.apply_tick_array(&test_tick_array(0, &[(0, 500_000_000_000)]));
+
+        // within the tick both paths agree
+        assert_eq!(edge.amount_out_across_ticks(1_000, true), Some(1_002));
+        assert_eq!(edge.amount_out(10_000_000_000, true), None);
+
+        let liquidity = 1e12;
+        let sqrt_price = 1.0001f64.powi(16);
+        let mut remaining = 10_000_000_000.0 * 0.9996;
+        // down to tick 0, where sqrt price is 1
+        remaining -= liquidity * (1.0 - 1.0 / sqrt_price);
+        let first_range = liquidity * (sqrt_price - 1.0);
+        let liquidity = 5e11;
+        let new_sqrt_price = liquidity / (liquidity + remaining);
+        let expected = first_range + liquidity * (1.0 - new_sqrt_price);
+
+        let amount_out = edge.amount_out_across_ticks(10_000_000_000, true).unwrap();
+        assert!((amount_out as f64 - expected).abs() <= 1.0);
+    }
+
+    #[test]
+    fn test_cycle_amount_out_prices_hops_past_the_current_tick() {
+        let mut graph = test_two_pool_graph(1u128 << 64, 1u128 << 64, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+        // 1% of the liquidity moves the price well past the 64 tick range
+        assert_eq!(graph.cycle_amount_out(&[0, 1], true, 10_000_000_000), None);
+
+        for edge in &mut graph.edges {
+            edge.apply_tick_array(&test_tick_array(-5_632, &[]));
+            edge.apply_tick_array(&test_tick_array(0, &[]));
+        }
+
+        let amount_out = graph
+            .cycle_amount_out(&[0, 1], true, 10_000_000_000)
+            .unwrap();
+        // each swap moves its pool ~1%, on top of the two 0.04% fees
+        assert!((9_750_000_000..9_850_000_000).contains(&amount_out));
+    }
+
+    #[test]
+    fn test_amount_out_across_ticks_past_cached_range_returns_none() {
+        let mut edge = test_priced_edge(1_000_000_000_000);
+        edge.apply_tick_array(&test_tick_array(0, &[]));
+
+        assert!(edge.amount_out_across_ticks(1_000, false).is_some());
+        // crosses below tick 0, which no cached array covers
+        assert_eq!(edge.amount_out_across_ticks(10_000_000_000, true), None);
+        assert_eq!(
+            test_priced_edge(1_000_000_000_000).amount_out_across_ticks(1_000, true),
+            None
+        );
+    }
+
+    #[test]
+    fn test_apply_tick_array_replaces_ticks_in_its_range() {
+        let mut edge = test_priced_edge(1_000_000_000_000);
+        edge.apply_tick_array(&test_tick_array(0, &[(64, 5), (128, 7)]));
+        edge.apply_tick_array(&test_tick_array(-5_632, &[(-64, 3)]));
+        edge.apply_tick_array(&test_tick_array(0, &[(128, 9)]));
+
+        assert_eq!(edge.ticks, BTreeMap::from([(-64, 3), (128, 9)]));
+        assert_eq!(edge.tick_coverage, Some((-5_632, 5_632)));
+    }
+
+    #[test]
+    fn test_amount_out_without_liquidity_or_tick_returns_none() {
+        let mut edge = test_priced_edge(1_000_000_000_000);
+        edge.current_tick_index = None;
+        assert_eq!(edge.amount_out(1_000, true), None);
+
+        let edge = test_edge(Some(1u128 << 64), 400);
+        assert_eq!(edge.amount_out(1_000, true), None);
+    }
+
+    fn wsol_node(graph: &Graph) -> usize {
+        graph.address_to_node[&Pubkey::from_str(WSOL_ADDRESS).unwrap()]
+    }
+
+    fn test_two_pool_graph(sqrt_price_0: u128, sqrt_price_1: u128, liquidity: u128) -> Graph {
+        let mut graph = Graph::default();
+
+        for (address, sqrt_price) in [
+            ("Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE", sqrt_price_0),
+            ("7eMnzvi48Nbz2yRaQrCWqfQ7awPNPfV3AboaejktyGMD", sqrt_price_1),
+        ] {
+            graph
+                .insert_pool(PoolInfo {
+                    address: Some(address.to_string()),
+                    fee_rate: Some(400),
+                    pool_type: Some(PoolType::Concentrated),
+                    dex: Some(DexType::Orca),
+                    tick_spacing: Some(64),
+                    token_a: Some(TokenInfo {
+                        address: Some("So11111111111111111111111111111111111111112".to_string()),
+                        decimals: Some(9),
+                        name: Some("Wrapped SOL".to_string()),
+                        symbol: Some("SOL".to_string()),
+                    }),
+                    token_b: Some(TokenInfo {
+                        address: Some("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string()),
+                        decimals: Some(9),
+                        name: Some("USD Coin".to_string()),
+                        symbol: Some("USDC".to_string()),
+                    }),
+                    token_vault_a: Some("EUuUbDcafPrmVTD5M6qoJAoyyNbihBhugADAxRMn5he9".to_string()),
+                    token_vault_b: Some("2WLWEuKDgkDUccTpbwYp1GToYktiSB1cXvreHUwiSUVP".to_string()),
+                    config: Some("2LecshUwdy9xi7meFgHtFJQNSKk4KdTrcpvaB56dP2NQ".to_string()),
+                })
+                .unwrap();
+            graph
+                .update_edge(
+                    &Pubkey::from_str(address).unwrap(),
+                    PoolUpdate {
+                        new_liquidity: liquidity,
+                        new_sqrt_price: sqrt_price,
+                        new_current_tick_index: 0,
+                        slot: 0,
+                        fee_rate: None,
+                        tick_spacing: None,
+                    },
+                )
+                .unwrap();
+        }
+
+        graph
+    }
+
+    #[test]
+    fn test_optimal_input_amount_for_profitable_cycle_returns_peak() {
+        // pool 1 sells USDC 2% more expensive than pool 0 buys it back
+        let sqrt_price_1 = (1.02f64.sqrt() * 2f64.powi(64)) as u128;
+        let mut graph = test_two_pool_graph(1u128 << 64, sqrt_price_1, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+
+        let amount_in = graph.optimal_input_amount(&[1, 0]).unwrap();
+        let profit =
+            |amount: u64| graph.simulate_cycle(&[1, 0], amount as f64).unwrap() - amount as f64;
+
+        assert!(amount_in > 0);
+        assert!(profit(amount_in) > 0.0);
+        assert!(profit(amount_in) >= profit(amount_in / 2));
+        assert!(profit(amount_in) >= profit(amount_in + amount_in / 2));
+    }
+
+    #[test]
+    fn test_optimal_input_amount_for_unprofitable_direction_returns_none() {
+        let sqrt_price_1 = (1.02f64.sqrt() * 2f64.powi(64)) as u128;
+        let graph = test_two_pool_graph(1u128 << 64, sqrt_price_1, 1_000_000_000_000);
+
+        assert!(graph.optimal_input_amount(&[0, 1]).is_none());
+    }
+
+    #[test]
+    fn test_optimal_input_amount_for_equal_prices_returns_none() {
+        let graph = test_two_pool_graph(1u128 << 64, 1u128 << 64, 1_000_000_000_000);
+
+        assert!(graph.optimal_input_amount(&[1, 0]).is_none());
+    }
+
+    #[test]
+    fn test_apply_swap_moves_price_in_swap_direction() {
+        let mut graph = test_two_pool_graph(1u128 << 64, 1u128 << 64, 1_000_000_000_000);
+        let address = graph.edges[0].address;
+
+        graph.apply_swap(&address, 1_000_000_000, true).unwrap();
+        let after_a_to_b = graph.edges[0].sqrt_price.unwrap();
+        assert!(after_a_to_b < 1u128 << 64);
+        assert!(graph.edges[0].current_tick_index.unwrap() < 0);
+
+        graph.apply_swap(&address, 2_000_000_000, false).unwrap();
+        assert!(graph.edges[0].sqrt_price.unwrap() > after_a_to_b);
+        assert!(graph.edges[0].current_tick_index.unwrap() >= 0);
+    }
+
+    #[test]
+    fn test_apply_swap_leaves_disabled_pool_unchanged() {
+        let mut graph = test_two_pool_graph(1u128 << 64, 1u128 << 64, 1_000_000_000_000);
+        let address = graph.edges[0].address;
+        graph.disable_edge(&address).unwrap();
+
+        graph.apply_swap(&address, 1_000_000_000, true).unwrap();
+
+        assert_eq!(graph.edges[0].sqrt_price, Some(1u128 << 64));
+        assert_eq!(graph.edges[0].current_tick_index, Some(0));
+    }
+
+    #[test]
+    fn test_apply_swap_on_unknown_pool_returns_error() {
+        let mut graph = test_two_pool_graph(1u128 << 64, 1u128 << 64, 1_000_000_000_000);
+
+        assert!(
+            graph
+                .apply_swap(&Pubkey::new_unique(), 1_000, true)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parallel_cycle_search_matches_serial_search() {
+        let graph = Graph::build_graph("./tests/test_data").unwrap();
+
+        let sorted = |mut cycles: HashMap<String, Vec<Vec<usize>>>| {
+            cycles.values_mut().for_each(|cycle_vec| cycle_vec.sort());
+            cycles
+        };
+        let serial = sorted(graph.find_cycles_serial(&[wsol_node(&graph)], 4));
+        let parallel = sorted(graph.find_cycles_parallel(&[wsol_node(&graph)], 4));
+
+        assert!(!serial.is_empty());
+        assert_eq!(serial, parallel);
+    }
+
+    #[test]
+    fn test_phase_spans_record_counts() {
+        let recorder = SpanRecorder::default();
+        let subscriber = tracing_subscriber::registry().with(recorder.clone());
+
+        let (stats, opportunities) = tracing::subscriber::with_default(subscriber, || {
+            let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+            graph.build_cycles(3).unwrap();
+            (graph.stats(), graph.find_arbitrage_cycles(0.0))
+        });
+
+        let spans = recorder.0.lock().unwrap();
+        assert_eq!(spans["build_graph"]["nodes"], stats.nodes.to_string());
+        assert_eq!(spans["build_graph"]["edges"], stats.edges.to_string());
+        assert_eq!(spans["build_cycles"]["max_depth"], "3");
+        assert_eq!(spans["build_cycles"]["cycles"], stats.cycles.to_string());
+        assert_eq!(
+            spans["scan"]["opportunities"],
+            opportunities.len().to_string()
+        );
+    }
+
+    // every pair of tokens shares pools_per_pair pools, the first token is WSOL
+    fn test_dense_graph(tokens: usize, pools_per_pair: usize) -> Graph {
+        let mut graph = Graph::default();
+        let mut addresses = vec!["So11111111111111111111111111111111111111112".to_string()];
+        addresses.extend((1..tokens).map(|_| Pubkey::new_unique().to_string()));
+        let token = |address: &String| TokenInfo {
+            address: Some(address.clone()),
+            decimals: Some(9),
+            name: Some(address.clone()),
+            symbol: Some(address.clone()),
+        };
+
+        for a in 0..tokens {
+            for b in a + 1..tokens {
+                for _ in 0..pools_per_pair {
+                    graph
+                        .insert_pool(PoolInfo {
+                            address: Some(Pubkey::new_unique().to_string()),
+                            fee_rate: Some(400),
+                            pool_type: Some(PoolType::Concentrated),
+                            dex: Some(DexType::Orca),
+                            tick_spacing: Some(64),
+                            token_a: Some(token(&addresses[a])),
+                            token_b: Some(token(&addresses[b])),
+                            token_vault_a: Some(Pubkey::new_unique().to_string()),
+                            token_vault_b: Some(Pubkey::new_unique().to_string()),
+                            config: Some(Pubkey::new_unique().to_string()),
+                        })
+                        .unwrap();
+                }
+            }
+        }
+
+        graph
+    }
+
+    #[test]
+    fn test_search_deeper_than_the_graph_terminates() {
+        let mut graph = test_dense_graph(3, 2);
+        let unique = |cycles: &HashMap<String, Vec<Vec<usize>>>| -> HashSet<Vec<usize>> {
+            cycles.values().flatten().cloned().collect()
+        };
+
+        // no walk can reuse a pool, so nothing is longer than the 6 pools
+        graph.build_cycles(100).unwrap();
+        let all = unique(&graph.all_cycles);
+        assert_eq!(
+            all,
+            unique(&graph.find_cycles_serial(&[wsol_node(&graph)], 6))
+        );
+        assert!(all.len() > 10);
+        assert!(all.iter().all(|cycle| cycle.len() <= 6));
+
+        graph.set_max_cycles(10);
+        let capped = unique(&graph.find_cycles_serial(&[wsol_node(&graph)], 100));
+        assert_eq!(capped.len(), 10);
+        assert!(capped.is_subset(&all));
+
+        // rayon tasks may each add one more before they see the count
+        graph.build_cycles(100).unwrap();
+        let capped = unique(&graph.all_cycles);
+        assert!(capped.len() >= 10);
+        assert!(capped.is_subset(&all));
+    }
+
+    #[test]
+    fn test_collapsed_parallel_pools_keep_the_deepest_per_pair() {
+        // a triangle with two pools on every side
+        let mut graph = test_dense_graph(3, 2);
+        graph.build_cycles(3).unwrap();
+        assert!(graph.stats().cycles > 1);
+
+        graph.set_collapse_parallel_pools(true);
+        graph.build_cycles(3).unwrap();
+        assert_eq!(graph.stats().cycles, 1);
+        // no liquidity known yet, so the first pool of each pair
+        let mut pools = graph.all_cycles.values().flatten().next().unwrap().clone();
+        pools.sort();
+        assert_eq!(pools, [0, 2, 4]);
+
+        let address = graph.edges[1].address;
+        graph
+            .update_edge(
+                &address,
+                PoolUpdate {
+                    new_liquidity: 1_000,
+                    new_sqrt_price: 1u128 << 64,
+                    new_current_tick_index: 0,
+                    slot: 0,
+                    fee_rate: None,
+                    tick_spacing: None,
+                },
+            )
+            .unwrap();
+        graph.build_cycles(3).unwrap();
+        assert_eq!(graph.stats().cycles, 1);
+        let mut pools = graph.all_cycles.values().flatten().next().unwrap().clone();
+        pools.sort();
+        assert_eq!(pools, [1, 2, 4]);
+    }
+
+    #[test]
+    fn test_build_cycles_from_non_wsol_token() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        let usdc_node = graph.address_to_node[&usdc];
+
+        graph.build_cycles_from(usdc, 3).unwrap();
+
+        assert!(!graph.all_cycles.is_empty());
+        for cycle in graph.all_cycles.values().flatten() {
+            let first_edge = &graph.edges[cycle[0]];
+            assert!(first_edge.node_lowest == usdc_node || first_edge.node_highest == usdc_node);
+            assert!(!graph.check_cycle(&mut cycle.clone()));
+        }
+    }
+
+    #[test]
+    fn test_build_cycles_anchored_on_usdc_base() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        let usdc_node = graph.address_to_node[&usdc];
+        let wsol_node = wsol_node(&graph);
+        let unique = |graph: &Graph| -> HashSet<Vec<usize>> {
+            graph.all_cycles.values().flatten().cloned().collect()
+        };
+        let touches_wsol = |graph: &Graph, cycle: &[usize]| {
+            cycle
+                .iter()
+                .any(|&edge_index| graph.edges[edge_index].get_other_node(wsol_node).is_some())
+        };
+
+        graph.set_base_tokens(vec![usdc]);
+        graph.build_cycles(3).unwrap();
+        let usdc_cycles = unique(&graph);
+        assert!(!usdc_cycles.is_empty());
+        let usdc_symbol = graph.nodes[usdc_node].symbol.clone();
+        for cycle in &usdc_cycles {
+            assert!(graph.is_walk_from(cycle, usdc_node));
+            assert!(!graph.check_cycle(&mut cycle.clone()));
+            assert!(graph.describe_cycle(cycle).starts_with(&usdc_symbol));
+        }
+
+        // with WSOL first, the cycles through both stay with WSOL and USDC keeps the rest
+        graph.set_base_tokens(vec![Pubkey::from_str(WSOL_ADDRESS).unwrap(), usdc]);
+        graph.build_cycles(3).unwrap();
+        let both_cycles = unique(&graph);
+        for cycle in &both_cycles {
+            let start_node = match touches_wsol(&graph, cycle) {
+                true => wsol_node,
+                false => usdc_node,
+            };
+            assert!(graph.is_walk_from(cycle, start_node));
+            assert!(!graph.check_cycle(&mut cycle.clone()));
+        }
+        let without_wsol = |cycles: &HashSet<Vec<usize>>| -> HashSet<Vec<usize>> {
+            cycles
+                .iter()
+                .filter(|cycle| !touches_wsol(&graph, cycle))
+                .cloned()
+                .collect()
+        };
+        let usdc_only = without_wsol(&both_cycles);
+        assert!(!usdc_only.is_empty());
+        assert_eq!(usdc_only, without_wsol(&usdc_cycles));
+
+        graph.set_base_tokens(default_base_tokens());
+        graph.build_cycles(3).unwrap();
+        assert_eq!(both_cycles.len(), unique(&graph).len() + usdc_only.len());
+    }
+
+    // all_cycles with every key's cycles sorted, so two searches compare equal
+    fn sorted_cycles(graph: &Graph) -> HashMap<String, Vec<Vec<usize>>> {
+        let mut cycles = graph.all_cycles.clone();
+        cycles.values_mut().for_each(|cycle_vec| cycle_vec.sort());
+        cycles
+    }
+
+    #[test]
+    fn test_incremental_cycles_match_full_rebuild() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        graph.set_base_tokens(vec![Pubkey::from_str(WSOL_ADDRESS).unwrap(), usdc]);
+        graph.build_cycles(3).unwrap();
+        let full = sorted_cycles(&graph);
+
+        // a second pool next to a USDC pool that doesn't trade WSOL, so its new cycles are
+        // anchored at both bases
+        let (wsol_node, usdc_node) = (wsol_node(&graph), graph.address_to_node[&usdc]);
+        let edge = graph
+            .edges
+            .iter()
+            .find(|edge| {
+                edge.get_other_node(usdc_node)
+                    .is_some_and(|other_node| other_node != wsol_node)
+            })
+            .unwrap();
+        let token = |node: usize| TokenInfo {
+            address: Some(graph.nodes[node].address.to_string()),
+            decimals: Some(graph.nodes[node].decimals),
+            name: None,
+            symbol: None,
+        };
+        let address = Pubkey::new_unique();
+        let pool = PoolInfo {
+            address: Some(address.to_string()),
+            fee_rate: Some(400),
+            pool_type: Some(PoolType::Concentrated),
+            dex: Some(DexType::Orca),
+            tick_spacing: Some(64),
+            token_a: Some(token(edge.node_lowest)),
+            token_b: Some(token(edge.node_highest)),
+            token_vault_a: Some(Pubkey::new_unique().to_string()),
+            token_vault_b: Some(Pubkey::new_unique().to_string()),
+            config: None,
+        };
+
+        graph.add_pool_incremental(pool).unwrap();
+        let incremental = sorted_cycles(&graph);
+        graph.build_cycles(3).unwrap();
+
+        assert_ne!(incremental, full);
+        assert_eq!(incremental, sorted_cycles(&graph));
+
+        graph.remove_pool_incremental(&address).unwrap();
+        let incremental = sorted_cycles(&graph);
+        graph.build_cycles(3).unwrap();
+
+        assert_eq!(incremental, full);
+        assert_eq!(incremental, sorted_cycles(&graph));
+    }
+
+    #[test]
+    fn test_describe_cycle_on_fixture() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        graph.build_cycles(3).unwrap();
+        let cycle = graph
+            .all_cycles
+            .values()
+            .flatten()
+            .find(|cycle| cycle.len() == 3)
+            .unwrap()
+            .clone();
+
+        let description = graph.describe_cycle(&cycle);
+
+        let symbols: Vec<&str> = description.split(" -> ").collect();
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(symbols[0], "WSOL");
+        assert_eq!(symbols[3], "WSOL");
+        for (hop, &edge_index) in cycle.iter().enumerate() {
+            let (low, high) = graph.edge_pair_symbols(edge_index).unwrap();
+            let mut pair = [symbols[hop], symbols[hop + 1]];
+            pair.sort();
+            let mut expected = [low, high];
+            expected.sort();
+            assert_eq!(pair, expected);
+        }
+
+        assert_eq!(graph.edge_pair_symbols(graph.edges.len()), None);
+        // a 3-cycle is a triangle, so its middle pool doesn't touch WSOL
+        let broken = [cycle[1], cycle[0], cycle[2]];
+        assert_eq!(graph.describe_cycle(&broken), format!("{:?}", broken));
+    }
+
+    #[test]
+    fn test_stats_on_fixture_graph() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+
+        let stats = graph.stats();
+        assert_eq!(stats.nodes, 105);
+        assert_eq!(stats.edges, 138);
+        assert_eq!(stats.cycles, 0);
+        assert_eq!(stats.priced_edges, 0);
+        // every pool adds itself to both of its tokens
+        assert!((stats.avg_degree - 2.0 * 138.0 / 105.0).abs() < 1e-9);
+        assert_eq!(stats.max_degree, graph.adjacency[&wsol_node(&graph)].len());
+
+        graph.build_cycles(3).unwrap();
+        let address = graph.edges[0].address;
+        graph
+            .update_edge(
+                &address,
+                PoolUpdate {
+                    new_liquidity: 1,
+                    new_sqrt_price: 1u128 << 64,
+                    new_current_tick_index: 0,
+                    slot: 0,
+                    fee_rate: None,
+                    tick_spacing: None,
+                },
+            )
+            .unwrap();
+
+        let stats = graph.stats();
+        let unique: HashSet<&Vec<usize>> = graph.all_cycles.values().flatten().collect();
+        assert!(stats.cycles > 0);
+        assert_eq!(stats.cycles, unique.len());
+        assert_eq!(stats.priced_edges, 1);
+    }
+
+    #[test]
+    fn test_build_graph_from_other_data_folder() {
+        let dir = std::env::temp_dir().join(format!("graph-data-dir-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy(
+            "./tests/test_data/orca_pools.json",
+            dir.join("orca_pools.json"),
+        )
+        .unwrap();
+        // the snapshot lives next to the pool files and isn't one of them
+        std::fs::write(dir.join("graph.bin"), b"not a pool file").unwrap();
+
+        let graph = Graph::build_graph(dir.to_str().unwrap()).unwrap();
+        let fixture = Graph::build_graph("./tests/test_data").unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!graph.edges.is_empty());
+        assert!(graph.edges.len() < fixture.edges.len());
+        assert!(Graph::build_graph(dir.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_build_graph_skips_malformed_pool_file() {
+        let dir = std::env::temp_dir().join(format!("graph-malformed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy(
+            "./tests/test_data/orca_pools.json",
+            dir.join("orca_pools.json"),
+        )
+        .unwrap();
+        let good = Graph::build_graph(dir.to_str().unwrap()).unwrap();
+        std::fs::write(dir.join("raydium_pools.json"), b"{\"all_pools\": [{\"addr").unwrap();
+        std::fs::write(dir.join("meteora_pools.json"), b"garbage").unwrap();
+
+        let recorder = SpanRecorder::default();
+        let subscriber = tracing_subscriber::registry().with(recorder.clone());
+        let graph = tracing::subscriber::with_default(subscriber, || {
+            Graph::build_graph(dir.to_str().unwrap())
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let graph = graph.unwrap();
+        assert!(!good.edges.is_empty());
+        assert_eq!(graph.edges.len(), good.edges.len());
+        assert_eq!(
+            recorder.0.lock().unwrap()["build_graph"]["skipped_files"],
+            "2"
+        );
+    }
+
+    #[test]
+    fn test_build_cycles_without_wsol_returns_error() {
+        let mut graph = Graph::default();
+        let tokens: Vec<TokenInfo> = (0..2)
+            .map(|_| TokenInfo {
+                address: Some(Pubkey::new_unique().to_string()),
+                decimals: Some(6),
+                name: None,
+                symbol: None,
+            })
+            .collect();
+        // two pools on the same pair, so there is a cycle to find from either token
+        for _ in 0..2 {
+            graph
+                .insert_pool(PoolInfo {
+                    address: Some(Pubkey::new_unique().to_string()),
+                    fee_rate: Some(400),
+                    pool_type: Some(PoolType::Concentrated),
+                    dex: Some(DexType::Orca),
+                    tick_spacing: Some(64),
+                    token_a: Some(tokens[0].clone()),
+                    token_b: Some(tokens[1].clone()),
+                    token_vault_a: Some(Pubkey::new_unique().to_string()),
+                    token_vault_b: Some(Pubkey::new_unique().to_string()),
+                    config: None,
+                })
+                .unwrap();
+        }
+
+        let result = graph.build_cycles(3);
+
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("No pool in the graph trades a base token")
+        );
+        assert!(graph.all_cycles.is_empty());
+        let start = Pubkey::from_str(tokens[0].address.as_deref().unwrap()).unwrap();
+        graph.build_cycles_from(start, 3).unwrap();
+        assert_eq!(graph.stats().cycles, 1);
+    }
+
+    #[test]
+    fn test_build_cycles_from_unknown_token_returns_error() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+
+        assert!(graph.build_cycles_from(Pubkey::new_unique(), 3).is_err());
+    }
+
+    #[test]
+    fn test_save_and_load_round_trips_static_graph() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        graph.build_cycles(3).unwrap();
+        let address = graph.edges[0].address;
+        graph
+            .update_edge(
+                &address,
+                PoolUpdate {
+                    new_liquidity: 1,
+                    new_sqrt_price: 1,
+                    new_current_tick_index: 1,
+                    slot: 0,
+                    fee_rate: None,
+                    tick_spacing: None,
+                },
+            )
+            .unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("graph_round_trip-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        graph.save(path).unwrap();
+        let loaded = Graph::load(path).unwrap();
+
+        assert_eq!(loaded.nodes.len(), graph.nodes.len());
+        assert_eq!(loaded.edges.len(), graph.edges.len());
+        assert_eq!(loaded.edges[0].address, address);
+        assert_eq!(loaded.address_to_edge, graph.address_to_edge);
+        assert_eq!(loaded.adjacency, graph.adjacency);
+        assert_eq!(loaded.all_cycles, graph.all_cycles);
+        assert_eq!(loaded.cycles_start_nodes, graph.cycles_start_nodes);
+        assert_eq!(loaded.max_cycles, DEFAULT_MAX_CYCLES);
+        assert!(loaded.edges[0].sqrt_price.is_none());
+        assert!(loaded.edges[0].liquidity.is_none());
+    }
+
+    #[test]
+    fn test_loaded_snapshot_needs_a_rebuild_for_another_depth() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        assert!(!graph.has_cycles_for(3));
+        graph.build_cycles(3).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("graph_other_depth-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        graph.save(path).unwrap();
+
+        let loaded = Graph::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert!(loaded.has_cycles_for(3));
+        assert!(!loaded.has_cycles_for(4));
+    }
+
+    #[test]
+    fn test_loaded_snapshot_needs_a_rebuild_for_another_cycle_cap() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        graph.set_max_cycles(10);
+        graph.build_cycles(3).unwrap();
+        let path = std::env::temp_dir().join(format!("graph_other_cap-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        graph.save(path).unwrap();
+
+        let mut loaded = Graph::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // the cap isn't stored, a scan without --max-cycles gets the default one
+        assert!(!loaded.has_cycles_for(3));
+        loaded.set_max_cycles(10);
+        assert!(loaded.has_cycles_for(3));
+    }
+
+    #[test]
+    fn test_loaded_snapshot_needs_a_rebuild_for_other_base_tokens() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        graph.set_base_tokens(vec![usdc]);
+        graph.build_cycles(3).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("graph_other_bases-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        graph.save(path).unwrap();
+
+        let mut loaded = Graph::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // the base tokens aren't stored, a scan without --base-tokens starts from WSOL
+        assert!(!loaded.has_cycles_for(3));
+        loaded.set_base_tokens(vec![usdc]);
+        assert!(loaded.has_cycles_for(3));
+    }
+
+    #[test]
+    fn test_loaded_snapshot_needs_a_rebuild_without_collapse() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        graph.set_collapse_parallel_pools(true);
+        graph.build_cycles(3).unwrap();
+        let path = std::env::temp_dir().join(format!("graph_collapsed-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        graph.save(path).unwrap();
+
+        let mut loaded = Graph::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // the collapsed search skipped pools a full one would walk
+        assert!(!loaded.has_cycles_for(3));
+        loaded.set_collapse_parallel_pools(true);
+        assert!(loaded.has_cycles_for(3));
+        loaded.set_min_liquidity(1_000);
+        assert!(!loaded.has_cycles_for(3));
+    }
+
+    #[test]
+    fn test_load_rejects_mismatched_snapshot_version() {
+        let path =
+            std::env::temp_dir().join(format!("graph_wrong_version-{}.bin", std::process::id()));
+        std::fs::write(
+            &path,
+            bincode::serialize(&(GRAPH_SNAPSHOT_VERSION + 1)).unwrap(),
+        )
+        .unwrap();
+
+        assert!(Graph::load(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_export_cycles_round_trip() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        graph.build_cycles(3).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("exported_cycles-{}.json", std::process::id()));
+
+        graph.export_cycles(path.to_str().unwrap()).unwrap();
+
+        let exported: Vec<ExportedCycle> =
+            serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        assert_eq!(exported.len(), graph.stats().cycles);
+        let start_symbol = &graph.nodes[wsol_node(&graph)].symbol;
+        for cycle in &exported {
+            assert_eq!(cycle.hops.len(), cycle.edges.len());
+            assert_eq!(&cycle.hops[0].token_in, start_symbol);
+            assert_eq!(&cycle.hops.last().unwrap().token_out, start_symbol);
+            for (hop, &edge_index) in cycle.hops.iter().zip(&cycle.edges) {
+                assert_eq!(hop.pool, graph.edges[edge_index].address.to_string());
+                assert_eq!(hop.fee_rate, graph.edges[edge_index].fee_rate);
+            }
+            // the fixture pools have never been priced
+            assert_eq!(cycle.log_profit, None);
+        }
+        assert!(
+            exported
+                .windows(2)
+                .all(|pair| pair[0].edges < pair[1].edges)
+        );
+    }
+
+    #[test]
+    fn test_export_cycles_includes_log_profit() {
+        let sqrt_price_1 = (0.255f64.sqrt() * 2f64.powi(64)) as u128;
+        let mut graph = test_two_pool_graph(1u128 << 63, sqrt_price_1, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "exported_priced_cycles-{}.json",
+            std::process::id()
+        ));
+
+        graph.export_cycles(path.to_str().unwrap()).unwrap();
+
+        let exported: Vec<ExportedCycle> =
+            serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        assert_eq!(exported.len(), 1);
+        assert_eq!(exported[0].direction, Some(false));
+        assert_eq!(
+            exported[0].log_profit,
+            graph
+                .evaluate_cycle(&exported[0].edges)
+                .map(|(_, log_profit)| log_profit)
+        );
+        assert_eq!(exported[0].hops[0].token_out, "USDC");
+    }
+
+    #[test]
+    fn test_edges_between_returns_all_pools_on_pair() {
+        let graph = Graph::build_graph("./tests/test_data").unwrap();
+        let sol = Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap();
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+
+        let edges = graph.edges_between(&sol, &usdc);
+
+        assert_eq!(edges.len(), 7);
+        assert_eq!(edges, graph.edges_between(&usdc, &sol));
+        for edge_index in edges {
+            let edge = &graph.edges[edge_index];
+            let tokens = [
+                graph.nodes[edge.node_lowest].address,
+                graph.nodes[edge.node_highest].address,
+            ];
+            assert!(tokens.contains(&sol) && tokens.contains(&usdc));
+        }
+    }
+
+    #[test]
+    fn test_edges_between_unknown_or_same_token_returns_empty() {
+        let graph = Graph::build_graph("./tests/test_data").unwrap();
+        let sol = Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap();
+
+        assert!(graph.edges_between(&sol, &Pubkey::new_unique()).is_empty());
+        assert!(graph.edges_between(&sol, &sol).is_empty());
+    }
+
+    #[test]
+    fn test_accessors_mirror_the_internal_maps() {
+        let mut graph = test_two_pool_graph(1u128 << 64, 1u128 << 64, 1_000_000_000_000);
+        let wsol = Pubkey::from_str(WSOL_ADDRESS).unwrap();
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        let (wsol_node, usdc_node) = (
+            graph.node_index(&wsol).unwrap(),
+            graph.node_index(&usdc).unwrap(),
+        );
+
+        // two pools on the pair, one neighbor
+        assert_eq!(graph.neighbors(wsol_node).collect::<Vec<_>>(), [usdc_node]);
+        assert_eq!(graph.neighbors(usdc_node).collect::<Vec<_>>(), [wsol_node]);
+        assert_eq!(graph.neighbors(graph.nodes.len()).count(), 0);
+        assert_eq!(graph.node_index(&Pubkey::new_unique()), None);
+        for (index, edge) in graph.edges.iter().enumerate() {
+            assert_eq!(graph.edge_index(&edge.address), Some(index));
+        }
+        assert_eq!(graph.edge_index(&wsol), None);
+
+        for address in [graph.edges[0].address, graph.edges[1].address] {
+            graph.disable_edge(&address).unwrap();
+        }
+        assert_eq!(graph.neighbors(wsol_node).count(), 0);
+    }
+
+    #[test]
+    fn test_has_edge_matches_pool_addresses() {
+        let graph = Graph::build_graph("./tests/test_data").unwrap();
+        let pools = graph.pool_addresses();
+
+        assert_eq!(pools.len(), graph.edges.len());
+        for edge in &graph.edges {
+            assert!(graph.has_edge(&edge.address));
+            assert!(pools.contains(&edge.address));
+        }
+        // a token or vault address is not a pool
+        assert!(!graph.has_edge(&graph.nodes[0].address));
+        assert!(!graph.has_edge(&graph.edges[0].token_vault_lowest));
+    }
+
+    #[test]
+    fn test_find_arbitrage_cycles_reports_profitable_direction() {
+        // pool 1 pays 0.255 USDC per SOL, pool 0 sells SOL back at 0.25 USDC
+        let sqrt_price_0 = 1u128 << 63;
+        let sqrt_price_1 = (0.255f64.sqrt() * 2f64.powi(64)) as u128;
+        let mut graph = test_two_pool_graph(sqrt_price_0, sqrt_price_1, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+
+        let opportunities = graph.find_arbitrage_cycles(DEFAULT_MIN_LOG_PROFIT);
+
+        assert_eq!(opportunities.len(), 1);
+        let opportunity = &opportunities[0];
+        assert_eq!(opportunity.cycle, vec![0, 1]);
+        assert!(!opportunity.direction);
+        assert_eq!(
+            opportunity.pools,
+            vec![graph.edges[0].address, graph.edges[1].address]
+        );
+        let expected = (1.02f64 * 0.9996 * 0.9996).log10();
+        assert!((opportunity.log_profit - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_find_arbitrage_cycles_through_one_pool() {
+        let sqrt_price_1 = (0.255f64.sqrt() * 2f64.powi(64)) as u128;
+        let mut graph = test_two_pool_graph(1u128 << 63, sqrt_price_1, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+        let pool = graph.edges[1].address;
+
+        assert_eq!(
+            graph.find_arbitrage_cycles_through(&pool, DEFAULT_MIN_LOG_PROFIT),
+            graph.find_arbitrage_cycles(DEFAULT_MIN_LOG_PROFIT)
+        );
+        assert!(
+            graph
+                .find_arbitrage_cycles_through(&Pubkey::new_unique(), DEFAULT_MIN_LOG_PROFIT)
+                .is_empty()
+        );
+        graph.disable_edge(&pool).unwrap();
+        assert!(
+            graph
+                .find_arbitrage_cycles_through(&pool, DEFAULT_MIN_LOG_PROFIT)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_evaluate_cycle_picks_backward_direction() {
+        let sqrt_price_1 = (0.255f64.sqrt() * 2f64.powi(64)) as u128;
+        let mut graph = test_two_pool_graph(1u128 << 63, sqrt_price_1, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+
+        let (direction, log_profit) = graph.evaluate_cycle(&[0, 1]).unwrap();
+
+        assert!(!direction);
+        assert!(log_profit > 0.0);
+        assert!(graph.cycle_log_profit(&[0, 1], true).unwrap() < 0.0);
+
+        // the same cycle listed the other way round is profitable forward
+        let (direction, reversed_profit) = graph.evaluate_cycle(&[1, 0]).unwrap();
+        assert!(direction);
+        assert!((reversed_profit - log_profit).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_price_in_wsol_through_the_deepest_pool() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        let sol = Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap();
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        let sol_node = graph.node_index(&sol).unwrap();
+        let edges = graph.edges_between(&sol, &usdc);
+        assert!(edges.len() >= 2);
+        assert_eq!(graph.price_in_wsol(&usdc), None);
+
+        // the deeper pool quotes 160 USDC per SOL, the others 150
+        for (i, &edge_index) in edges.iter().enumerate() {
+            let usdc_per_sol = if i == 1 { 160.0 } else { 150.0 };
+            let edge = &mut graph.edges[edge_index];
+            // the pool's own price is token b per token a in raw units
+            let sol_is_token_a = (edge.node_lowest == sol_node) != edge.reversed;
+            let raw_price: f64 = match sol_is_token_a {
+                true => usdc_per_sol * 1e6 / 1e9,
+                false => 1e9 / (usdc_per_sol * 1e6),
+            };
+            edge.sqrt_price = Some((raw_price.sqrt() * 2f64.powi(64)) as u128);
+            edge.liquidity = Some(if i == 1 { 1_000_000 } else { 1_000 });
+            edge.current_tick_index = Some(0);
+        }
+
+        let price = graph.price_in_wsol(&usdc).unwrap();
+        assert!((price * 160.0 - 1.0).abs() < 1e-9, "{}", price);
+        assert_eq!(graph.price_in_wsol(&sol), Some(1.0));
+        assert_eq!(graph.price_in_wsol(&Pubkey::new_unique()), None);
+
+        graph
+            .disable_edge(&graph.edges[edges[1]].address.clone())
+            .unwrap();
+        let price = graph.price_in_wsol(&usdc).unwrap();
+        assert!((price * 150.0 - 1.0).abs() < 1e-9, "{}", price);
+    }
 
-        let index = self.edges.len();
-        self.edges.push(edge);
-        self.address_to_edge.insert(address, index);
+    #[test]
+    fn test_evaluate_cycle_skips_unpriced_edges() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        graph.build_cycles(2).unwrap();
+        let sol = Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap();
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        let edges = graph.edges_between(&sol, &usdc);
+
+        assert_eq!(graph.evaluate_cycle(&edges[0..2]), None);
+    }
 
-        self.adjacency.get_mut(&idx_lowest).unwrap().insert(index);
-        self.adjacency.get_mut(&idx_highest).unwrap().insert(index);
+    #[test]
+    fn test_build_swap_instructions_for_orca_cycle() {
+        let sqrt_price_1 = (0.255f64.sqrt() * 2f64.powi(64)) as u128;
+        let mut graph = test_two_pool_graph(1u128 << 63, sqrt_price_1, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+        // ticks matching prices 0.25 and 0.255
+        graph.edges[0].current_tick_index = Some(-13_864);
+        graph.edges[1].current_tick_index = Some(-13_666);
+        let authority = Pubkey::new_unique();
+
+        let instructions = graph
+            .build_swap_instructions(&[0, 1], false, 1_000_000, &authority)
+            .unwrap();
 
-        Ok(index)
+        assert_eq!(instructions.len(), 2);
+        // the backward direction sells SOL into pool 1 first
+        let hop = &graph.edges[1];
+        let sol = graph.nodes[hop.node_lowest].address;
+        let usdc = graph.nodes[hop.node_highest].address;
+        let expected = vec![
+            AccountMeta::new_readonly(TokenProgram::Token.id(), false),
+            AccountMeta::new_readonly(TokenProgram::Token.id(), false),
+            AccountMeta::new_readonly(
+                Pubkey::from_str("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr").unwrap(),
+                false,
+            ),
+            AccountMeta::new_readonly(authority, true),
+            AccountMeta::new(hop.address, false),
+            AccountMeta::new_readonly(sol, false),
+            AccountMeta::new_readonly(usdc, false),
+            AccountMeta::new(
+                swap::associated_token_address(&authority, &sol, TokenProgram::Token),
+                false,
+            ),
+            AccountMeta::new(hop.token_vault_lowest, false),
+            AccountMeta::new(
+                swap::associated_token_address(&authority, &usdc, TokenProgram::Token),
+                false,
+            ),
+            AccountMeta::new(hop.token_vault_highest, false),
+        ];
+        let first = &instructions[0];
+        assert_eq!(first.program_id, Pubkey::from_str(ORCA_OWNER).unwrap());
+        assert_eq!(first.accounts[..11], expected[..]);
+        let tick_arrays = swap::tick_array_addresses(&hop.address, 64, -13_666, true);
+        for (meta, tick_array) in first.accounts[11..14].iter().zip(tick_arrays) {
+            assert_eq!(*meta, AccountMeta::new(tick_array, false));
+        }
+        assert_eq!(first.accounts.len(), 15);
+
+        // the second hop spends what the first is expected to return and guards the round trip
+        let usdc_amount = hop.amount_out(1_000_000, true).unwrap();
+        assert_eq!(instructions[1].data[8..16], usdc_amount.to_le_bytes());
+        assert_eq!(instructions[1].data[16..24], 1_000_000u64.to_le_bytes());
     }
 
-    fn insert_pool(&mut self, mut pool: PoolInfo) -> Result<()> {
-        let node0_index = self.insert_node(pool.token_a.take().unwrap())?;
-        let node1_index = self.insert_node(pool.token_b.take().unwrap())?;
+    #[test]
+    fn test_net_profit_lamports_subtracts_transaction_fee() {
+        let sqrt_price_1 = (0.255f64.sqrt() * 2f64.powi(64)) as u128;
+        let mut graph = test_two_pool_graph(1u128 << 63, sqrt_price_1, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+        graph.edges[0].current_tick_index = Some(-13_864);
+        graph.edges[1].current_tick_index = Some(-13_666);
+
+        // 2% spread less two 0.04% pool fees, ~19_180 lamports on 1_000_000
+        let usdc = graph.edges[1].amount_out(1_000_000, true).unwrap();
+        let sol = graph.edges[0].amount_out(usdc, false).unwrap();
+        let gross = sol as i64 - 1_000_000;
+        assert!((19_100..19_200).contains(&gross));
+
+        assert_eq!(
+            graph.net_profit_lamports(&[0, 1], false, 1_000_000, 5_000),
+            Some(gross - 5_000)
+        );
+        assert!(
+            graph
+                .net_profit_lamports(&[0, 1], false, 1_000_000, 25_000)
+                .unwrap()
+                < 0
+        );
+        // forward loses the same spread on top of the fee
+        assert!(
+            graph
+                .net_profit_lamports(&[0, 1], true, 1_000_000, 0)
+                .unwrap()
+                < 0
+        );
+    }
 
-        self.insert_edge(pool, node0_index, node1_index)?;
+    #[test]
+    fn test_net_profit_lamports_needs_a_wsol_cycle() {
+        let sqrt_price_1 = (0.255f64.sqrt() * 2f64.powi(64)) as u128;
+        let mut graph = test_two_pool_graph(1u128 << 63, sqrt_price_1, 1_000_000_000_000);
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        graph.set_base_tokens(vec![usdc]);
+        graph.build_cycles(2).unwrap();
+        graph.edges[0].current_tick_index = Some(-13_864);
+        graph.edges[1].current_tick_index = Some(-13_666);
+
+        // the amounts are USDC base units, the lamport fee can't come out of them
+        assert!(graph.cycle_amount_out(&[0, 1], false, 1_000_000).is_some());
+        assert_eq!(
+            graph.net_profit_lamports(&[0, 1], false, 1_000_000, 5_000),
+            None
+        );
+    }
 
-        Ok(())
+    #[test]
+    fn test_net_profit_lamports_past_i64_max_returns_none() {
+        // the second pool prices USDC 2^54 times higher than the first, so the cycle returns
+        // more lamports than an i64 holds
+        let mut graph = test_two_pool_graph(1u128 << 63, 1u128 << 90, 1u128 << 100);
+        graph.build_cycles(2).unwrap();
+        graph.edges[0].current_tick_index = Some(-13_864);
+        graph.edges[1].current_tick_index = Some(-13_666);
+
+        let amount_out = graph
+            .cycle_amount_out(&[0, 1], false, 1_000_000_000)
+            .unwrap();
+        assert!(amount_out > i64::MAX as u64);
+        assert_eq!(
+            graph.net_profit_lamports(&[0, 1], false, 1_000_000_000, 5_000),
+            None
+        );
     }
 
-    pub fn update_edge(&mut self, address: &Pubkey, data: PoolUpdate) -> Result<()> {
-        if let Some(edge_index) = self.address_to_edge.get(address)
-            && let Some(edge) = self.edges.get_mut(*edge_index)
-        {
-            edge.liquidity = Some(data.new_liquidity);
-            edge.sqrt_price = Some(data.new_sqrt_price);
-            edge.current_tick_index = Some(data.new_current_tick_index);
-            return Ok(());
-        }
-        Err(anyhow!("Edge with address {} doesn't exist", address))
+    #[test]
+    fn test_net_profit_lamports_unpriced_cycle_returns_none() {
+        let mut graph = test_two_pool_graph(1u128 << 63, 1u128 << 63, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+        graph.edges[0].current_tick_index = None;
+
+        assert_eq!(graph.net_profit_lamports(&[0, 1], true, 1_000, 0), None);
     }
 
-    pub fn build_graph(data_folder_path: &str) -> Result<Self> {
-        let pool_files = get_all_pool_files(data_folder_path)?;
+    #[test]
+    fn test_update_tick_arrays_routes_by_whirlpool() {
+        let mut graph = test_two_pool_graph(1u128 << 63, 1u128 << 63, 1_000_000_000_000);
+        graph.edges[0].current_tick_index = Some(-13_864);
 
-        let mut graph = Graph::default();
-        for pool_path in pool_files {
-            let raw_json = read_to_string(pool_path)?;
+        // five arrays around the current tick for each of the two priced pools
+        let addresses = graph.tick_array_addresses();
+        assert_eq!(addresses.len(), 10);
+        assert!(addresses.values().all(|&tick_spacing| tick_spacing == 64));
 
-            let deserialized: StoredPools = serde_json::from_str(&raw_json)?;
-            let pools: Vec<PoolInfo> = deserialized.all_pools;
+        let mut tick_array = test_tick_array(-16_896, &[(-13_888, 1)]);
+        tick_array.whirlpool = graph.edges[0].address;
+        graph.update_tick_arrays(&[tick_array, test_tick_array(0, &[(64, 1)])]);
 
-            for pool in pools {
-                if let Err(e) = graph.insert_pool(pool) {
-                    warn!("Failed to insert the pool: {:?}", e);
-                }
-            }
-        }
+        assert_eq!(graph.edges[0].ticks, BTreeMap::from([(-13_888, 1)]));
+        assert!(graph.edges[1].ticks.is_empty());
+    }
 
-        info!("Amount of Edges in the Graph: {:?}", graph.edges.len());
-        info!("Amount of Nodes in the Graph: {:?}", graph.nodes.len());
-        Ok(graph)
+    #[test]
+    fn test_update_edges_reports_unknown_addresses() {
+        let mut graph = test_two_pool_graph(1u128 << 63, 1u128 << 63, 1_000_000_000_000);
+        let update = |liquidity: u128| PoolUpdate {
+            new_liquidity: liquidity,
+            new_sqrt_price: 1u128 << 64,
+            new_current_tick_index: 0,
+            slot: 0,
+            fee_rate: None,
+            tick_spacing: None,
+        };
+        let unknown = Pubkey::new_unique();
+
+        let failures = graph.update_edges([
+            (graph.edges[0].address, update(5)),
+            (unknown, update(6)),
+            (graph.edges[1].address, update(7)),
+        ]);
+
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, unknown);
+        assert!(failures[0].1.to_string().contains("doesn't exist"));
+        assert_eq!(graph.edges[0].liquidity, Some(5));
+        assert_eq!(graph.edges[1].liquidity, Some(7));
     }
 
-    pub fn build_cycles(&mut self, max_depth: usize) -> Result<()> {
-        let start = Instant::now();
+    #[test]
+    fn test_update_edge_ignores_older_slot() {
+        let mut graph = test_two_pool_graph(1u128 << 63, 1u128 << 63, 1_000_000_000_000);
+        let address = graph.edges[0].address;
+        let update = |liquidity: u128, slot: u64| PoolUpdate {
+            new_liquidity: liquidity,
+            new_sqrt_price: 1u128 << 64,
+            new_current_tick_index: 0,
+            slot,
+            fee_rate: None,
+            tick_spacing: None,
+        };
 
-        let start_node = self.wsol_node;
-        let mut visited_edges: Vec<bool> = vec![false; self.edges.len()]; // bitmap
-        let mut path: Vec<usize> = Vec::with_capacity(max_depth);
-        let mut cycles: HashMap<String, Vec<Vec<usize>>> = HashMap::new();
+        graph.update_edge(&address, update(5, 10)).unwrap();
+        let stale = graph.update_edge(&address, update(6, 9));
 
-        self.dfs_recursive(
-            start_node,
-            start_node,
-            &mut visited_edges,
-            &mut path,
-            max_depth,
-            &mut cycles,
+        assert!(
+            stale
+                .unwrap_err()
+                .to_string()
+                .contains("older than slot 10")
         );
+        assert_eq!(graph.edges[0].liquidity, Some(5));
+        assert_eq!(graph.edges[0].last_updated_slot, Some(10));
 
-        self.all_cycles = cycles;
+        // the same slot can still carry a later write
+        graph.update_edge(&address, update(7, 10)).unwrap();
+        assert_eq!(graph.edges[0].liquidity, Some(7));
+    }
 
-        info!("Number of Keys: {:?}", &self.all_cycles.len());
+    #[test]
+    fn test_update_edge_refreshes_fee_rate_and_tick_spacing() {
+        let mut graph = test_two_pool_graph(1u128 << 63, 1u128 << 63, 1_000_000_000_000);
+        let address = graph.edges[0].address;
+        let mut tick_array = test_tick_array(0, &[(64, 1)]);
+        tick_array.whirlpool = address;
+        graph.update_tick_arrays(&[tick_array]);
+        let fee_rate = graph.edges[0].fee_rate;
+        let update = |fee_rate: Option<u32>, tick_spacing: Option<u16>| PoolUpdate {
+            new_liquidity: 1,
+            new_sqrt_price: 1u128 << 64,
+            new_current_tick_index: 0,
+            slot: 0,
+            fee_rate,
+            tick_spacing,
+        };
 
-        dbg!(&self.all_cycles);
+        graph.update_edge(&address, update(None, None)).unwrap();
+        assert_eq!(graph.edges[0].fee_rate, fee_rate);
+        assert_eq!(graph.edges[0].tick_spacing, 64);
 
-        let duration = start.elapsed();
-        info!("Cycles Building Took: {:?}", duration);
+        graph
+            .update_edge(&address, update(Some(100), Some(64)))
+            .unwrap();
+        assert_eq!(graph.edges[0].fee_rate, 100);
+        assert_eq!(graph.edges[0].ticks.len(), 1);
 
-        Ok(())
+        graph
+            .update_edge(&address, update(Some(100), Some(8)))
+            .unwrap();
+        assert_eq!(graph.edges[0].tick_spacing, 8);
+        assert!(graph.edges[0].ticks.is_empty());
+        assert_eq!(graph.edges[0].tick_coverage, None);
     }
 
-    fn dfs_recursive(
-        &self,
-        start_node: usize,
-        current_node: usize,
-        visited_edges: &mut Vec<bool>,
-        path: &mut Vec<usize>,
-        max_depth: usize,
-        cycles: &mut HashMap<String, Vec<Vec<usize>>>,
-    ) {
-        if path.len() >= max_depth {
-            return;
-        }
+    #[test]
+    fn test_coverage_of_partially_priced_graph() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        assert_eq!(graph.coverage(), 0.0);
+        assert_eq!(graph.stale_edges().len(), graph.edges.len());
 
-        for &edge_index in &self.adjacency[&current_node] {
-            if visited_edges[edge_index] {
-                continue;
-            }
+        let updates: Vec<(Pubkey, PoolUpdate)> = graph.edges[..69]
+            .iter()
+            .map(|edge| {
+                (
+                    edge.address,
+                    PoolUpdate {
+                        new_liquidity: 1,
+                        new_sqrt_price: 1u128 << 64,
+                        new_current_tick_index: 0,
+                        slot: 0,
+                        fee_rate: None,
+                        tick_spacing: None,
+                    },
+                )
+            })
+            .collect();
+        assert!(graph.update_edges(updates).is_empty());
+        // a price alone doesn't count
+        graph.edges[100].sqrt_price = Some(1u128 << 64);
+
+        assert_eq!(graph.coverage(), 0.5);
+        let stale = graph.stale_edges();
+        assert_eq!(stale.len(), 69);
+        assert!(stale.contains(&graph.edges[100].address));
+        assert!(!stale.contains(&graph.edges[0].address));
+        assert_eq!(Graph::default().coverage(), 0.0);
+    }
 
-            let edge = &self.edges[edge_index];
-            let other_node = edge.get_other_node(current_node).unwrap();
+    #[test]
+    fn test_build_swap_instructions_rejects_other_dexes() {
+        let mut graph = test_two_pool_graph(1u128 << 63, 1u128 << 63, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+        graph.edges[1].dex = DexType::Raydium;
 
-            visited_edges[edge_index] = true;
+        let result = graph.build_swap_instructions(&[0, 1], true, 1_000, &Pubkey::new_unique());
 
-            path.push(edge_index);
+        assert!(result.is_err());
+    }
 
-            if other_node == start_node && path.len() >= 2 {
-                let mut canonical = Self::canonicalize(path.as_ref());
+    #[test]
+    fn test_min_log_profit_filters_marginal_cycle() {
+        // 0.1% apart, which the two 0.04% fees nearly eat up
+        let sqrt_price_1 = (0.25025f64.sqrt() * 2f64.powi(64)) as u128;
+        let mut graph = test_two_pool_graph(1u128 << 63, sqrt_price_1, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+
+        let opportunities = graph.find_arbitrage_cycles(0.0);
+        assert_eq!(opportunities.len(), 1);
+        let log_profit = opportunities[0].log_profit;
+        assert!(log_profit > DEFAULT_MIN_LOG_PROFIT && log_profit < 1e-4);
+
+        assert!(graph.find_arbitrage_cycles(1e-4).is_empty());
+    }
 
-                let path_length: usize = canonical.len();
+    #[test]
+    fn test_find_arbitrage_cycles_skips_unpriced_cycles() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        graph.build_cycles(3).unwrap();
 
-                if let Some(pos) = canonical.iter().position(|pool_index| {
-                    let edge = &self.edges[*pool_index];
-                    let node_a = &self.nodes[edge.node_lowest];
-                    let node_b = &self.nodes[edge.node_highest];
-                    node_a.address == self.wsol_address || node_b.address == self.wsol_address
-                }) {
-                    canonical.rotate_left(pos);
-                }
+        assert!(
+            graph
+                .find_arbitrage_cycles(DEFAULT_MIN_LOG_PROFIT)
+                .is_empty()
+        );
+    }
 
-                self.check_cycle(&mut canonical);
+    #[test]
+    fn test_transfer_fee_makes_cycle_unprofitable() {
+        let sqrt_price_1 = (0.255f64.sqrt() * 2f64.powi(64)) as u128;
+        let mut graph = test_two_pool_graph(1u128 << 63, sqrt_price_1, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+        assert_eq!(graph.find_arbitrage_cycles(DEFAULT_MIN_LOG_PROFIT).len(), 1);
 
-                for pool_index in &canonical {
-                    let edge = &self.edges[*pool_index];
-                    let node_a = &self.nodes[edge.node_lowest];
-                    let node_b = &self.nodes[edge.node_highest];
+        // 1% on USDC, paid leaving the first pool and again entering the second
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        graph.update_transfer_fees(&HashMap::from([(usdc, 100)]));
 
-                    let addr_low = node_a.address.to_string();
-                    let addr_high = node_b.address.to_string();
+        assert!(
+            graph
+                .find_arbitrage_cycles(DEFAULT_MIN_LOG_PROFIT)
+                .is_empty()
+        );
+    }
 
-                    // Ensure lexicographic ordering
-                    let (left, right) = if addr_low < addr_high {
-                        (addr_low, addr_high)
-                    } else {
-                        (addr_high, addr_low)
-                    };
+    #[test]
+    fn test_amount_out_subtracts_transfer_fees() {
+        let edge = test_priced_edge(1_000_000_000_000);
+        let without_fee = edge.amount_out(1_000_000, true).unwrap();
 
-                    let key = format!("{}-{}-{}", left, right, path_length);
-                    match cycles.get_mut(&key) {
-                        Some(cycle_vec) => cycle_vec.push(canonical.clone()),
-                        None => {
-                            cycles.insert(key, vec![canonical.clone()]);
-                        }
-                    }
-                }
-            }
+        let mut edge = test_priced_edge(1_000_000_000_000);
+        edge.transfer_fee_bps_highest = 100;
 
-            self.dfs_recursive(
-                start_node,
-                other_node,
-                visited_edges,
-                path,
-                max_depth,
-                cycles,
-            );
-            path.pop();
-            visited_edges[edge_index] = false;
-        }
+        let with_fee = edge.amount_out(1_000_000, true).unwrap();
+        assert!((with_fee as f64 - without_fee as f64 * 0.99).abs() <= 1.0);
+        assert!(
+            edge.get_exchange_rate_with_fee(true)
+                < test_priced_edge(1).get_exchange_rate_with_fee(true)
+        );
     }
 
-    #[inline]
-    fn canonicalize(cycle: &[usize]) -> Vec<usize> {
-        let n = cycle.len();
-        if n == 0 {
-            return Vec::new();
-        }
+    #[test]
+    fn test_build_cycles_skips_pools_below_min_liquidity() {
+        let mut graph = test_two_pool_graph(1u128 << 63, 1u128 << 63, 1_000_000);
 
-        let (min_idx, _) = cycle
-            .iter()
-            .enumerate()
-            .min_by_key(|&(_, edge_idx)| edge_idx)
-            .unwrap();
+        graph.set_min_liquidity(1_000_000);
+        graph.build_cycles(2).unwrap();
+        assert!(!graph.all_cycles.is_empty());
 
-        let forward: Vec<usize> = (0..n).map(|i| cycle[(min_idx + i) % n]).collect();
+        graph.set_min_liquidity(1_000_001);
+        graph.build_cycles(2).unwrap();
+        assert!(graph.all_cycles.is_empty());
+    }
 
-        let mut reversed: Vec<usize> = cycle.iter().rev().copied().collect();
+    #[test]
+    fn test_min_liquidity_keeps_unread_pools_out_of_the_scans() {
+        let sqrt_price_1 = (0.255f64.sqrt() * 2f64.powi(64)) as u128;
+        let mut graph = test_two_pool_graph(1u128 << 63, sqrt_price_1, 1_000_000);
+        graph.build_cycles(2).unwrap();
+        // as if the pool hadn't been refreshed, the search keeps it but no scan reports it
+        graph.edges[0].liquidity = None;
+        graph.set_min_liquidity(1);
+        graph.build_cycles(2).unwrap();
+        assert!(!graph.all_cycles.is_empty());
+
+        assert!(graph.find_arbitrage_cycles(0.0).is_empty());
+        let address = graph.edges[1].address;
+        assert!(
+            graph
+                .find_arbitrage_cycles_through(&address, 0.0)
+                .is_empty()
+        );
 
-        let (rev_min_idx, _) = reversed
-            .iter()
-            .enumerate()
-            .min_by_key(|&(_, edge_idx)| edge_idx)
+        graph.edges[0].liquidity = Some(1_000_000);
+        assert_eq!(graph.find_arbitrage_cycles(0.0).len(), 1);
+    }
+
+    #[test]
+    fn test_disabled_edge_leaves_the_cycle_set() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        graph.build_cycles(3).unwrap();
+        let all_cycles = graph.stats().cycles;
+        let edge_index = *graph
+            .all_cycles
+            .values()
+            .flatten()
+            .next()
+            .unwrap()
+            .first()
             .unwrap();
-        reversed.rotate_left(rev_min_idx);
+        let address = graph.edges[edge_index].address;
 
-        if forward <= reversed {
-            forward
-        } else {
-            reversed
-        }
-    }
+        graph.disable_edge(&address).unwrap();
+        graph.build_cycles(3).unwrap();
 
-    #[inline]
-    pub fn check_cycle(&self, cycle: &mut [usize]) -> bool {
-        let cycle_len = cycle.len();
-        let mut need_change = false;
-        let mut last_node: usize = self.wsol_node; // WSOL
-        let mut problematic_edge_index: usize = cycle_len; // set to unreal index
+        assert!(graph.stats().cycles < all_cycles);
+        assert!(
+            graph
+                .all_cycles
+                .values()
+                .flatten()
+                .all(|cycle| !cycle.contains(&edge_index))
+        );
+        let update = || PoolUpdate {
+            new_liquidity: 1,
+            new_sqrt_price: 1u128 << 64,
+            new_current_tick_index: 0,
+            slot: 0,
+            fee_rate: None,
+            tick_spacing: None,
+        };
+        graph.update_edge(&address, update()).unwrap();
+        assert_eq!(graph.edges[edge_index].sqrt_price, None);
 
-        for (index, pool) in cycle.iter().enumerate() {
-            let edge = &self.edges[*pool];
-            match edge.get_other_node(last_node) {
-                Some(other_node) => last_node = other_node,
-                None => {
-                    need_change = true;
-                    problematic_edge_index = index;
-                    break;
-                }
-            }
-        }
-        if !need_change && last_node != 0 {
-            problematic_edge_index = cycle_len - 1;
-            need_change = true;
-            println!("Last Edge Was Wrong");
-        }
+        graph.enable_edge(&address).unwrap();
+        graph.build_cycles(3).unwrap();
+        assert_eq!(graph.stats().cycles, all_cycles);
+        graph.update_edge(&address, update()).unwrap();
+        assert_eq!(graph.edges[edge_index].sqrt_price, Some(1u128 << 64));
 
-        if need_change {
-            // info!(%problematic_edge_index, "Wrong Edge Index");
-            // println!("Cycle before rotation: {:?}", &cycle);
-            if problematic_edge_index < cycle_len && problematic_edge_index > 0 {
-                cycle.rotate_left(1);
-            } else if problematic_edge_index == 0 {
-                cycle.rotate_left(cycle_len - 1);
-            }
-            // println!("Cycle after rotation: {:?}", &cycle);
-        }
+        assert!(graph.disable_edge(&Pubkey::new_unique()).is_err());
+    }
 
-        need_change
+    #[test]
+    fn test_find_arbitrage_cycles_skips_disabled_pools_without_rebuild() {
+        let sqrt_price_1 = (0.255f64.sqrt() * 2f64.powi(64)) as u128;
+        let mut graph = test_two_pool_graph(1u128 << 63, sqrt_price_1, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+        assert_eq!(graph.find_arbitrage_cycles(DEFAULT_MIN_LOG_PROFIT).len(), 1);
+
+        let address = graph.edges[1].address;
+        graph.disable_edge(&address).unwrap();
+
+        assert!(
+            graph
+                .find_arbitrage_cycles(DEFAULT_MIN_LOG_PROFIT)
+                .is_empty()
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::vec;
+    #[test]
+    fn test_find_arbitrage_cycles_skips_pools_with_empty_vault() {
+        let sqrt_price_1 = (0.255f64.sqrt() * 2f64.powi(64)) as u128;
+        let mut graph = test_two_pool_graph(1u128 << 63, sqrt_price_1, 1_000_000_000_000);
+        graph.build_cycles(2).unwrap();
+        let empty_vault = graph.edges[1].token_vault_lowest;
+
+        graph.update_vaults(&HashMap::from([(
+            empty_vault,
+            TokenAccount {
+                mint: Pubkey::new_unique(),
+                amount: 0,
+                program: TokenProgram::Token2022,
+                withheld_transfer_fee: None,
+            },
+        )]));
+
+        assert_eq!(
+            graph.edges[1].token_program(0),
+            Some(TokenProgram::Token2022)
+        );
 
-    use super::*;
+        assert!(
+            graph
+                .find_arbitrage_cycles(DEFAULT_MIN_LOG_PROFIT)
+                .is_empty()
+        );
+    }
 
     #[test]
     fn test_canonicalize_empty_cycle() {
         let cycle: Vec<usize> = vec![];
-        let result = Graph::canonicalize(&cycle);
+        let result = Graph::canonicalize(&cycle, |_| true).unwrap();
         assert!(result.is_empty());
     }
 
     #[test]
     fn test_canonicalize_single_step() {
         let cycle = vec![42];
-        let result = Graph::canonicalize(&cycle);
+        let result = Graph::canonicalize(&cycle, |_| true).unwrap();
         assert_eq!(result, vec![42]);
     }
 
     #[test]
     fn test_canonicalize_two_steps_forward() {
         let cycle = vec![10, 20];
-        let result = Graph::canonicalize(&cycle);
+        let result = Graph::canonicalize(&cycle, |_| true).unwrap();
         assert_eq!(result, cycle);
     }
 
     #[test]
     fn test_canonicalize_two_steps_reverse_orientation() {
         let cycle = vec![20, 10];
-        let result = Graph::canonicalize(&cycle);
+        let result = Graph::canonicalize(&cycle, |_| true).unwrap();
         assert_eq!(result, vec![10, 20]);
     }
 
@@ -476,8 +3654,8 @@ mod tests {
         let cycle = vec![123, 321, 0, 222];
         let rotated = vec![321, 0, 222, 123];
 
-        let result = Graph::canonicalize(&cycle);
-        let rotated_result = Graph::canonicalize(&rotated);
+        let result = Graph::canonicalize(&cycle, |_| true).unwrap();
+        let rotated_result = Graph::canonicalize(&rotated, |_| true).unwrap();
 
         assert_eq!(result, rotated_result);
     }
@@ -487,12 +3665,65 @@ mod tests {
         let cycle = vec![123, 321, 0, 222];
         let reversed = vec![222, 0, 321, 123];
 
-        let result = Graph::canonicalize(&cycle);
-        let reversed_result = Graph::canonicalize(&reversed);
+        let result = Graph::canonicalize(&cycle, |_| true).unwrap();
+        let reversed_result = Graph::canonicalize(&reversed, |_| true).unwrap();
 
         assert_eq!(result, reversed_result);
     }
 
+    #[test]
+    fn test_canonicalize_only_returns_valid_candidates() {
+        let cycle = vec![5, 1, 9];
+
+        let result = Graph::canonicalize(&cycle, |candidate| candidate[0] == 9);
+
+        assert_eq!(result, Some(vec![9, 1, 5]));
+        assert_eq!(Graph::canonicalize(&cycle, |_| false), None);
+    }
+
+    #[test]
+    fn test_rotations_and_reflections_of_a_cycle_store_one_entry() {
+        let graph = Graph::build_graph("./tests/test_data").unwrap();
+        let mut cycles = CycleSets::new();
+        let mut path = Vec::new();
+        let wsol_node = wsol_node(&graph);
+        let found_cycles = AtomicUsize::new(0);
+        graph.dfs_iterative(
+            wsol_node,
+            wsol_node,
+            &[],
+            &mut vec![false; graph.edges.len()],
+            &mut path,
+            3,
+            &mut |path| graph.record_cycle(path, wsol_node, &mut cycles),
+            &found_cycles,
+        );
+        let cycle = cycles
+            .values()
+            .flatten()
+            .find(|cycle| cycle.len() == 3)
+            .unwrap()
+            .clone();
+
+        let mut reflected = cycle.clone();
+        reflected.reverse();
+        for variant in [cycle.clone(), reflected] {
+            for shift in 0..variant.len() {
+                let mut rotated = variant.clone();
+                rotated.rotate_left(shift);
+
+                let canonical = Graph::canonicalize(&rotated, |candidate| {
+                    graph.is_walk_from(candidate, wsol_node)
+                });
+                assert_eq!(canonical.as_ref(), Some(&cycle));
+            }
+        }
+
+        // each cycle is counted once, by the one traversal that stored it
+        let unique: HashSet<&Vec<usize>> = cycles.values().flatten().collect();
+        assert_eq!(found_cycles.load(Ordering::Relaxed), unique.len());
+    }
+
     #[test]
     fn test_insert_node_with_invalid_address_returns_error() {
         let mut graph = Graph::default();
@@ -509,6 +3740,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_insert_node_without_decimals_or_address_returns_error() {
+        let mut graph = Graph::default();
+        let result = graph.insert_node(TokenInfo {
+            address: Some("Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE".to_string()),
+            decimals: None,
+            name: None,
+            symbol: None,
+        });
+
+        assert!(result.unwrap_err().to_string().contains("missing decimals"));
+
+        let result = graph.insert_node(TokenInfo {
+            address: None,
+            decimals: Some(6),
+            name: None,
+            symbol: None,
+        });
+
+        assert!(result.is_err());
+        assert!(graph.nodes.is_empty());
+    }
+
+    #[test]
+    fn test_insert_pool_without_token_decimals_returns_error() {
+        let mut graph = Graph::default();
+
+        let result = graph.insert_pool(PoolInfo {
+            address: Some("Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE".to_string()),
+            fee_rate: Some(400),
+            pool_type: Some(PoolType::Concentrated),
+            dex: Some(DexType::Orca),
+            tick_spacing: Some(64),
+            token_a: Some(TokenInfo {
+                address: Some("So11111111111111111111111111111111111111112".to_string()),
+                decimals: None,
+                name: None,
+                symbol: None,
+            }),
+            token_b: Some(TokenInfo {
+                address: Some("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string()),
+                decimals: Some(6),
+                name: None,
+                symbol: None,
+            }),
+            token_vault_a: Some("EUuUbDcafPrmVTD5M6qoJAoyyNbihBhugADAxRMn5he9".to_string()),
+            token_vault_b: Some("2WLWEuKDgkDUccTpbwYp1GToYktiSB1cXvreHUwiSUVP".to_string()),
+            config: Some("2LecshUwdy9xi7meFgHtFJQNSKk4KdTrcpvaB56dP2NQ".to_string()),
+        });
+
+        assert!(result.unwrap_err().to_string().contains("missing decimals"));
+        assert!(graph.nodes.is_empty());
+        assert!(graph.edges.is_empty());
+    }
+
     #[test]
     fn test_insert_node_add_two_same_nodes_returns_same_index() {
         let mut graph = Graph::default();
@@ -534,7 +3820,6 @@ mod tests {
     #[test]
     fn test_insert_node_add_two_nodes_returns_indexes() {
         let mut graph = Graph::default();
-        let wsol_address = Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap();
         let result_1 = graph.insert_node(TokenInfo {
             address: Some("Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE".to_string()),
             decimals: Some(18),
@@ -549,7 +3834,7 @@ mod tests {
             symbol: Some("Test Symbol".to_string()),
         });
 
-        assert_eq!(graph.wsol_address, wsol_address);
+        assert_eq!(graph.base_tokens, default_base_tokens());
         assert_eq!(graph.nodes.len(), 2);
         assert_eq!(result_1.unwrap(), 0);
         assert_eq!(result_2.unwrap(), 1);
@@ -633,7 +3918,60 @@ mod tests {
         assert_eq!(graph.edges.len(), 1);
         assert_eq!(graph.address_to_edge.len(), 1);
         assert_eq!(graph.address_to_node.len(), 2);
-        assert_eq!(graph.wsol_node, 0);
+        assert_eq!(wsol_node(&graph), 0);
+    }
+
+    #[test]
+    fn test_insert_pool_with_the_same_token_twice_returns_error() {
+        let mut graph = Graph::default();
+        let wsol = TokenInfo {
+            address: Some("So11111111111111111111111111111111111111112".to_string()),
+            decimals: Some(9),
+            name: Some("Wrapped SOL".to_string()),
+            symbol: Some("SOL".to_string()),
+        };
+
+        let result = graph.insert_pool(PoolInfo {
+            address: Some("Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE".to_string()),
+            fee_rate: Some(400),
+            pool_type: Some(PoolType::Concentrated),
+            dex: Some(DexType::Orca),
+            tick_spacing: Some(64),
+            token_a: Some(wsol.clone()),
+            token_b: Some(wsol),
+            token_vault_a: Some("EUuUbDcafPrmVTD5M6qoJAoyyNbihBhugADAxRMn5he9".to_string()),
+            token_vault_b: Some("2WLWEuKDgkDUccTpbwYp1GToYktiSB1cXvreHUwiSUVP".to_string()),
+            config: Some("2LecshUwdy9xi7meFgHtFJQNSKk4KdTrcpvaB56dP2NQ".to_string()),
+        });
+
+        assert!(result.unwrap_err().to_string().contains("on both sides"));
+        assert!(graph.edges.is_empty());
+        assert!(graph.address_to_edge.is_empty());
+        assert!(graph.adjacency[&wsol_node(&graph)].is_empty());
+    }
+
+    #[test]
+    fn test_insert_pool_twice_keeps_one_edge() {
+        let mut graph = test_two_pool_graph(1u128 << 64, 1u128 << 64, 1_000);
+        let duplicate = PoolInfo {
+            address: Some("Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE".to_string()),
+            fee_rate: Some(100),
+            pool_type: Some(PoolType::Concentrated),
+            dex: Some(DexType::Raydium),
+            tick_spacing: Some(1),
+            token_a: None,
+            token_b: None,
+            token_vault_a: Some("EUuUbDcafPrmVTD5M6qoJAoyyNbihBhugADAxRMn5he9".to_string()),
+            token_vault_b: Some("2WLWEuKDgkDUccTpbwYp1GToYktiSB1cXvreHUwiSUVP".to_string()),
+            config: Some("2LecshUwdy9xi7meFgHtFJQNSKk4KdTrcpvaB56dP2NQ".to_string()),
+        };
+
+        let index = graph.insert_edge(duplicate, 0, 1).unwrap();
+
+        assert_eq!(index, 0);
+        assert_eq!(graph.edges.len(), 2);
+        assert_eq!(graph.edges[0].fee_rate, 400);
+        assert_eq!(graph.adjacency[&0].len(), 2);
     }
 
     #[test]
@@ -669,6 +4007,9 @@ mod tests {
             new_liquidity: 123456,
             new_sqrt_price: 1234567,
             new_current_tick_index: -1234,
+            slot: 0,
+            fee_rate: None,
+            tick_spacing: None,
         };
         let test_addres = Pubkey::from_str("Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE").unwrap();
         let result = graph.update_edge(&test_addres, test_edge_update_data);
@@ -3,9 +3,17 @@ use std::{fs::read_dir, path::PathBuf};
 use anyhow::Result;
 
 pub mod bootstrap;
+pub mod cli;
+pub mod config;
 pub mod decoders;
 pub mod deshred;
 pub mod graph;
+pub mod lookup_tables;
+pub mod metrics;
+pub mod opportunities;
+pub mod refresh;
+pub mod subscribe;
+pub mod swap;
 pub fn get_all_pool_files(data_folder_path: &str) -> Result<Vec<PathBuf>> {
     Ok(Vec::from_iter(
         read_dir(data_folder_path)?
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use solana_sdk::{account::Account, message::VersionedMessage, pubkey::Pubkey};
+
+use crate::decoders::{DecodeError, decode_lookup_table};
+
+// address lookup table contents by table address. Tables only ever grow, so an index past the
+// cached end means the table was extended since it was fetched
+#[derive(Debug, Default)]
+pub struct LookupTableCache {
+    tables: HashMap<Pubkey, Vec<Pubkey>>,
+}
+
+impl LookupTableCache {
+    pub fn insert(&mut self, table: Pubkey, addresses: Vec<Pubkey>) {
+        self.tables.insert(table, addresses);
+    }
+
+    pub fn insert_account(&mut self, table: Pubkey, account: &Account) -> Result<(), DecodeError> {
+        self.insert(table, decode_lookup_table(account)?);
+        Ok(())
+    }
+
+    // tables the message needs that aren't cached or are too short for its indexes
+    pub fn missing_tables(&self, message: &VersionedMessage) -> Vec<Pubkey> {
+        let Some(lookups) = message.address_table_lookups() else {
+            return Vec::new();
+        };
+
+        lookups
+            .iter()
+            .filter(|lookup| {
+                let cached_len = self.tables.get(&lookup.account_key).map(Vec::len);
+                lookup
+                    .writable_indexes
+                    .iter()
+                    .chain(&lookup.readonly_indexes)
+                    .any(|&index| cached_len.is_none_or(|len| index as usize >= len))
+            })
+            .map(|lookup| lookup.account_key)
+            .collect()
+    }
+
+    // the full account list instructions index into: static keys, then every table's writable
+    // addresses, then every table's readonly ones. None until missing_tables is empty
+    pub fn account_keys(&self, message: &VersionedMessage) -> Option<Vec<Pubkey>> {
+        let mut keys = message.static_account_keys().to_vec();
+        let Some(lookups) = message.address_table_lookups() else {
+            return Some(keys);
+        };
+
+        let mut readonly = Vec::new();
+        for lookup in lookups {
+            let table = self.tables.get(&lookup.account_key)?;
+            for &index in &lookup.writable_indexes {
+                keys.push(*table.get(index as usize)?);
+            }
+            for &index in &lookup.readonly_indexes {
+                readonly.push(*table.get(index as usize)?);
+            }
+        }
+        keys.extend(readonly);
+
+        Some(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{
+        hash::Hash,
+        instruction::CompiledInstruction,
+        message::{
+            MessageHeader,
+            v0::{self, MessageAddressTableLookup},
+        },
+    };
+
+    use super::*;
+
+    fn v0_message(
+        static_keys: Vec<Pubkey>,
+        lookups: Vec<MessageAddressTableLookup>,
+    ) -> VersionedMessage {
+        VersionedMessage::V0(v0::Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            },
+            account_keys: static_keys,
+            recent_blockhash: Hash::default(),
+            // the program is static, the pool and its vault come from the table
+            instructions: vec![CompiledInstruction {
+                program_id_index: 1,
+                accounts: vec![0, 2, 3],
+                data: vec![],
+            }],
+            address_table_lookups: lookups,
+        })
+    }
+
+    #[test]
+    fn test_resolves_lookup_table_addresses() {
+        let (payer, program, table) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let table_addresses = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let message = v0_message(
+            vec![payer, program],
+            vec![MessageAddressTableLookup {
+                account_key: table,
+                writable_indexes: vec![1],
+                readonly_indexes: vec![0],
+            }],
+        );
+        let mut cache = LookupTableCache::default();
+
+        assert_eq!(cache.missing_tables(&message), vec![table]);
+        assert_eq!(cache.account_keys(&message), None);
+
+        cache.insert(table, table_addresses.clone());
+
+        assert!(cache.missing_tables(&message).is_empty());
+        assert_eq!(
+            cache.account_keys(&message).unwrap(),
+            vec![payer, program, table_addresses[1], table_addresses[0]]
+        );
+    }
+
+    #[test]
+    fn test_extended_table_is_missing_again() {
+        let table = Pubkey::new_unique();
+        let message = v0_message(
+            vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            vec![MessageAddressTableLookup {
+                account_key: table,
+                writable_indexes: vec![],
+                readonly_indexes: vec![2],
+            }],
+        );
+        let mut cache = LookupTableCache::default();
+        cache.insert(table, vec![Pubkey::new_unique(), Pubkey::new_unique()]);
+
+        assert_eq!(cache.missing_tables(&message), vec![table]);
+        assert_eq!(cache.account_keys(&message), None);
+    }
+
+    #[test]
+    fn test_legacy_message_uses_static_keys() {
+        let keys = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let message = VersionedMessage::Legacy(solana_sdk::message::Message {
+            header: MessageHeader::default(),
+            account_keys: keys.clone(),
+            recent_blockhash: Hash::default(),
+            instructions: vec![],
+        });
+
+        let cache = LookupTableCache::default();
+
+        assert!(cache.missing_tables(&message).is_empty());
+        assert_eq!(cache.account_keys(&message), Some(keys));
+    }
+}
@@ -1,116 +1,324 @@
-use std::{env, fs::read_to_string, str::FromStr, sync::Arc, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::BufReader,
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Result;
-use client::{bootstrap, decoders, deshred, get_all_pool_files, graph};
-use futures::future::join_all;
+use clap::Parser;
+use client::{
+    bootstrap::{
+        self,
+        limits::BootstrapLimits,
+        retry::{RetryPolicy, TimeoutPolicy},
+    },
+    cli::{self, Cli, Command, ScanArgs},
+    config::Config,
+    decoders, deshred, get_all_pool_files, graph, metrics,
+    opportunities::{self, OpportunityRecord},
+    refresh, subscribe,
+};
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_commitment_config::CommitmentConfig;
-use solana_sdk::{account::Account, pubkey::Pubkey};
-use tracing::{info, warn};
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, debug, info, info_span, warn};
+use tracing_subscriber::fmt::format::FmtSpan;
 
 fn load_pools(data_folder_path: &str) -> anyhow::Result<Vec<Pubkey>> {
     let pool_files = get_all_pool_files(data_folder_path)?;
 
     let mut addresses = Vec::new();
+    let mut skipped_addresses = 0;
 
     for pool_path in pool_files {
-        let raw_json = read_to_string(pool_path)?;
-        let deserialized: bootstrap::pool_schema::StoredPools = serde_json::from_str(&raw_json)?;
-
-        addresses.extend(
-            deserialized
-                .all_pools
-                .iter()
-                .filter_map(|pool| pool.address.as_ref())
-                .map(|addr| addr.parse::<Pubkey>().expect("Failed to parse")),
-        );
+        let reader = BufReader::new(File::open(&pool_path)?);
+
+        // same as build_graph, a malformed file keeps the pools read before the error
+        let parsed = bootstrap::pool_schema::for_each_stored_pool(reader, |pool| {
+            if let Some(address) = pool.address {
+                match address.parse::<Pubkey>() {
+                    Ok(address) => addresses.push(address),
+                    Err(e) => {
+                        warn!("Skipping pool address {}: {}", address, e);
+                        skipped_addresses += 1;
+                    }
+                }
+            }
+        });
+        if let Err(e) = parsed {
+            warn!("Skipping the rest of {}: {}", pool_path.display(), e);
+        }
+    }
+    if skipped_addresses > 0 {
+        warn!("{} pool addresses failed to parse", skipped_addresses);
     }
 
     Ok(addresses)
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
-    let args: Vec<String> = env::args().collect();
+fn rpc_client(config: &Config) -> Arc<RpcClient> {
+    Arc::new(RpcClient::new_with_commitment(
+        config.rpc_url.clone(),
+        CommitmentConfig::confirmed(),
+    ))
+}
 
-    const DATA_FOLDER: &str = "./cached-blockchain-data";
+// the long running commands stop cleanly on Ctrl-C
+fn shutdown_on_ctrl_c() -> CancellationToken {
+    let shutdown = CancellationToken::new();
+    let on_ctrl_c = shutdown.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            info!("Received Ctrl-C, shutting down");
+            on_ctrl_c.cancel();
+        }
+    });
+    shutdown
+}
 
-    if args.contains(&"setup".to_string()) {
-        let start = Instant::now();
-        //update cached pools data
-        let _ = bootstrap::update_all(DATA_FOLDER, false).await;
-        let duration = start.elapsed();
-        println!("Bootstrap took: {:?}", duration);
-    }
+async fn setup(config: &Config, limits: &BootstrapLimits, merge: bool) -> Result<()> {
+    //update cached pools data
+    bootstrap::update_all(
+        config,
+        Some(rpc_client(config)),
+        &config.data_dir,
+        limits,
+        &RetryPolicy::default(),
+        merge,
+    )
+    .instrument(info_span!(
+        "bootstrap",
+        cluster = ?config.cluster,
+        source = ?limits.source,
+        merge,
+    ))
+    .await?;
+    // the snapshot no longer matches the refreshed pool files
+    let _ = std::fs::remove_file(config.graph_snapshot_path());
+
+    Ok(())
+}
 
-    deshred::deshred().await?;
+// tells whether the cached pools still parse, so users know when to re-run setup
+fn validate(config: &Config) -> Result<()> {
+    let report = bootstrap::pool_schema::validate_pool_files(&config.data_dir)?;
+    info!(
+        "Checked {} pool files: {} valid pools, {} invalid pools, {} unparseable files",
+        report.files, report.valid_pools, report.invalid_pools, report.unparseable_files
+    );
+    for error in &report.errors {
+        warn!("{}", error);
+    }
 
-    panic!("Test Panic");
-    let mut graph = graph::Graph::build_graph(DATA_FOLDER)?;
+    if report.unparseable_files > 0 {
+        anyhow::bail!(
+            "{} pool files don't match the current schema, re-run setup",
+            report.unparseable_files
+        );
+    }
+    if report.invalid_pools > 0 {
+        warn!("Invalid pools are skipped when building the graph, re-run setup to refresh them");
+    }
+    Ok(())
+}
 
-    graph.build_cycles(4)?;
+async fn scan(config: &Config, args: &ScanArgs) -> Result<()> {
+    let data_dir = &config.data_dir;
+    // created up front so a bad output path fails before the slow graph build
+    let mut sink = opportunities::sink_for(args.output_format, args.output.as_deref(), data_dir)?;
+    let snapshot_path = &config.graph_snapshot_path();
+    let mut graph = match graph::Graph::load(snapshot_path) {
+        Ok(graph) => graph,
+        Err(e) => {
+            info!("No usable graph snapshot, rebuilding: {:?}", e);
+            graph::Graph::build_graph(data_dir)?
+        }
+    };
+    graph.set_max_cycles(args.max_cycles);
+    graph.set_min_liquidity(args.min_liquidity);
+    graph.set_collapse_parallel_pools(args.collapse_parallel_pools);
+    // an empty list means the flag wasn't given
+    if !args.base_tokens.is_empty() {
+        graph.set_base_tokens(args.base_tokens.clone());
+    }
+    // a snapshot from a scan with other flags keeps its pools but not its cycles. collapsing
+    // keeps the deepest pool of each pair and min_liquidity prunes the shallow ones, so those
+    // cycles wait for the liquidity read below
+    let needs_liquidity = args.collapse_parallel_pools || args.min_liquidity > 0;
+    if !needs_liquidity && !graph.has_cycles_for(args.max_depth) {
+        graph.build_cycles(args.max_depth)?;
+        graph.save(snapshot_path)?;
+    }
 
-    //https://api.mainnet-beta.solana.com
-    //https://api.devnet.solana.com
-    let client = Arc::new(RpcClient::new_with_commitment(
-        "https://api.mainnet-beta.solana.com".to_string(),
-        CommitmentConfig::confirmed(),
-    ));
+    let client: Arc<dyn refresh::AccountFetcher> = rpc_client(config);
 
-    let addresses = load_pools(DATA_FOLDER).unwrap();
+    let addresses = load_pools(data_dir)?;
     info!("Amount of Addresses: {:?}", addresses.len());
 
-    let chunks: Vec<Vec<Pubkey>> = addresses.chunks(100).map(|c| c.to_vec()).collect();
-    let number_of_chunks = chunks.len();
-    let start = Instant::now();
+    let batch_timeout = Duration::from_millis(args.batch_timeout_ms);
+    let timeout = TimeoutPolicy {
+        initial_timeout: batch_timeout,
+        max_timeout: batch_timeout * 4,
+        ..TimeoutPolicy::default()
+    };
+    let vault_accounts =
+        refresh::refresh_graph(&mut graph, client.clone(), &addresses, config, &timeout).await;
+    if needs_liquidity {
+        graph.build_cycles(args.max_depth)?;
+        graph.save(snapshot_path)?;
+    }
 
-    let accounts_data: Vec<(Pubkey, Account)> = join_all(chunks.into_iter().map(|chunk| {
-        let client = Arc::clone(&client);
-        let chunk_clone = chunk.clone(); // local chunk
-        tokio::spawn(async move {
-            let accounts = client.get_multiple_accounts(&chunk_clone).await.unwrap();
-            // zip addresses with accounts, keep only Some(account)
-            chunk_clone
-                .into_iter()
-                .zip(accounts.into_iter())
-                .filter_map(|(address, account_opt)| account_opt.map(|acc| (address, acc)))
-                .collect::<Vec<_>>()
-        })
-    }))
-    .await
-    .into_iter()
-    .filter_map(|join_result| match join_result {
-        Ok(accounts) => Some(accounts), // Vec<(Pubkey, Account)>
-        Err(_) => {
-            warn!("A task panicked, skipping chunk");
-            None
-        }
-    })
-    .flatten()
-    .collect();
-
-    for (address, account) in accounts_data {
-        match decoders::decode_account(&account) {
-            Ok(data) => {
-                if let Err(e) = graph.update_edge(&address, data) {
-                    warn!("Failed to update edge {}: {:?}", address, e);
-                }
-            }
-            Err(e) => {
-                warn!("Failed to decode account {}: {:?}", address, e);
+    // only Token-2022 mints can carry a transfer fee
+    let fee_mints: Vec<Pubkey> = vault_accounts
+        .values()
+        .filter(|vault| vault.program == decoders::TokenProgram::Token2022)
+        .map(|vault| vault.mint)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    // same deadlines as the pools, a failed chunk leaves its mints without a fee
+    let mints = refresh::refresh_accounts(
+        &client,
+        fee_mints.into(),
+        config.account_chunk_size,
+        &timeout,
+    )
+    .await;
+    let mut transfer_fees = HashMap::new();
+    for (mint, account, _) in mints {
+        match decoders::decode_mint_transfer_fee_bps(&account) {
+            Ok(bps) => {
+                transfer_fees.insert(mint, bps);
             }
+            Err(e) => warn!("Failed to decode mint {}: {}", mint, e),
         }
     }
+    graph.update_transfer_fees(&transfer_fees);
 
-    let duration = start.elapsed();
-    info!(number_of_chunks, "Number of chunks: ");
-    info!(
-        "Average Duration per Chunk: {:?}",
-        duration.div_f32(number_of_chunks as f32)
-    );
+    // lets amount_out_across_ticks size swaps past the current tick
+    let tick_array_spacings = graph.tick_array_addresses();
+    let tick_array_addresses: Vec<Pubkey> = tick_array_spacings.keys().copied().collect();
+    // an array is only created once one of its ticks is initialized, missing ones are left out
+    let accounts = refresh::refresh_accounts(
+        &client,
+        tick_array_addresses.into(),
+        config.account_chunk_size,
+        &timeout,
+    )
+    .await;
+    let mut tick_arrays = Vec::new();
+    for (address, account, _) in accounts {
+        match decoders::decode_orca_tick_array(&account, tick_array_spacings[&address]) {
+            Ok(tick_array) => tick_arrays.push(tick_array),
+            Err(e) => warn!("Failed to decode tick array {}: {}", address, e),
+        }
+    }
+    info!("Read {} tick arrays", tick_arrays.len());
+    graph.update_tick_arrays(&tick_arrays);
 
-    // let _ = graph.find_arbitrage_cycles()?;
+    let opportunities = graph.find_arbitrage_cycles(args.min_log_profit);
+    info!("Found {} arbitrage opportunities", opportunities.len());
+    metrics::ARBITRAGE_OPPORTUNITIES.set(opportunities.len() as i64);
+    if let Some(best) = opportunities.first() {
+        let mut cycle = best.cycle.clone();
+        if !best.direction {
+            cycle.reverse();
+        }
+        info!(
+            "Best opportunity: {} (log profit {:.6})",
+            graph.describe_cycle(&cycle),
+            best.log_profit
+        );
+    }
+    sink.write(&OpportunityRecord::from_opportunities(
+        &graph,
+        &opportunities,
+        args.priority_fee_lamports,
+    ))?;
+
+    // the batch fetch above is the initial snapshot, websocket notifications keep it fresh
+    let handler = subscribe::UpdateHandler {
+        cluster: config.cluster,
+        graph: Arc::new(Mutex::new(graph)),
+        sink: Arc::new(Mutex::new(sink)),
+        min_log_profit: args.min_log_profit,
+    };
+    subscribe::subscribe_accounts(
+        &config.ws_url,
+        &addresses,
+        subscribe::DEFAULT_SUBSCRIPTIONS_PER_CONNECTION,
+        Arc::new(handler),
+        shutdown_on_ctrl_c(),
+    )
+    .await?;
 
     Ok(())
 }
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // closing a span logs its fields and how long it took
+    tracing_subscriber::fmt()
+        .with_span_events(FmtSpan::CLOSE)
+        .init();
+    let cli = Cli::parse();
+
+    if let Some(port) = cli.metrics_port {
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(port).await {
+                warn!("Metrics endpoint stopped: {:?}", e);
+            }
+        });
+    }
+
+    let config = cli.config();
+    config.validate()?;
+
+    match &cli.command {
+        Command::Setup {
+            max_pages,
+            merge,
+            source,
+            token_allow_list,
+        } => {
+            let limits = cli::bootstrap_limits(*max_pages, *source, token_allow_list);
+            setup(&config, &limits, *merge).await
+        }
+        Command::Stream { shred_endpoint } => {
+            // the snapshot scan saves, so stream works without the pool files and skips the
+            // JSON parse. without one every entry is still counted, just none matched to a pool
+            let pools = match graph::Graph::load(&config.graph_snapshot_path()) {
+                Ok(graph) => graph.pool_addresses(),
+                Err(e) => {
+                    warn!(
+                        "No usable graph snapshot, run scan once to match pools: {:?}",
+                        e
+                    );
+                    HashSet::new()
+                }
+            };
+            deshred::stream_entries(
+                shred_endpoint,
+                deshred::DEFAULT_MAX_BACKOFF,
+                shutdown_on_ctrl_c(),
+                |slot, entries| {
+                    let pool_transactions = deshred::pool_transactions(entries, &pools).count();
+                    metrics::POOL_TRANSACTIONS_MATCHED.inc_by(pool_transactions as u64);
+                    debug!(
+                        slot,
+                        entries = entries.len(),
+                        pool_transactions,
+                        "Decoded entries"
+                    );
+                },
+            )
+            .await
+        }
+        Command::Validate => validate(&config),
+        Command::Scan(args) => scan(&config, args).await,
+    }
+}
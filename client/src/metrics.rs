@@ -0,0 +1,140 @@
+use anyhow::{Context, Result};
+use prometheus::{
+    Encoder, IntCounter, IntGauge, IntGaugeVec, TextEncoder, register_int_counter,
+    register_int_gauge, register_int_gauge_vec,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{info, warn};
+
+lazy_static::lazy_static! {
+    pub static ref SHRED_ENTRIES: IntCounter = register_int_counter!(
+        "shred_entries_total",
+        "Entries deserialized from the shredstream"
+    )
+    .unwrap();
+    pub static ref SHRED_DESERIALIZE_FAILURES: IntCounter = register_int_counter!(
+        "shred_deserialize_failures_total",
+        "Shredstream slot payloads that failed to deserialize into entries"
+    )
+    .unwrap();
+    pub static ref TRANSACTIONS_SEEN: IntCounter = register_int_counter!(
+        "transactions_seen_total",
+        "Transactions contained in shredstream entries"
+    )
+    .unwrap();
+    pub static ref POOL_TRANSACTIONS_MATCHED: IntCounter = register_int_counter!(
+        "pool_transactions_matched_total",
+        "Shredstream transactions naming one of the graph's pools"
+    )
+    .unwrap();
+    pub static ref POOL_UPDATES: IntCounter = register_int_counter!(
+        "pool_updates_total",
+        "Pool accounts decoded and applied to the graph"
+    )
+    .unwrap();
+    pub static ref DECODE_FAILURES: IntCounter = register_int_counter!(
+        "decode_failures_total",
+        "Accounts owned by a known DEX that failed to decode"
+    )
+    .unwrap();
+    pub static ref CYCLES: IntGauge = register_int_gauge!(
+        "graph_cycles",
+        "Unique cycles found by the last build_cycles"
+    )
+    .unwrap();
+    pub static ref MISORIENTED_CYCLES: IntCounter = register_int_counter!(
+        "misoriented_cycles_total",
+        "Cycles that had to be rotated to start at the cycle start token"
+    )
+    .unwrap();
+    pub static ref ARBITRAGE_OPPORTUNITIES: IntGauge = register_int_gauge!(
+        "arbitrage_opportunities",
+        "Profitable cycles found by the last arbitrage scan"
+    )
+    .unwrap();
+    pub static ref BOOTSTRAP_POOLS: IntGaugeVec = register_int_gauge_vec!(
+        "bootstrap_pools",
+        "Pools written to each pool file by the last setup",
+        &["file"]
+    )
+    .unwrap();
+}
+
+pub fn render() -> Result<String> {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .context("Failed to encode metrics")?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+pub async fn serve(port: u16) -> Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .await
+        .with_context(|| format!("Failed to bind the metrics endpoint to port {}", port))?;
+    info!("Serving metrics on {}/metrics", listener.local_addr()?);
+    serve_listener(listener).await
+}
+
+async fn serve_listener(listener: TcpListener) -> Result<()> {
+    loop {
+        let (mut socket, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            if let Err(e) = respond(&mut socket).await {
+                warn!("Failed to answer a metrics request: {:?}", e);
+            }
+        });
+    }
+}
+
+// scrapes are a single small GET, so only the request line is looked at
+async fn respond(socket: &mut TcpStream) -> Result<()> {
+    let mut buffer = [0u8; 1024];
+    let read = socket.read(&mut buffer).await?;
+    let request = String::from_utf8_lossy(&buffer[..read]);
+
+    let (status, body) = if request.starts_with("GET /metrics ") {
+        ("200 OK", render()?)
+    } else {
+        ("404 Not Found", String::new())
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\ncontent-type: text/plain; version=0.0.4\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn get(address: std::net::SocketAddr, path: &str) -> reqwest::Response {
+        reqwest::get(format!("http://{}{}", address, path))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_serves_registered_metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve_listener(listener));
+
+        ARBITRAGE_OPPORTUNITIES.set(3);
+        BOOTSTRAP_POOLS.with_label_values(&["orca_pools"]).set(50);
+
+        let body = get(address, "/metrics").await.text().await.unwrap();
+
+        assert!(body.contains("arbitrage_opportunities 3"));
+        assert!(body.contains("bootstrap_pools{file=\"orca_pools\"} 50"));
+        assert_eq!(get(address, "/").await.status(), 404);
+    }
+}
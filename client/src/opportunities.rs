@@ -0,0 +1,343 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::graph::{ArbitrageOpportunity, Graph};
+
+const CSV_HEADER: &str = "cycle_id,direction,pools,symbols,log_profit,estimated_lamports";
+
+// where scan writes the opportunities it finds, stdout keeps them in the terminal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Stdout,
+    // one JSON object per line, appended so every scan adds to the same file
+    Json,
+    // appended the same way, the header is only written to a new or empty file
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn default_path(self, data_dir: &str) -> Option<String> {
+        match self {
+            OutputFormat::Stdout => None,
+            OutputFormat::Json => Some(format!("{}/opportunities.jsonl", data_dir)),
+            OutputFormat::Csv => Some(format!("{}/opportunities.csv", data_dir)),
+        }
+    }
+}
+
+// an opportunity with everything needed to read it without the graph. pools and symbols are in
+// trading order, cycle_id is the stored cycle so both directions of a cycle share it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OpportunityRecord {
+    pub cycle_id: String,
+    pub direction: bool,
+    pub pools: Vec<String>,
+    pub symbols: Vec<String>,
+    pub log_profit: f64,
+    // net profit at the optimal input in lamports, after the priority fee. None when it can't be priced within the
+    // cached ticks, the cycle starts at another base token or the record has no estimate
+    pub estimated_lamports: Option<i64>,
+}
+
+// the cycle's pools in the order the opportunity trades them
+fn walk(opportunity: &ArbitrageOpportunity) -> Vec<usize> {
+    match opportunity.direction {
+        true => opportunity.cycle.clone(),
+        false => opportunity.cycle.iter().rev().copied().collect(),
+    }
+}
+
+impl OpportunityRecord {
+    // cheap enough to build while holding the graph on every account update, the estimate is
+    // left out
+    pub fn new(graph: &Graph, opportunity: &ArbitrageOpportunity) -> Self {
+        let walk = walk(opportunity);
+
+        OpportunityRecord {
+            cycle_id: opportunity
+                .cycle
+                .iter()
+                .map(|index| index.to_string())
+                .collect::<Vec<_>>()
+                .join("-"),
+            direction: opportunity.direction,
+            pools: walk
+                .iter()
+                .map(|&index| graph.edges[index].address.to_string())
+                .collect(),
+            symbols: graph
+                .cycle_symbols(&walk)
+                .unwrap_or_default()
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            log_profit: opportunity.log_profit,
+            estimated_lamports: None,
+        }
+    }
+
+    // runs the optimal input search on top, which takes a few hundred swap simulations
+    pub fn with_estimate(
+        graph: &Graph,
+        opportunity: &ArbitrageOpportunity,
+        priority_fee_lamports: u64,
+    ) -> Self {
+        let walk = walk(opportunity);
+        OpportunityRecord {
+            estimated_lamports: graph.optimal_input_amount(&walk).and_then(|amount_in| {
+                graph.net_profit_lamports(&walk, true, amount_in, priority_fee_lamports)
+            }),
+            ..OpportunityRecord::new(graph, opportunity)
+        }
+    }
+
+    pub fn from_opportunities(
+        graph: &Graph,
+        opportunities: &[ArbitrageOpportunity],
+        priority_fee_lamports: u64,
+    ) -> Vec<Self> {
+        opportunities
+            .iter()
+            .map(|opportunity| {
+                OpportunityRecord::with_estimate(graph, opportunity, priority_fee_lamports)
+            })
+            .collect()
+    }
+}
+
+// the batch scan and the account subscriptions both report through one of these
+pub trait OpportunitySink: Send {
+    fn write(&mut self, opportunities: &[OpportunityRecord]) -> Result<()>;
+}
+
+pub struct StdoutSink;
+
+impl OpportunitySink for StdoutSink {
+    fn write(&mut self, opportunities: &[OpportunityRecord]) -> Result<()> {
+        let mut stdout = io::stdout().lock();
+        for opportunity in opportunities {
+            let estimate = match opportunity.estimated_lamports {
+                Some(lamports) => lamports.to_string(),
+                None => "unknown".to_string(),
+            };
+            writeln!(
+                stdout,
+                "{} (log profit {:.6}, estimated {} lamports)",
+                opportunity.symbols.join(" -> "),
+                opportunity.log_profit,
+                estimate
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub struct JsonSink<W: Write>(W);
+
+impl<W: Write> JsonSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonSink(writer)
+    }
+}
+
+impl JsonSink<BufWriter<File>> {
+    pub fn create(path: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path))?;
+        Ok(JsonSink::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write + Send> OpportunitySink for JsonSink<W> {
+    fn write(&mut self, opportunities: &[OpportunityRecord]) -> Result<()> {
+        for opportunity in opportunities {
+            serde_json::to_writer(&mut self.0, opportunity)?;
+            self.0.write_all(b"\n")?;
+        }
+        // flushed per batch so the file is readable while the scan keeps running
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+pub struct CsvSink<W: Write>(W);
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(mut writer: W) -> Result<Self> {
+        writeln!(writer, "{}", CSV_HEADER)?;
+        Ok(CsvSink(writer))
+    }
+}
+
+impl CsvSink<BufWriter<File>> {
+    pub fn create(path: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path))?;
+        match file.metadata()?.len() {
+            0 => CsvSink::new(BufWriter::new(file)),
+            _ => Ok(CsvSink(BufWriter::new(file))),
+        }
+    }
+}
+
+// symbols come from the pool APIs and can hold anything, quoted the way spreadsheets expect
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+impl<W: Write + Send> OpportunitySink for CsvSink<W> {
+    fn write(&mut self, opportunities: &[OpportunityRecord]) -> Result<()> {
+        for opportunity in opportunities {
+            writeln!(
+                self.0,
+                "{},{},{},{},{},{}",
+                opportunity.cycle_id,
+                opportunity.direction,
+                opportunity.pools.join(";"),
+                csv_field(&opportunity.symbols.join(" -> ")),
+                opportunity.log_profit,
+                opportunity
+                    .estimated_lamports
+                    .map(|lamports| lamports.to_string())
+                    .unwrap_or_default()
+            )?;
+        }
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+// output falls back to the format's file in the data dir
+pub fn sink_for(
+    format: OutputFormat,
+    output: Option<&str>,
+    data_dir: &str,
+) -> Result<Box<dyn OpportunitySink>> {
+    let path = output
+        .map(str::to_string)
+        .or_else(|| format.default_path(data_dir));
+    Ok(match (format, path) {
+        (OutputFormat::Json, Some(path)) => Box::new(JsonSink::create(&path)?),
+        (OutputFormat::Csv, Some(path)) => Box::new(CsvSink::create(&path)?),
+        _ => Box::new(StdoutSink),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> OpportunityRecord {
+        OpportunityRecord {
+            cycle_id: "0-4-2".to_string(),
+            direction: false,
+            pools: vec!["pool_a".to_string(), "pool_b".to_string()],
+            symbols: vec!["WSOL".to_string(), "A,B".to_string(), "WSOL".to_string()],
+            log_profit: 0.0025,
+            estimated_lamports: Some(1_200),
+        }
+    }
+
+    #[test]
+    fn test_csv_has_header_and_one_row_per_opportunity() {
+        let mut sink = CsvSink::new(Vec::new()).unwrap();
+
+        sink.write(&[record()]).unwrap();
+
+        let csv = String::from_utf8(sink.0).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "cycle_id,direction,pools,symbols,log_profit,estimated_lamports",
+                "0-4-2,false,pool_a;pool_b,\"WSOL -> A,B -> WSOL\",0.0025,1200",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_sink_writes_one_object_per_line() {
+        let mut sink = JsonSink::new(Vec::new());
+
+        sink.write(&[record(), record()]).unwrap();
+
+        let json = String::from_utf8(sink.0).unwrap();
+        let lines: Vec<serde_json::Value> = json
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["cycle_id"], "0-4-2");
+        assert_eq!(lines[0]["estimated_lamports"], 1_200);
+    }
+
+    #[test]
+    fn test_json_sink_appends_to_an_existing_file() {
+        let path = std::env::temp_dir().join(format!("opportunities-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        for _ in 0..2 {
+            JsonSink::create(path).unwrap().write(&[record()]).unwrap();
+        }
+
+        let json = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(json.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_csv_sink_appends_without_repeating_the_header() {
+        let path = std::env::temp_dir().join(format!("opportunities-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        for _ in 0..2 {
+            CsvSink::create(path).unwrap().write(&[record()]).unwrap();
+        }
+
+        let csv = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert_eq!(csv.matches(CSV_HEADER).count(), 1);
+        assert!(csv.starts_with(CSV_HEADER));
+    }
+
+    #[test]
+    fn test_record_follows_the_traded_direction() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        graph.build_cycles(3).unwrap();
+        let cycle = graph.all_cycles.values().flatten().next().unwrap();
+        let opportunity = ArbitrageOpportunity {
+            cycle: cycle.clone(),
+            direction: false,
+            log_profit: 0.01,
+            pools: Vec::new(),
+        };
+
+        let record = OpportunityRecord::with_estimate(&graph, &opportunity, 5_000);
+
+        let last = graph.edges[*cycle.last().unwrap()].address.to_string();
+        assert_eq!(record.pools[0], last);
+        assert_eq!(record.symbols.len(), cycle.len() + 1);
+        assert_eq!(record.symbols.first(), record.symbols.last());
+        // unpriced pools can't be sized
+        assert_eq!(record.estimated_lamports, None);
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use futures::future::{BoxFuture, join_all};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use tracing::{Span, field, info, instrument, warn};
+
+use crate::{
+    bootstrap::{
+        pool_schema::PoolUpdate,
+        retry::{TimeoutPolicy, with_timeout},
+    },
+    config::{self, Config},
+    decoders::{self, DecodeError, TokenAccount},
+    graph::Graph,
+    metrics,
+};
+
+// where refresh_graph reads accounts from, RpcClient in the binary and canned accounts in tests
+pub trait AccountFetcher: Send + Sync {
+    // one entry per address in the same order, and the slot they were read at
+    fn fetch_accounts<'a>(
+        &'a self,
+        addresses: &'a [Pubkey],
+    ) -> BoxFuture<'a, Result<(Vec<Option<Account>>, u64)>>;
+}
+
+impl AccountFetcher for RpcClient {
+    fn fetch_accounts<'a>(
+        &'a self,
+        addresses: &'a [Pubkey],
+    ) -> BoxFuture<'a, Result<(Vec<Option<Account>>, u64)>> {
+        Box::pin(async move {
+            let response = self
+                .get_multiple_accounts_with_commitment(addresses, self.commitment())
+                .await
+                .context("Failed to fetch accounts")?;
+            Ok((response.value, response.context.slot))
+        })
+    }
+}
+
+// answers from a fixed account map, counting the calls
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct CannedAccounts {
+    pub accounts: HashMap<Pubkey, Account>,
+    pub slot: u64,
+    pub calls: std::sync::atomic::AtomicUsize,
+    pub fail: bool,
+}
+
+#[cfg(test)]
+impl AccountFetcher for CannedAccounts {
+    fn fetch_accounts<'a>(
+        &'a self,
+        addresses: &'a [Pubkey],
+    ) -> BoxFuture<'a, Result<(Vec<Option<Account>>, u64)>> {
+        Box::pin(async move {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if self.fail {
+                anyhow::bail!("connection refused");
+            }
+            let accounts = addresses
+                .iter()
+                .map(|address| self.accounts.get(address).cloned())
+                .collect();
+            Ok((accounts, self.slot))
+        })
+    }
+}
+
+// reads the accounts in parallel chunks, each under the timeout policy. a chunk that keeps
+// failing is skipped and accounts that don't exist are left out
+#[instrument(
+    skip_all,
+    fields(accounts = addresses.len(), chunks = field::Empty, fetched = field::Empty)
+)]
+pub async fn refresh_accounts(
+    client: &Arc<dyn AccountFetcher>,
+    addresses: Arc<[Pubkey]>,
+    chunk_size: usize,
+    timeout: &TimeoutPolicy,
+) -> Vec<(Pubkey, Account, u64)> {
+    let chunks = config::account_chunks(addresses.len(), chunk_size);
+    Span::current().record("chunks", chunks.len());
+
+    let accounts_data: Vec<(Pubkey, Account, u64)> = join_all(chunks.into_iter().map(|range| {
+        let client = Arc::clone(client);
+        let addresses = Arc::clone(&addresses);
+        let timeout = timeout.clone();
+        tokio::spawn(async move {
+            let chunk = &addresses[range];
+            let (accounts, slot) = with_timeout(&timeout, || client.fetch_accounts(chunk)).await?;
+            // zip addresses with accounts, keep only Some(account)
+            Ok::<_, anyhow::Error>(
+                chunk
+                    .iter()
+                    .copied()
+                    .zip(accounts)
+                    .filter_map(|(address, account_opt)| {
+                        account_opt.map(|acc| (address, acc, slot))
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+    }))
+    .await
+    .into_iter()
+    .filter_map(|join_result| match join_result {
+        Ok(Ok(accounts)) => Some(accounts), // Vec<(Pubkey, Account, slot)>
+        Ok(Err(e)) => {
+            warn!("Skipping a chunk of accounts: {:?}", e);
+            None
+        }
+        Err(_) => {
+            warn!("A task panicked, skipping chunk");
+            None
+        }
+    })
+    .flatten()
+    .collect();
+
+    Span::current().record("fetched", accounts_data.len());
+    accounts_data
+}
+
+// reads the pools and their vaults in chunks, decodes them and applies the updates to the
+// graph. a failed chunk is skipped, so its pools keep their old state. the decoded vaults are
+// returned for the mint lookups that follow
+pub async fn refresh_graph(
+    graph: &mut Graph,
+    client: Arc<dyn AccountFetcher>,
+    addresses: &[Pubkey],
+    config: &Config,
+    timeout: &TimeoutPolicy,
+) -> HashMap<Pubkey, TokenAccount> {
+    // vault balances ride along in the same batch so empty pools can be filtered out
+    let vaults: HashSet<Pubkey> = graph.vault_addresses().into_iter().collect();
+    // one allocation shared by every task, each reads its own key range of it
+    let fetch_addresses: Arc<[Pubkey]> = addresses.iter().chain(&vaults).copied().collect();
+    let accounts_data =
+        refresh_accounts(&client, fetch_addresses, config.account_chunk_size, timeout).await;
+
+    let mut vault_accounts = HashMap::new();
+    let mut pool_updates = Vec::new();
+    for (address, account, slot) in accounts_data {
+        if vaults.contains(&address) {
+            match decoders::decode_token_account(&account) {
+                Ok(vault) => {
+                    vault_accounts.insert(address, vault);
+                }
+                Err(e) => warn!("Failed to decode vault {}: {}", address, e),
+            }
+            continue;
+        }
+
+        match decoders::decoders_for(config.cluster).decode(&account) {
+            Ok(data) => pool_updates.push((address, PoolUpdate { slot, ..data })),
+            Err(DecodeError::UnknownDex) => {}
+            Err(e) => {
+                warn!("Failed to decode account {}: {}", address, e);
+                metrics::DECODE_FAILURES.inc();
+            }
+        }
+    }
+
+    let decoded_pools = pool_updates.len();
+    let failures = graph.update_edges(pool_updates);
+    for (address, e) in &failures {
+        warn!("Failed to update edge {}: {:?}", address, e);
+    }
+    metrics::POOL_UPDATES.inc_by((decoded_pools - failures.len()) as u64);
+    let coverage = graph.coverage();
+    info!("{:.1}% of pools priced", coverage * 100.0);
+    if coverage < 0.5 {
+        warn!(
+            "Most pools have no price yet, {} are stale",
+            graph.stale_edges().len()
+        );
+    }
+
+    info!("Read {} vault accounts", vault_accounts.len());
+    graph.update_vaults(&vault_accounts);
+    vault_accounts
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::atomic::Ordering};
+
+    use super::*;
+    use crate::decoders::{ORCA_OWNER, TokenProgram, WHIRLPOOL_DISCRIMINATOR, test_account};
+
+    fn whirlpool(liquidity: u128, sqrt_price: u128, tick_index: i32) -> Account {
+        let mut data = vec![0u8; 653];
+        data[0..8].copy_from_slice(&WHIRLPOOL_DISCRIMINATOR);
+        data[41..43].copy_from_slice(&64u16.to_le_bytes());
+        data[45..47].copy_from_slice(&400u16.to_le_bytes());
+        data[49..65].copy_from_slice(&liquidity.to_le_bytes());
+        data[65..81].copy_from_slice(&sqrt_price.to_le_bytes());
+        data[81..85].copy_from_slice(&tick_index.to_le_bytes());
+        test_account(Pubkey::from_str(ORCA_OWNER).unwrap(), data)
+    }
+
+    fn token_account(amount: u64) -> Account {
+        let mut data = vec![0u8; 165];
+        data[64..72].copy_from_slice(&amount.to_le_bytes());
+        test_account(TokenProgram::Token.id(), data)
+    }
+
+    #[tokio::test]
+    async fn test_refresh_applies_canned_pool_and_vault_accounts() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        let pool = graph.edges[0].address;
+        let vault = graph.vault_addresses()[0];
+        let client = Arc::new(CannedAccounts {
+            accounts: HashMap::from([
+                (pool, whirlpool(7_000, 1u128 << 64, 0)),
+                (vault, token_account(42)),
+            ]),
+            slot: 123,
+            ..CannedAccounts::default()
+        });
+        let addresses: Vec<Pubkey> = graph.edges.iter().map(|edge| edge.address).collect();
+        let config = Config {
+            account_chunk_size: 10,
+            ..Config::default()
+        };
+
+        let vaults = refresh_graph(
+            &mut graph,
+            client.clone(),
+            &addresses,
+            &config,
+            &TimeoutPolicy::default(),
+        )
+        .await;
+
+        let edge = graph
+            .edges
+            .iter()
+            .find(|edge| edge.address == pool)
+            .unwrap();
+        assert_eq!(edge.sqrt_price, Some(1u128 << 64));
+        assert_eq!(graph.stats().priced_edges, 1);
+        assert_eq!(vaults[&vault].amount, 42);
+        assert_eq!(vaults.len(), 1);
+        let fetched = addresses.len() + graph.vault_addresses().len();
+        assert_eq!(
+            client.calls.load(Ordering::SeqCst),
+            fetched.div_ceil(config.account_chunk_size)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_chunks_leave_the_graph_unchanged() {
+        let mut graph = Graph::build_graph("./tests/test_data").unwrap();
+        let addresses: Vec<Pubkey> = graph.edges.iter().map(|edge| edge.address).collect();
+        let client = Arc::new(CannedAccounts {
+            fail: true,
+            ..CannedAccounts::default()
+        });
+
+        let vaults = refresh_graph(
+            &mut graph,
+            client,
+            &addresses,
+            &Config::default(),
+            &TimeoutPolicy::default(),
+        )
+        .await;
+
+        assert!(vaults.is_empty());
+        assert_eq!(graph.stats().priced_edges, 0);
+    }
+}
@@ -0,0 +1,277 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use futures::{
+    StreamExt,
+    stream::{FuturesUnordered, select_all},
+};
+use solana_account_decoder_client_types::{UiAccount, UiAccountEncoding};
+use solana_client::{
+    nonblocking::pubsub_client::PubsubClient, rpc_config::RpcAccountInfoConfig,
+    rpc_response::Response,
+};
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::{
+    bootstrap::pool_schema::PoolUpdate,
+    config::Cluster,
+    decoders::{self, DecodeError},
+    graph::Graph,
+    metrics,
+    opportunities::{OpportunityRecord, OpportunitySink},
+};
+
+// public RPC nodes cap how many subscriptions a single websocket connection may hold
+pub const DEFAULT_SUBSCRIPTIONS_PER_CONNECTION: usize = 100;
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+// connects or subscriptions in a row that may fail before a shard gives up
+const MAX_FAILED_RECONNECTS: usize = 10;
+
+// what each account notification updates and where the opportunities it opens go
+pub struct UpdateHandler {
+    pub cluster: Cluster,
+    pub graph: Arc<Mutex<Graph>>,
+    pub sink: Arc<Mutex<Box<dyn OpportunitySink>>>,
+    pub min_log_profit: f64,
+}
+
+// runs until shutdown is cancelled, resubscribing every shard whose connection drops. fails
+// with the first shard that can't be restored, the other shards are stopped then
+pub async fn subscribe_accounts(
+    ws_url: &str,
+    addresses: &[Pubkey],
+    subscriptions_per_connection: usize,
+    handler: Arc<UpdateHandler>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let mut shards = addresses
+        .chunks(subscriptions_per_connection)
+        .map(|shard| {
+            let shard = shard.to_vec();
+            let ws_url = ws_url.to_string();
+            let handler = Arc::clone(&handler);
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                keep_subscribed(
+                    || subscribe_shard(&ws_url, &shard, &handler, &shutdown),
+                    DEFAULT_MAX_BACKOFF,
+                    &shutdown,
+                )
+                .await
+            })
+        })
+        .collect::<FuturesUnordered<_>>();
+    info!(
+        "Subscribed to pool accounts over {} connections",
+        shards.len()
+    );
+
+    while let Some(join_result) = shards.next().await {
+        let result = join_result
+            .context("A subscription task panicked")
+            .and_then(|result| result);
+        if let Err(e) = result {
+            shutdown.cancel();
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+// reruns session whenever it ends, backing off while it keeps failing. a session that ends
+// without an error had connected, so only errors in a row count towards giving up
+async fn keep_subscribed<F, Fut>(
+    mut session: F,
+    max_backoff: Duration,
+    shutdown: &CancellationToken,
+) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    let mut failures = 0;
+
+    while !shutdown.is_cancelled() {
+        match session().await {
+            Ok(()) => {
+                failures = 0;
+                backoff = INITIAL_BACKOFF;
+                if !shutdown.is_cancelled() {
+                    warn!("Account subscription ended");
+                }
+            }
+            Err(e) => {
+                failures += 1;
+                if failures >= MAX_FAILED_RECONNECTS {
+                    return Err(e.context(format!(
+                        "Account subscription failed {} times in a row",
+                        failures
+                    )));
+                }
+                warn!("Account subscription failed: {:?}", e);
+            }
+        }
+        if shutdown.is_cancelled() {
+            break;
+        }
+
+        info!("Resubscribing to pool accounts in {:?}", backoff);
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = shutdown.cancelled() => break,
+        }
+        backoff = (backoff * 2).min(max_backoff);
+    }
+
+    Ok(())
+}
+
+// one connection, Ok once the server ends the streams or shutdown is cancelled
+async fn subscribe_shard(
+    ws_url: &str,
+    addresses: &[Pubkey],
+    handler: &UpdateHandler,
+    shutdown: &CancellationToken,
+) -> Result<()> {
+    let client = tokio::select! {
+        client = PubsubClient::new(ws_url) => {
+            client.context("Failed to connect to the websocket endpoint")?
+        }
+        _ = shutdown.cancelled() => return Ok(()),
+    };
+    let config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(CommitmentConfig::confirmed()),
+        ..RpcAccountInfoConfig::default()
+    };
+
+    let mut streams = Vec::with_capacity(addresses.len());
+    for &address in addresses {
+        let (stream, _unsubscribe) = client
+            .account_subscribe(&address, Some(config.clone()))
+            .await
+            .with_context(|| format!("Failed to subscribe to account {}", address))?;
+        streams.push(stream.map(move |response| (address, response)));
+    }
+
+    let mut notifications = select_all(streams);
+    loop {
+        let notification = tokio::select! {
+            notification = notifications.next() => notification,
+            _ = shutdown.cancelled() => return Ok(()),
+        };
+        let Some((address, response)) = notification else {
+            return Ok(());
+        };
+        handler.handle(address, response).await;
+    }
+}
+
+impl UpdateHandler {
+    async fn handle(&self, address: Pubkey, response: Response<UiAccount>) {
+        let Some(account) = response.value.decode::<Account>() else {
+            warn!("Failed to decode account data for {}", address);
+            return;
+        };
+
+        match decoders::decoders_for(self.cluster).decode(&account) {
+            Ok(data) => {
+                let mut graph = self.graph.lock().await;
+                let update = PoolUpdate {
+                    slot: response.context.slot,
+                    ..data
+                };
+                match graph.update_edge(&address, update) {
+                    Ok(()) => metrics::POOL_UPDATES.inc(),
+                    Err(e) => {
+                        warn!("Failed to update edge {}: {:?}", address, e);
+                        return;
+                    }
+                }
+
+                // only the cycles through this pool changed price
+                let opportunities =
+                    graph.find_arbitrage_cycles_through(&address, self.min_log_profit);
+                if opportunities.is_empty() {
+                    return;
+                }
+                // the estimate's input search would hold up every other shard on the lock
+                let records: Vec<OpportunityRecord> = opportunities
+                    .iter()
+                    .map(|opportunity| OpportunityRecord::new(&graph, opportunity))
+                    .collect();
+                drop(graph);
+                if let Err(e) = self.sink.lock().await.write(&records) {
+                    warn!("Failed to write opportunities: {:?}", e);
+                }
+            }
+            Err(DecodeError::UnknownDex) => {}
+            Err(e) => {
+                warn!("Failed to decode account {}: {}", address, e);
+                metrics::DECODE_FAILURES.inc();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use anyhow::bail;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shard_resubscribes_after_its_stream_ends() {
+        let shutdown = CancellationToken::new();
+        let sessions = AtomicUsize::new(0);
+
+        keep_subscribed(
+            || async {
+                // the first connection ends at once, the second one is cancelled
+                if sessions.fetch_add(1, Ordering::SeqCst) == 1 {
+                    shutdown.cancel();
+                }
+                Ok(())
+            },
+            DEFAULT_MAX_BACKOFF,
+            &shutdown,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(sessions.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_shard_gives_up_after_failed_reconnects() {
+        let shutdown = CancellationToken::new();
+        let sessions = AtomicUsize::new(0);
+
+        let result = keep_subscribed(
+            || async {
+                sessions.fetch_add(1, Ordering::SeqCst);
+                bail!("connection refused")
+            },
+            Duration::from_millis(1),
+            &shutdown,
+        )
+        .await;
+
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("10 times in a row")
+        );
+        assert_eq!(sessions.load(Ordering::SeqCst), MAX_FAILED_RECONNECTS);
+    }
+}
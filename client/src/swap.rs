@@ -0,0 +1,206 @@
+use std::str::FromStr;
+
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+use crate::decoders::{ORCA_OWNER, TICK_ARRAY_SIZE, TokenProgram};
+
+// sha256("global:swap_v2")[..8]
+const SWAP_V2_DISCRIMINATOR: [u8; 8] = [43, 4, 237, 11, 26, 201, 30, 98];
+// the swap price limits Whirlpool accepts, used when the caller doesn't want a limit
+pub const MIN_SQRT_PRICE: u128 = 4_295_048_016;
+pub const MAX_SQRT_PRICE: u128 = 79_226_673_515_401_279_992_447_579_055;
+
+lazy_static::lazy_static! {
+    static ref ORCA_PROGRAM_ID: Pubkey = Pubkey::from_str(ORCA_OWNER).unwrap();
+    static ref MEMO_PROGRAM_ID: Pubkey =
+        Pubkey::from_str("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr").unwrap();
+    static ref ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+        Pubkey::from_str("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL").unwrap();
+}
+
+// one exact-input swap through a whirlpool, token a and b in the pool's own order
+#[derive(Debug, Clone)]
+pub struct OrcaSwap {
+    pub whirlpool: Pubkey,
+    pub tick_spacing: u16,
+    pub current_tick_index: i32,
+    pub token_mint_a: Pubkey,
+    pub token_mint_b: Pubkey,
+    pub token_vault_a: Pubkey,
+    pub token_vault_b: Pubkey,
+    pub token_program_a: TokenProgram,
+    pub token_program_b: TokenProgram,
+    pub amount: u64,
+    // the least output the swap may return before it fails
+    pub other_amount_threshold: u64,
+    pub a_to_b: bool,
+}
+
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey, program: TokenProgram) -> Pubkey {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), program.id().as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_PROGRAM_ID,
+    )
+    .0
+}
+
+fn tick_array_start_index(tick_index: i32, tick_spacing: u16, offset: i32) -> i32 {
+    let ticks_per_array = tick_spacing as i32 * TICK_ARRAY_SIZE;
+    (tick_index.div_euclid(ticks_per_array) + offset) * ticks_per_array
+}
+
+// the three arrays the swap walks through, in trading order. b to a starts one tick spacing
+// ahead, the same shift the Orca SDK applies so a price sitting on an array edge still works
+pub fn tick_array_addresses(
+    whirlpool: &Pubkey,
+    tick_spacing: u16,
+    current_tick_index: i32,
+    a_to_b: bool,
+) -> [Pubkey; 3] {
+    let (start_tick, step) = match a_to_b {
+        true => (current_tick_index, -1),
+        false => (current_tick_index + tick_spacing as i32, 1),
+    };
+
+    [0, 1, 2].map(|offset| {
+        let start_index = tick_array_start_index(start_tick, tick_spacing, offset * step);
+        Pubkey::find_program_address(
+            &[
+                b"tick_array",
+                whirlpool.as_ref(),
+                start_index.to_string().as_bytes(),
+            ],
+            &ORCA_PROGRAM_ID,
+        )
+        .0
+    })
+}
+
+fn oracle_address(whirlpool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"oracle", whirlpool.as_ref()], &ORCA_PROGRAM_ID).0
+}
+
+// swap_v2 rather than swap, so Token-2022 pools go through the same instruction
+pub fn orca_swap_v2(swap: &OrcaSwap, authority: &Pubkey) -> Instruction {
+    let tick_arrays = tick_array_addresses(
+        &swap.whirlpool,
+        swap.tick_spacing,
+        swap.current_tick_index,
+        swap.a_to_b,
+    );
+    let sqrt_price_limit = match swap.a_to_b {
+        true => MIN_SQRT_PRICE,
+        false => MAX_SQRT_PRICE,
+    };
+
+    let mut data = SWAP_V2_DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&swap.amount.to_le_bytes());
+    data.extend_from_slice(&swap.other_amount_threshold.to_le_bytes());
+    data.extend_from_slice(&sqrt_price_limit.to_le_bytes());
+    // amount_specified_is_input, a_to_b, then no remaining accounts info
+    data.extend_from_slice(&[1, swap.a_to_b as u8, 0]);
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(swap.token_program_a.id(), false),
+        AccountMeta::new_readonly(swap.token_program_b.id(), false),
+        AccountMeta::new_readonly(*MEMO_PROGRAM_ID, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(swap.whirlpool, false),
+        AccountMeta::new_readonly(swap.token_mint_a, false),
+        AccountMeta::new_readonly(swap.token_mint_b, false),
+        AccountMeta::new(
+            associated_token_address(authority, &swap.token_mint_a, swap.token_program_a),
+            false,
+        ),
+        AccountMeta::new(swap.token_vault_a, false),
+        AccountMeta::new(
+            associated_token_address(authority, &swap.token_mint_b, swap.token_program_b),
+            false,
+        ),
+        AccountMeta::new(swap.token_vault_b, false),
+    ];
+    accounts.extend(
+        tick_arrays
+            .into_iter()
+            .map(|tick_array| AccountMeta::new(tick_array, false)),
+    );
+    accounts.push(AccountMeta::new(oracle_address(&swap.whirlpool), false));
+
+    Instruction {
+        program_id: *ORCA_PROGRAM_ID,
+        accounts,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tick_array_start_index_rounds_down() {
+        assert_eq!(tick_array_start_index(0, 64, 0), 0);
+        assert_eq!(tick_array_start_index(5_631, 64, 0), 0);
+        assert_eq!(tick_array_start_index(5_632, 64, 0), 5_632);
+        assert_eq!(tick_array_start_index(-1, 64, 0), -5_632);
+        assert_eq!(tick_array_start_index(-1, 64, -1), -11_264);
+        assert_eq!(tick_array_start_index(-1, 64, 2), 5_632);
+    }
+
+    #[test]
+    fn test_tick_arrays_follow_trade_direction() {
+        let whirlpool = Pubkey::new_unique();
+        let address = |start_index: i32| {
+            Pubkey::find_program_address(
+                &[
+                    b"tick_array",
+                    whirlpool.as_ref(),
+                    start_index.to_string().as_bytes(),
+                ],
+                &ORCA_PROGRAM_ID,
+            )
+            .0
+        };
+
+        assert_eq!(
+            tick_array_addresses(&whirlpool, 64, 100, true),
+            [address(0), address(-5_632), address(-11_264)]
+        );
+        // one spacing below the next array, so b to a already starts in it
+        assert_eq!(
+            tick_array_addresses(&whirlpool, 64, 5_600, false),
+            [address(5_632), address(11_264), address(16_896)]
+        );
+    }
+
+    #[test]
+    fn test_swap_v2_data_layout() {
+        let swap = OrcaSwap {
+            whirlpool: Pubkey::new_unique(),
+            tick_spacing: 64,
+            current_tick_index: 0,
+            token_mint_a: Pubkey::new_unique(),
+            token_mint_b: Pubkey::new_unique(),
+            token_vault_a: Pubkey::new_unique(),
+            token_vault_b: Pubkey::new_unique(),
+            token_program_a: TokenProgram::Token,
+            token_program_b: TokenProgram::Token2022,
+            amount: 1_000,
+            other_amount_threshold: 990,
+            a_to_b: false,
+        };
+
+        let instruction = orca_swap_v2(&swap, &Pubkey::new_unique());
+
+        assert_eq!(instruction.data.len(), 8 + 8 + 8 + 16 + 3);
+        assert_eq!(instruction.data[0..8], SWAP_V2_DISCRIMINATOR);
+        assert_eq!(instruction.data[8..16], 1_000u64.to_le_bytes());
+        assert_eq!(instruction.data[16..24], 990u64.to_le_bytes());
+        assert_eq!(instruction.data[24..40], MAX_SQRT_PRICE.to_le_bytes());
+        assert_eq!(instruction.data[40..], [1, 0, 0]);
+        assert_eq!(instruction.accounts[1].pubkey, TokenProgram::Token2022.id());
+    }
+}
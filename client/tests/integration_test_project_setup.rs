@@ -15,9 +15,10 @@ async fn test_graph_and_cycles_setup() {
     assert_eq!(graph.all_cycles.len(), 61);
 
     let mut invalid_cycle_counter: usize = 0;
-    for cycles in graph.all_cycles.clone() {
-        for mut cycle in cycles.1 {
+    for (key, cycles) in graph.all_cycles.clone() {
+        for mut cycle in cycles {
             assert!(cycle.len() <= test_depth);
+            assert!(key.ends_with(&format!("-{}", cycle.len())));
             if graph.check_cycle(cycle.as_mut()) {
                 invalid_cycle_counter += 1;
             }